@@ -1,36 +1,179 @@
-use std::{panic, thread};
+use std::{
+  env, panic,
+  path::PathBuf,
+  process,
+  sync::{Arc, Mutex},
+  thread,
+};
 
 use async_std::{channel, task};
 use rbtc::{
+  events::{thread::start_dispatching, validation::ValidationEvents},
+  index::thread::start_indexing,
+  mempool::pool::Mempool,
   mining::thread::start_mining,
   networking::thread::start_networking,
-  util::types::{block::Block, txn::Txn},
+  util::{
+    config::Config,
+    params::ChainParams,
+    shutdown::Shutdown,
+    time::{SystemClock, TimeSource},
+    types::{block::Block, chain::ActiveChain},
+  },
 };
 
 /// TODO: Rewrite or heavily scrutinize all files marked with "REWRITE".
 fn main() {
+  // Select the network to mine and validate blocks on, the directory to store
+  // its chain in (first argument, if given), the prune target in MiB
+  // (`--prune=<MiB>`, if given), the address to listen on (`--bind=<IPv4>`,
+  // if given), the mempool's size in MiB and expiry in hours
+  // (`--maxmempool=<MiB>`, `--mempoolexpiry=<hours>`, if given), the optional
+  // indexes to keep (`--txindex`, `--addrindex`, `--blockfilterindex`) and
+  // whether to replace mempool transactions that do not signal replacement
+  // (`--mempoolfullrbf`).
+  let params = ChainParams::mainnet();
+  let mut config = Config::default();
+  for arg in env::args().skip(1) {
+    if let Some(mib) = arg.strip_prefix("--prune=") {
+      match mib.parse::<u64>() {
+        Ok(mib) => config.prune_target = Some(mib << 20),
+        Err(err) => {
+          println!("Invalid prune target {}: {}", mib, err);
+          process::exit(1);
+        },
+      }
+    } else if let Some(mib) = arg.strip_prefix("--maxmempool=") {
+      match mib.parse::<usize>() {
+        Ok(mib) => config.mempool_max_size = mib << 20,
+        Err(err) => {
+          println!("Invalid mempool size {}: {}", mib, err);
+          process::exit(1);
+        },
+      }
+    } else if let Some(hours) = arg.strip_prefix("--mempoolexpiry=") {
+      match hours.parse::<u32>() {
+        Ok(hours) => config.mempool_expiry = hours.saturating_mul(60 * 60),
+        Err(err) => {
+          println!("Invalid mempool expiry {}: {}", hours, err);
+          process::exit(1);
+        },
+      }
+    } else if let Some(addr) = arg.strip_prefix("--bind=") {
+      match addr.parse() {
+        Ok(addr) => config.listen_addr = addr,
+        Err(err) => {
+          println!("Invalid listen address {}: {}", addr, err);
+          process::exit(1);
+        },
+      }
+    } else if arg == "--txindex" {
+      config.txindex = true;
+    } else if arg == "--addrindex" {
+      config.addrindex = true;
+    } else if arg == "--blockfilterindex" {
+      config.blockfilterindex = true;
+    } else if arg == "--mempoolfullrbf" {
+      config.mempool_full_rbf = true;
+    } else {
+      config.data_dir = PathBuf::from(arg);
+    }
+  }
+
+  // Initialize the network-adjusted clock, shared between threads.
+  let time = Arc::new(Mutex::new(TimeSource::new(Arc::new(SystemClock))));
+
+  // Open the local chain, shared between threads.
+  let mut chain = match ActiveChain::open(params, &config) {
+    Ok(chain) => chain,
+    Err(err) => {
+      println!(
+        "Failed to open chain in {}: {}",
+        config.data_dir.display(),
+        err
+      );
+      process::exit(1);
+    },
+  };
+
   // Initialize inter-thread communication channels.
-  let (blks_to_miner, blks_from_network) = channel::unbounded::<Block>();
   let (blks_to_network, blks_from_miner) = channel::unbounded::<Block>();
-  let (txns_to_miner, txns_from_network) = channel::unbounded::<Txn>();
-  let (txns_to_network, txns_from_miner) = channel::unbounded::<Txn>();
-
-  // Spawn mining and networking threads.
-  let mining_thread = thread::spawn(|| {
-    task::block_on(start_mining(
-      blks_from_network,
-      txns_from_network,
-      blks_to_network,
-      txns_to_network,
-    ))
+  let (blk_requests_to_network, blk_requests) = channel::unbounded();
+
+  // Ask the network for the missing parents of orphan blocks.
+  chain.set_missing_parent_hook(Box::new(move |peer, hash| {
+    if let Err(err) = blk_requests_to_network.try_send((peer, hash)) {
+      println!("Failed to send block request to networking thread: {}", err);
+    }
+  }));
+
+  // Publish changes to the chain and mempool to subscribers from the event
+  // thread.
+  let (validation_events, event_queue) = ValidationEvents::new();
+  chain.set_validation_events(validation_events.clone());
+
+  // Initialize the mempool with the transactions and fee estimates dumped on
+  // last shutdown, shared between threads.
+  let mut mempool = Mempool::new(&config);
+  mempool.set_validation_events(validation_events.clone());
+  if let Err(err) = mempool.fee_estimator_mut().load(&config.data_dir) {
+    println!("Failed to load fee estimates: {}", err);
+  }
+  let now = time.lock().expect("Poisoned time lock").adjusted_time();
+  match mempool.load(&config.data_dir, &mut chain, now) {
+    Ok(loaded) => println!("Loaded {} mempool transactions", loaded),
+    Err(err) => println!("Failed to load mempool: {}", err),
+  }
+  let chain = Arc::new(Mutex::new(chain));
+  let mempool = Arc::new(Mutex::new(mempool));
+
+  // Ask the mining, networking and indexing threads to return on ctrl-c.
+  let shutdown = Shutdown::new();
+  if let Err(err) = shutdown.request_on_signal() {
+    println!("Failed to handle termination signals: {}", err);
+  }
+
+  // Spawn event, mining, networking and indexing threads.
+  let event_thread =
+    thread::spawn(|| task::block_on(start_dispatching(event_queue)));
+  let indexing_thread = thread::spawn({
+    let chain = chain.clone();
+    let shutdown = shutdown.clone();
+    || task::block_on(start_indexing(chain, shutdown))
+  });
+  let mining_thread = thread::spawn({
+    let chain = chain.clone();
+    let mempool = mempool.clone();
+    let time = time.clone();
+    let validation_events = validation_events.clone();
+    let shutdown = shutdown.clone();
+    || {
+      task::block_on(start_mining(
+        chain,
+        mempool,
+        time,
+        validation_events,
+        blks_to_network,
+        shutdown,
+      ))
+    }
   });
-  let networking_thread = thread::spawn(|| {
-    task::block_on(start_networking(
-      blks_from_miner,
-      txns_from_miner,
-      blks_to_miner,
-      txns_to_miner,
-    ))
+  let networking_thread = thread::spawn({
+    let chain = chain.clone();
+    let mempool = mempool.clone();
+    let listen_addr = config.listen_addr;
+    move || {
+      task::block_on(start_networking(
+        listen_addr,
+        chain,
+        mempool,
+        time,
+        validation_events,
+        blks_from_miner,
+        blk_requests,
+        shutdown,
+      ))
+    }
   });
 
   // TODO: Figure out what "panic::resume_unwind()" thing does.
@@ -39,7 +182,37 @@ fn main() {
     Err(err) => panic::resume_unwind(err),
   }
   match networking_thread.join() {
-    Ok(_) => println!("Exited networking thread"),
+    Ok(Ok(())) => println!("Exited networking thread"),
+    Ok(Err(err)) => println!("Networking thread failed: {}", err),
+    Err(err) => panic::resume_unwind(err),
+  }
+  match indexing_thread.join() {
+    Ok(_) => println!("Exited indexing thread"),
+    Err(err) => panic::resume_unwind(err),
+  }
+
+  // Dump the mempool and fee estimates to be loaded again on the next
+  // startup, and write out the chainstate's cache.
+  {
+    let mut chain = chain.lock().expect("Poisoned chain lock");
+    let mempool = mempool.lock().expect("Poisoned mempool lock");
+    match mempool.dump(&config.data_dir) {
+      Ok(()) => println!("Dumped {} mempool transactions", mempool.len()),
+      Err(err) => println!("Failed to dump mempool: {}", err),
+    }
+    if let Err(err) = mempool.fee_estimator().dump(&config.data_dir) {
+      println!("Failed to dump fee estimates: {}", err);
+    }
+    if let Err(err) = chain.flush() {
+      println!("Failed to flush chainstate: {}", err);
+    }
+  }
+
+  // The event queue closes once the chain and mempool, the last to publish
+  // to it, are dropped.
+  drop((chain, mempool));
+  match event_thread.join() {
+    Ok(_) => println!("Exited event thread"),
     Err(err) => panic::resume_unwind(err),
   }
 }
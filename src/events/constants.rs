@@ -0,0 +1,13 @@
+use std::time::Duration;
+
+/// The number of events each subscriber may have waiting before delivery to
+/// every subscriber pauses for it to catch up.
+pub const SUBSCRIBER_QUEUE_SIZE: usize = 100;
+
+/// The number of events that may wait to be delivered before producers of new
+/// events pause for delivery to catch up.
+pub const MAX_PENDING_EVENTS: usize = 10;
+
+/// How long a producer waits before checking again whether delivery has
+/// caught up.
+pub const EVENT_SYNC_INTERVAL: Duration = Duration::from_millis(10);
@@ -0,0 +1,9 @@
+use super::validation::EventQueue;
+
+/// # Event thread
+/// Delivers validation events, in the order they happened, to every
+/// subscriber, waiting on any whose queue is full.
+pub async fn start_dispatching(queue: EventQueue) {
+  queue.dispatch().await;
+  println!("Validation event queue closed");
+}
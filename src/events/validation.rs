@@ -0,0 +1,144 @@
+use std::sync::{
+  atomic::{AtomicUsize, Ordering},
+  Arc, Mutex, MutexGuard,
+};
+
+use async_std::{
+  channel::{self, Receiver, Sender},
+  task,
+};
+
+use super::constants::{EVENT_SYNC_INTERVAL, SUBSCRIBER_QUEUE_SIZE};
+use crate::{
+  mempool::pool::RemovalReason,
+  util::{
+    constants::SHA256_HASH_SIZE,
+    types::{block::Block, txn::Txn},
+  },
+};
+
+/// A change to the active chain or the mempool.
+#[derive(Clone, Debug)]
+pub enum ValidationEvent {
+  /// A block was connected to the active chain at the given height.
+  BlockConnected { block: Arc<Block>, height: u32 },
+
+  /// A block was disconnected from the tip of the active chain, which was at
+  /// the given height.
+  BlockDisconnected { block: Arc<Block>, height: u32 },
+
+  /// The active chain moved to a new tip, after the blocks disconnected and
+  /// connected along the way. Sent once per move, however many blocks it took.
+  TipUpdated {
+    hash: [u8; SHA256_HASH_SIZE],
+    height: u32,
+  },
+
+  /// A transaction entered the mempool.
+  TransactionAddedToMempool(Arc<Txn>),
+
+  /// A transaction left the mempool, for the given reason.
+  TransactionRemovedFromMempool(Arc<Txn>, RemovalReason),
+}
+
+/// State shared between the handles publishing events and the queue
+/// delivering them.
+struct Shared {
+  subscribers: Mutex<Vec<Sender<ValidationEvent>>>,
+  pending: AtomicUsize,
+}
+
+impl Shared {
+  /// Lock and return the subscribers' channels.
+  fn subscribers(&self) -> MutexGuard<'_, Vec<Sender<ValidationEvent>>> {
+    self.subscribers.lock().expect("Poisoned subscribers lock")
+  }
+}
+
+/// A handle through which validation events are published and subscribed to.
+///
+/// Events are published without blocking, e.g. while the chain's lock is held,
+/// onto a queue that the event thread drains in order into every subscriber's
+/// bounded channel. A subscriber that falls `SUBSCRIBER_QUEUE_SIZE` events
+/// behind holds up delivery to all of them, and producers that call `sync`
+/// before making further changes are in turn held up until delivery catches
+/// up. Subscribers must therefore keep receiving, and never wait on the
+/// chain's lock while their channel is full.
+#[derive(Clone)]
+pub struct ValidationEvents {
+  queue: Sender<ValidationEvent>,
+  shared: Arc<Shared>,
+}
+
+/// The receiving end of the queue of published events, drained by the event
+/// thread.
+pub struct EventQueue {
+  queue: Receiver<ValidationEvent>,
+  shared: Arc<Shared>,
+}
+
+impl ValidationEvents {
+  /// Initialize a handle with no subscribers, along with the queue its events
+  /// are delivered from.
+  pub fn new() -> (Self, EventQueue) {
+    let (sender, receiver) = channel::unbounded();
+    let shared = Arc::new(Shared {
+      subscribers: Mutex::new(Vec::new()),
+      pending: AtomicUsize::new(0),
+    });
+    let queue = EventQueue { queue: receiver, shared: shared.clone() };
+    (Self { queue: sender, shared }, queue)
+  }
+
+  /// Subscribe to every event published from now on. The subscription ends
+  /// when the returned receiver is dropped.
+  pub fn subscribe(&self) -> Receiver<ValidationEvent> {
+    let (sender, receiver) = channel::bounded(SUBSCRIBER_QUEUE_SIZE);
+    self.shared.subscribers().push(sender);
+    receiver
+  }
+
+  /// Publish an event, to be delivered after every event published before it.
+  pub fn publish(&self, event: ValidationEvent) {
+    self.shared.pending.fetch_add(1, Ordering::SeqCst);
+    if self.queue.try_send(event).is_err() {
+      self.shared.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+  }
+
+  /// Return the number of published events yet to be delivered to every
+  /// subscriber.
+  pub fn pending(&self) -> usize {
+    self.shared.pending.load(Ordering::SeqCst)
+  }
+
+  /// Wait until at most `max_pending` published events are yet to be
+  /// delivered to every subscriber.
+  pub async fn sync(&self, max_pending: usize) {
+    while self.pending() > max_pending {
+      task::sleep(EVENT_SYNC_INTERVAL).await;
+    }
+  }
+}
+
+impl EventQueue {
+  /// Deliver published events to every subscriber in order, dropping
+  /// subscribers whose receiver has been dropped, until every handle
+  /// publishing to the queue is dropped.
+  pub async fn dispatch(self) {
+    while let Ok(event) = self.queue.recv().await {
+      let subscribers = self.shared.subscribers().clone();
+      let mut any_closed = false;
+      for subscriber in subscribers {
+        any_closed |= subscriber.send(event.clone()).await.is_err();
+      }
+      if any_closed {
+        self
+          .shared
+          .subscribers()
+          .retain(|subscriber| !subscriber.is_closed());
+      }
+      self.shared.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+  }
+}
@@ -0,0 +1,315 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+  chain_index::ChainIndex,
+  constants::{ADDRINDEX_DIR, INDEXES_DIR},
+};
+use crate::{
+  storage::{
+    error::Error,
+    kv::{KvStore, WriteBatch},
+  },
+  util::{
+    constants::{RIPEMD160_HASH_SIZE, SHA256_HASH_SIZE},
+    types::{
+      block::Block,
+      coin::{BlockUndo, OutPoint},
+    },
+  },
+};
+
+/// Key prefix of outputs paying an address, followed by the address's pubkey
+/// hash and the output's outpoint.
+const KEY_FUNDING: u8 = b'o';
+
+/// Key prefix of spends of outputs paying an address, followed by the
+/// address's pubkey hash and the spent output's outpoint.
+const KEY_SPENDING: u8 = b's';
+
+/// Key of the hash of the last block indexed.
+const KEY_BEST_BLOCK: u8 = b'B';
+
+/// An output paying an address.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+struct FundingRecord {
+  /// The height of the block confirming the output.
+  height: u32,
+
+  /// The position in that block of the transaction creating the output.
+  txn_index: u32,
+
+  /// The value of the output.
+  value: u64,
+}
+
+/// A spend of an output paying an address.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+struct SpendingRecord {
+  /// The height of the block confirming the spend.
+  height: u32,
+
+  /// The position in that block of the spending transaction.
+  txn_index: u32,
+
+  /// The hash of the spending transaction.
+  txn_hash: [u8; SHA256_HASH_SIZE],
+
+  /// The value of the spent output.
+  value: u64,
+}
+
+/// A transaction that paid or spent from an address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddrTxn {
+  /// The hash of the transaction.
+  pub txn_hash: [u8; SHA256_HASH_SIZE],
+
+  /// The height of the block confirming the transaction.
+  pub height: u32,
+
+  /// The total value of the transaction's outputs paying the address.
+  pub received: u64,
+
+  /// The total value of the address's outputs the transaction spent.
+  pub sent: u64,
+}
+
+/// An unspent output paying an address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddrUtxo {
+  /// The output's outpoint.
+  pub outpoint: OutPoint,
+
+  /// The height of the block confirming the output.
+  pub height: u32,
+
+  /// The value of the output.
+  pub value: u64,
+}
+
+/// The address index: a key-value database under the data directory's indexes
+/// directory recording, for the pubkey hash of every address paid on the
+/// active chain, the outputs paying it and the spends of those outputs.
+pub struct AddrIndex {
+  db: KvStore,
+  best_block: Option<[u8; SHA256_HASH_SIZE]>,
+}
+
+impl AddrIndex {
+  /// Open the address index under the given data directory, creating it if
+  /// needed.
+  pub fn open(data_dir: &Path) -> Result<Self, Error> {
+    let db = KvStore::open(&data_dir.join(INDEXES_DIR).join(ADDRINDEX_DIR))?;
+    let best_block = match db.get(&[KEY_BEST_BLOCK])? {
+      Some(value) => Some(value.try_into().map_err(|_| {
+        Error::Corrupt("malformed block hash in address index".into())
+      })?),
+      None => None,
+    };
+    Ok(Self { db, best_block })
+  }
+
+  /// Return the transactions that paid or spent from the address with the
+  /// given pubkey hash, oldest first.
+  pub fn history(
+    &self,
+    pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  ) -> Result<Vec<AddrTxn>, Error> {
+    let mut txns = BTreeMap::new();
+    for (key, value) in
+      self.db.scan_prefix(&addr_key(KEY_FUNDING, pubkey_hash))?
+    {
+      let outpoint = key_outpoint(&key)?;
+      let record: FundingRecord = bincode::deserialize(&value)?;
+      txns
+        .entry((record.height, record.txn_index))
+        .or_insert_with(|| AddrTxn {
+          txn_hash: outpoint.txn_hash,
+          height: record.height,
+          received: 0,
+          sent: 0,
+        })
+        .received += record.value;
+    }
+    for (_, value) in
+      self.db.scan_prefix(&addr_key(KEY_SPENDING, pubkey_hash))?
+    {
+      let record: SpendingRecord = bincode::deserialize(&value)?;
+      txns
+        .entry((record.height, record.txn_index))
+        .or_insert_with(|| AddrTxn {
+          txn_hash: record.txn_hash,
+          height: record.height,
+          received: 0,
+          sent: 0,
+        })
+        .sent += record.value;
+    }
+    Ok(txns.into_values().collect())
+  }
+
+  /// Return the unspent outputs paying the address with the given pubkey
+  /// hash, oldest first.
+  pub fn utxos(
+    &self,
+    pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  ) -> Result<Vec<AddrUtxo>, Error> {
+    let mut utxos = Vec::new();
+    for (key, value) in
+      self.db.scan_prefix(&addr_key(KEY_FUNDING, pubkey_hash))?
+    {
+      let outpoint = key_outpoint(&key)?;
+      if self
+        .db
+        .contains(&outpoint_key(KEY_SPENDING, pubkey_hash, &outpoint))
+      {
+        continue;
+      }
+      let record: FundingRecord = bincode::deserialize(&value)?;
+      utxos.push((
+        (record.height, record.txn_index),
+        AddrUtxo { outpoint, height: record.height, value: record.value },
+      ));
+    }
+    utxos.sort_by_key(|(order, utxo)| (*order, utxo.outpoint.index));
+    Ok(utxos.into_iter().map(|(_, utxo)| utxo).collect())
+  }
+
+  /// Return the total value of the unspent outputs paying the address with
+  /// the given pubkey hash.
+  pub fn balance(
+    &self,
+    pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  ) -> Result<u64, Error> {
+    Ok(self.utxos(pubkey_hash)?.iter().map(|utxo| utxo.value).sum())
+  }
+}
+
+impl ChainIndex for AddrIndex {
+  fn name(&self) -> &'static str {
+    "addrindex"
+  }
+
+  fn best_block(&self) -> Option<[u8; SHA256_HASH_SIZE]> {
+    self.best_block
+  }
+
+  fn connect_block(
+    &mut self,
+    block: &Block,
+    height: u32,
+    undo: &BlockUndo,
+  ) -> Result<(), Error> {
+    let mut batch = WriteBatch::new();
+    let mut spent = undo.spent.iter();
+    for (txn_index, txn) in block.txns().iter().enumerate() {
+      let txn_index = txn_index as u32;
+      let txn_hash = txn.hash();
+      for txi in txn.txi_list() {
+        let coin = spent.next().ok_or_else(|| {
+          Error::Corrupt("undo data missing spent coins".into())
+        })?;
+        let record = SpendingRecord {
+          height,
+          txn_index,
+          txn_hash,
+          value: coin.txo.value(),
+        };
+        batch.put(
+          &outpoint_key(
+            KEY_SPENDING,
+            &coin.txo.pubkey_hash(),
+            &OutPoint::spent_by(txi),
+          ),
+          &bincode::serialize(&record)?,
+        );
+      }
+      for (index, txo) in txn.txo_list().iter().enumerate() {
+        let record = FundingRecord { height, txn_index, value: txo.value() };
+        batch.put(
+          &outpoint_key(
+            KEY_FUNDING,
+            &txo.pubkey_hash(),
+            &OutPoint::new(txn_hash, index),
+          ),
+          &bincode::serialize(&record)?,
+        );
+      }
+    }
+    batch.put(&[KEY_BEST_BLOCK], &block.hash());
+    self.db.write(batch, false)?;
+    self.best_block = Some(block.hash());
+    Ok(())
+  }
+
+  fn disconnect_block(
+    &mut self,
+    block: &Block,
+    _height: u32,
+    undo: &BlockUndo,
+  ) -> Result<(), Error> {
+    let mut batch = WriteBatch::new();
+    let mut spent = undo.spent.iter();
+    for txn in block.txns() {
+      let txn_hash = txn.hash();
+      for txi in txn.txi_list() {
+        let coin = spent.next().ok_or_else(|| {
+          Error::Corrupt("undo data missing spent coins".into())
+        })?;
+        batch.delete(&outpoint_key(
+          KEY_SPENDING,
+          &coin.txo.pubkey_hash(),
+          &OutPoint::spent_by(txi),
+        ));
+      }
+      for (index, txo) in txn.txo_list().iter().enumerate() {
+        batch.delete(&outpoint_key(
+          KEY_FUNDING,
+          &txo.pubkey_hash(),
+          &OutPoint::new(txn_hash, index),
+        ));
+      }
+    }
+    batch.put(&[KEY_BEST_BLOCK], &block.prev_block_hash());
+    self.db.write(batch, false)?;
+    self.best_block = Some(block.prev_block_hash());
+    Ok(())
+  }
+}
+
+/// Return the prefix of the keys of the given kind for the address with the
+/// given pubkey hash.
+fn addr_key(kind: u8, pubkey_hash: &[u8; RIPEMD160_HASH_SIZE]) -> Vec<u8> {
+  [&[kind][..], pubkey_hash].concat()
+}
+
+/// Return the key of the given kind for an outpoint paying the address with
+/// the given pubkey hash.
+fn outpoint_key(
+  kind: u8,
+  pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  outpoint: &OutPoint,
+) -> Vec<u8> {
+  [
+    &addr_key(kind, pubkey_hash)[..],
+    &outpoint.txn_hash,
+    &(outpoint.index as u32).to_be_bytes(),
+  ]
+  .concat()
+}
+
+/// Return the outpoint at the end of a key made by `outpoint_key`.
+fn key_outpoint(key: &[u8]) -> Result<OutPoint, Error> {
+  let malformed = || Error::Corrupt("malformed address index key".into());
+  let outpoint = key.get(1 + RIPEMD160_HASH_SIZE..).ok_or_else(malformed)?;
+  if outpoint.len() != SHA256_HASH_SIZE + 4 {
+    return Err(malformed());
+  }
+  let (txn_hash, index) = outpoint.split_at(SHA256_HASH_SIZE);
+  Ok(OutPoint::new(
+    txn_hash.try_into().map_err(|_| malformed())?,
+    u32::from_be_bytes(index.try_into().map_err(|_| malformed())?) as usize,
+  ))
+}
@@ -0,0 +1,42 @@
+use crate::{
+  storage::error::Error,
+  util::{
+    constants::SHA256_HASH_SIZE,
+    types::{block::Block, coin::BlockUndo},
+  },
+};
+
+/// An optional index of the active chain, e.g. from transaction hashes to the
+/// blocks confirming them.
+///
+/// An index records the last block it has indexed. One that is behind the
+/// active chain, e.g. because it was just enabled, is caught up in the
+/// background by `ActiveChain::sync_indexes`, which reads old blocks back from
+/// the block store. Once caught up, it follows the active chain as blocks are
+/// connected and disconnected.
+pub trait ChainIndex: Send {
+  /// Return the index's name, for logging.
+  fn name(&self) -> &'static str;
+
+  /// Return the hash of the last block indexed, or `None` if none is.
+  fn best_block(&self) -> Option<[u8; SHA256_HASH_SIZE]>;
+
+  /// Index a block at the given height that spent the coins in `undo`, and
+  /// make it the last block indexed. The block's parent must be the last
+  /// block indexed, or the block must be the genesis block of an empty index.
+  fn connect_block(
+    &mut self,
+    block: &Block,
+    height: u32,
+    undo: &BlockUndo,
+  ) -> Result<(), Error>;
+
+  /// Remove the last block indexed, at the given height and having spent the
+  /// coins in `undo`, and make its parent the last block indexed.
+  fn disconnect_block(
+    &mut self,
+    block: &Block,
+    height: u32,
+    undo: &BlockUndo,
+  ) -> Result<(), Error>;
+}
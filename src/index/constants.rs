@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+/// The name of the directory, under the data directory, holding the optional
+/// indexes' databases.
+pub const INDEXES_DIR: &str = "indexes";
+
+/// The name of the directory, under the indexes directory, holding the
+/// transaction index database.
+pub const TXINDEX_DIR: &str = "txindex";
+
+/// The name of the directory, under the indexes directory, holding the address
+/// index database.
+pub const ADDRINDEX_DIR: &str = "addrindex";
+
+/// The name of the directory, under the indexes directory, holding the block
+/// filter index database.
+pub const FILTERINDEX_DIR: &str = "blockfilterindex";
+
+/// The maximum number of blocks indexed per batch while an index catches up
+/// with the active chain, between which the chain is unlocked.
+pub const INDEX_SYNC_BATCH_SIZE: usize = 100;
+
+/// How long the indexing thread waits before checking again for indexes
+/// behind the active chain, once all have caught up.
+pub const INDEX_SYNC_INTERVAL: Duration = Duration::from_secs(1);
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use super::{
+  chain_index::ChainIndex,
+  constants::{FILTERINDEX_DIR, INDEXES_DIR},
+};
+use crate::{
+  storage::{
+    error::Error,
+    kv::{KvStore, WriteBatch},
+  },
+  util::{
+    constants::SHA256_HASH_SIZE,
+    types::{block::Block, block_filter::BlockFilter, coin::BlockUndo},
+  },
+};
+
+/// Key prefix of serialized block filters, followed by the block hash.
+const KEY_FILTER: u8 = b'f';
+
+/// Key prefix of block filter headers, followed by the block hash.
+const KEY_FILTER_HEADER: u8 = b'h';
+
+/// Key of the hash of the last block indexed.
+const KEY_BEST_BLOCK: u8 = b'B';
+
+/// The block filter index: a key-value database under the data directory's
+/// indexes directory holding the filter of every block on the active chain
+/// and the filter header chain committing to them.
+pub struct FilterIndex {
+  db: KvStore,
+  best_block: Option<[u8; SHA256_HASH_SIZE]>,
+}
+
+impl FilterIndex {
+  /// Open the block filter index under the given data directory, creating it
+  /// if needed.
+  pub fn open(data_dir: &Path) -> Result<Self, Error> {
+    let db = KvStore::open(&data_dir.join(INDEXES_DIR).join(FILTERINDEX_DIR))?;
+    let best_block = match db.get(&[KEY_BEST_BLOCK])? {
+      Some(value) => Some(value.try_into().map_err(|_| {
+        Error::Corrupt("malformed block hash in block filter index".into())
+      })?),
+      None => None,
+    };
+    Ok(Self { db, best_block })
+  }
+
+  /// Get the filter of the block with the given hash, if indexed.
+  pub fn filter(
+    &self,
+    block_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Option<BlockFilter>, Error> {
+    match self.db.get(&block_key(KEY_FILTER, block_hash))? {
+      Some(value) => {
+        Ok(Some(BlockFilter::decode(*block_hash, &value).map_err(
+          |err| Error::Corrupt(format!("{} in block filter index", err)),
+        )?))
+      },
+      None => Ok(None),
+    }
+  }
+
+  /// Get the filter header of the block with the given hash, if indexed.
+  pub fn filter_header(
+    &self,
+    block_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Option<[u8; SHA256_HASH_SIZE]>, Error> {
+    match self.db.get(&block_key(KEY_FILTER_HEADER, block_hash))? {
+      Some(value) => Ok(Some(value.try_into().map_err(|_| {
+        Error::Corrupt("malformed filter header in block filter index".into())
+      })?)),
+      None => Ok(None),
+    }
+  }
+}
+
+impl ChainIndex for FilterIndex {
+  fn name(&self) -> &'static str {
+    "blockfilterindex"
+  }
+
+  fn best_block(&self) -> Option<[u8; SHA256_HASH_SIZE]> {
+    self.best_block
+  }
+
+  fn connect_block(
+    &mut self,
+    block: &Block,
+    height: u32,
+    undo: &BlockUndo,
+  ) -> Result<(), Error> {
+    let prev_header = match height {
+      0 => [0u8; SHA256_HASH_SIZE],
+      _ => self
+        .filter_header(&block.prev_block_hash())?
+        .ok_or_else(|| {
+          Error::Corrupt(
+            "block filter index missing previous filter header".into(),
+          )
+        })?,
+    };
+    let block_hash = block.hash();
+    let filter = BlockFilter::new(block, undo);
+    let mut batch = WriteBatch::new();
+    batch.put(&block_key(KEY_FILTER, &block_hash), &filter.encode());
+    batch.put(
+      &block_key(KEY_FILTER_HEADER, &block_hash),
+      &filter.header(&prev_header),
+    );
+    batch.put(&[KEY_BEST_BLOCK], &block_hash);
+    self.db.write(batch, false)?;
+    self.best_block = Some(block_hash);
+    Ok(())
+  }
+
+  fn disconnect_block(
+    &mut self,
+    block: &Block,
+    _height: u32,
+    _undo: &BlockUndo,
+  ) -> Result<(), Error> {
+    let block_hash = block.hash();
+    let mut batch = WriteBatch::new();
+    batch.delete(&block_key(KEY_FILTER, &block_hash));
+    batch.delete(&block_key(KEY_FILTER_HEADER, &block_hash));
+    batch.put(&[KEY_BEST_BLOCK], &block.prev_block_hash());
+    self.db.write(batch, false)?;
+    self.best_block = Some(block.prev_block_hash());
+    Ok(())
+  }
+}
+
+/// Return the key of the given kind for the block with the given hash.
+fn block_key(kind: u8, block_hash: &[u8; SHA256_HASH_SIZE]) -> Vec<u8> {
+  [&[kind][..], block_hash].concat()
+}
@@ -0,0 +1,6 @@
+pub mod addrindex;
+pub mod chain_index;
+pub mod constants;
+pub mod filterindex;
+pub mod thread;
+pub mod txindex;
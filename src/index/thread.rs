@@ -0,0 +1,30 @@
+use std::sync::{Arc, Mutex};
+
+use async_std::task;
+
+use super::constants::{INDEX_SYNC_BATCH_SIZE, INDEX_SYNC_INTERVAL};
+use crate::util::{shutdown::Shutdown, types::chain::ActiveChain};
+
+/// # Indexing thread
+/// Catches up enabled indexes that are behind the chain shared with the other
+/// threads, a batch of blocks at a time so as not to hold up block
+/// validation, then checks again periodically until shutdown is requested.
+pub async fn start_indexing(
+  chain: Arc<Mutex<ActiveChain>>,
+  shutdown: Shutdown,
+) {
+  while !shutdown.is_requested() {
+    let result = chain
+      .lock()
+      .expect("Poisoned chain lock")
+      .sync_indexes(INDEX_SYNC_BATCH_SIZE);
+    match result {
+      Ok(false) => task::yield_now().await,
+      Ok(true) => task::sleep(INDEX_SYNC_INTERVAL).await,
+      Err(err) => {
+        println!("Failed to sync indexes: {}", err);
+        task::sleep(INDEX_SYNC_INTERVAL).await;
+      },
+    }
+  }
+}
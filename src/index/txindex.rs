@@ -0,0 +1,120 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+  chain_index::ChainIndex,
+  constants::{INDEXES_DIR, TXINDEX_DIR},
+};
+use crate::{
+  storage::{
+    error::Error,
+    kv::{KvStore, WriteBatch},
+  },
+  util::{
+    constants::SHA256_HASH_SIZE,
+    types::{block::Block, coin::BlockUndo},
+  },
+};
+
+/// Key prefix of transaction locations, followed by the transaction hash.
+const KEY_TXN: u8 = b't';
+
+/// Key of the hash of the last block indexed.
+const KEY_BEST_BLOCK: u8 = b'B';
+
+/// Where a confirmed transaction is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TxnLocation {
+  /// The hash of the block confirming the transaction.
+  pub block_hash: [u8; SHA256_HASH_SIZE],
+
+  /// The position of the transaction in the block's transactions.
+  pub index: u32,
+}
+
+/// The transaction index: a key-value database under the data directory's
+/// indexes directory mapping the hash of every transaction on the active chain
+/// to the block confirming it.
+///
+/// Should two transactions share a hash, only the later one is found, and
+/// neither once the later one is disconnected.
+pub struct TxIndex {
+  db: KvStore,
+  best_block: Option<[u8; SHA256_HASH_SIZE]>,
+}
+
+impl TxIndex {
+  /// Open the transaction index under the given data directory, creating it
+  /// if needed.
+  pub fn open(data_dir: &Path) -> Result<Self, Error> {
+    let db = KvStore::open(&data_dir.join(INDEXES_DIR).join(TXINDEX_DIR))?;
+    let best_block = match db.get(&[KEY_BEST_BLOCK])? {
+      Some(value) => Some(value.try_into().map_err(|_| {
+        Error::Corrupt("malformed block hash in transaction index".into())
+      })?),
+      None => None,
+    };
+    Ok(Self { db, best_block })
+  }
+
+  /// Get the location of the transaction with the given hash, if indexed.
+  pub fn get(
+    &self,
+    txn_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Option<TxnLocation>, Error> {
+    match self.db.get(&txn_key(txn_hash))? {
+      Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+      None => Ok(None),
+    }
+  }
+}
+
+impl ChainIndex for TxIndex {
+  fn name(&self) -> &'static str {
+    "txindex"
+  }
+
+  fn best_block(&self) -> Option<[u8; SHA256_HASH_SIZE]> {
+    self.best_block
+  }
+
+  fn connect_block(
+    &mut self,
+    block: &Block,
+    _height: u32,
+    _undo: &BlockUndo,
+  ) -> Result<(), Error> {
+    let block_hash = block.hash();
+    let mut batch = WriteBatch::new();
+    for (index, txn) in block.txns().iter().enumerate() {
+      let location = TxnLocation { block_hash, index: index as u32 };
+      batch.put(&txn_key(&txn.hash()), &bincode::serialize(&location)?);
+    }
+    batch.put(&[KEY_BEST_BLOCK], &block_hash);
+    self.db.write(batch, false)?;
+    self.best_block = Some(block_hash);
+    Ok(())
+  }
+
+  fn disconnect_block(
+    &mut self,
+    block: &Block,
+    _height: u32,
+    _undo: &BlockUndo,
+  ) -> Result<(), Error> {
+    let mut batch = WriteBatch::new();
+    for txn in block.txns() {
+      batch.delete(&txn_key(&txn.hash()));
+    }
+    batch.put(&[KEY_BEST_BLOCK], &block.prev_block_hash());
+    self.db.write(batch, false)?;
+    self.best_block = Some(block.prev_block_hash());
+    Ok(())
+  }
+}
+
+/// Return the key of the location of the transaction with the given hash.
+fn txn_key(txn_hash: &[u8; SHA256_HASH_SIZE]) -> Vec<u8> {
+  [&[KEY_TXN][..], txn_hash].concat()
+}
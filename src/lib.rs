@@ -1,3 +1,7 @@
+pub mod events;
+pub mod index;
+pub mod mempool;
 pub mod mining;
 pub mod networking;
+pub mod storage;
 pub mod util;
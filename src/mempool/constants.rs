@@ -0,0 +1,67 @@
+/// The lowest fee rate, in nanoRBTC per 1000 bytes, a transaction must pay to
+/// enter the mempool.
+pub const MIN_RELAY_FEE_RATE: u64 = 1000;
+
+/// How long, in seconds, the mempool takes to halve the minimum fee rate it
+/// raised after evicting transactions for space.
+pub const MIN_FEE_RATE_HALF_LIFE: u32 = 12 * 60 * 60;
+
+/// The maximum number of mempool transactions, counting descendants, a
+/// replacement transaction may evict.
+pub const MAX_REPLACEMENT_EVICTIONS: usize = 100;
+
+/// The maximum number of mempool transactions, counting itself, that a
+/// mempool transaction may descend from.
+pub const MAX_ANCESTORS: usize = 25;
+
+/// The maximum number of mempool transactions, counting itself, that may
+/// descend from a mempool transaction.
+pub const MAX_DESCENDANTS: usize = 25;
+
+/// The file in the data directory the mempool is written to on shutdown and
+/// read back from on startup.
+pub const MEMPOOL_DUMP_FILE: &str = "mempool.dat";
+
+/// The version of the mempool dump format, written at the start of the file.
+pub const MEMPOOL_DUMP_VERSION: u32 = 1;
+
+/// The maximum number of orphan transactions held while waiting for their
+/// parents.
+pub const MAX_ORPHAN_TXNS: usize = 100;
+
+/// The maximum number of orphan transactions held from any one peer.
+pub const MAX_ORPHAN_TXNS_PER_PEER: usize = 25;
+
+/// The number of seconds after which an orphan transaction is evicted.
+pub const ORPHAN_TXN_EXPIRY: u32 = 20 * 60;
+
+/// The file in the data directory the fee estimator's history is written to
+/// on shutdown and read back from on startup.
+pub const FEE_ESTIMATES_FILE: &str = "fee_estimates.dat";
+
+/// The version of the fee estimates file format, written at the start of the
+/// file.
+pub const FEE_ESTIMATES_VERSION: u32 = 1;
+
+/// The highest number of blocks the fee estimator estimates fees to confirm
+/// within.
+pub const MAX_CONFIRM_TARGET: u32 = 48;
+
+/// The factor between the lower bounds of consecutive fee rate buckets.
+pub const FEE_BUCKET_SPACING: f64 = 1.1;
+
+/// The lower bound of the highest fee rate bucket, in nanoRBTC per 1000
+/// bytes.
+pub const MAX_BUCKET_FEE_RATE: u64 = 10_000_000;
+
+/// The factor by which the fee estimator's counts decay with each block.
+pub const FEE_STATS_DECAY: f64 = 0.998;
+
+/// The number of transactions, after decay, a range of fee rate buckets must
+/// hold to estimate from.
+pub const SUFFICIENT_FEE_TXNS: f64 = 4.0;
+
+/// The number of blocks that must be built on a coinbase transaction's block
+/// before the mempool accepts spends of its outputs, so that they are
+/// unlikely to be undone by a reorg.
+pub const COINBASE_MATURITY: u32 = 100;
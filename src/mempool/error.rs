@@ -0,0 +1,233 @@
+use std::fmt::Display;
+
+use crate::{
+  storage,
+  util::{
+    constants::SHA256_HASH_SIZE,
+    hashes::hex,
+    types::{chain, coin::OutPoint},
+  },
+};
+
+/// Reasons a transaction is turned away from the mempool.
+#[derive(Debug)]
+pub enum Error {
+  /// Indicates a coinbase transaction, which is only valid in a block.
+  Coinbase,
+
+  /// Indicates a transaction already in the mempool.
+  AlreadyInMempool([u8; SHA256_HASH_SIZE]),
+
+  /// Indicates a transaction whose outputs are already in the UTXO set.
+  AlreadyConfirmed([u8; SHA256_HASH_SIZE]),
+
+  /// Indicates a transaction spending the same output twice.
+  DuplicateInput(OutPoint),
+
+  /// Indicates a transaction spending an output already spent by the given
+  /// mempool transaction.
+  Conflict(OutPoint, [u8; SHA256_HASH_SIZE]),
+
+  /// Indicates a transaction spending an output that is neither unspent in
+  /// the UTXO set nor created by a mempool transaction.
+  MissingInput(OutPoint),
+
+  /// Indicates a transaction spending a coinbase output that is not yet
+  /// `COINBASE_MATURITY` blocks deep.
+  ImmatureCoinbaseSpend(OutPoint),
+
+  /// Indicates a transaction whose input or output values overflow.
+  ValueOverflow,
+
+  /// Indicates a transaction creating more value than it spends.
+  OutputsExceedInputs { input_value: u64, output_value: u64 },
+
+  /// Indicates a transaction paying less than the minimum fee rate.
+  FeeTooLow { fee_rate: u64, min_fee_rate: u64 },
+
+  /// Indicates a transaction evicted as soon as it was added, for paying too
+  /// little to fit in the mempool's size limit.
+  MempoolFull,
+
+  /// Indicates a transaction that would have more mempool ancestors,
+  /// counting itself, than the maximum.
+  TooManyAncestors { count: usize, max: usize },
+
+  /// Indicates a transaction that would give the given mempool transaction
+  /// more descendants, counting itself, than the maximum.
+  TooManyDescendants {
+    ancestor: [u8; SHA256_HASH_SIZE],
+    count: usize,
+    max: usize,
+  },
+
+  /// Indicates a replacement transaction that would evict more than the
+  /// maximum number of mempool transactions.
+  TooManyReplacements { count: usize, max: usize },
+
+  /// Indicates a replacement transaction spending an output of a mempool
+  /// transaction it would evict.
+  SpendsReplacedOutput(OutPoint),
+
+  /// Indicates a replacement transaction spending an output of a mempool
+  /// transaction that none of the transactions it replaces spend.
+  NewUnconfirmedInput(OutPoint),
+
+  /// Indicates a replacement transaction whose fee rate does not exceed that
+  /// of a transaction it replaces.
+  ReplacementFeeRateTooLow {
+    fee_rate: u64,
+    replaced_fee_rate: u64,
+  },
+
+  /// Indicates a replacement transaction whose fee does not pay for the
+  /// transactions it evicts and its own relay.
+  InsufficientReplacementFee { fee: u64, min_fee: u64 },
+
+  /// Indicates a mempool dump or fee estimates file written in a format
+  /// version this node cannot read.
+  UnsupportedDumpVersion(u32),
+
+  /// Wrapper type for `chain::Error`, from looking up spent coins.
+  ChainError(chain::Error),
+
+  /// Wrapper type for `storage::Error`, from reading or writing the mempool
+  /// dump.
+  StorageError(storage::error::Error),
+}
+
+impl From<chain::Error> for Error {
+  fn from(err: chain::Error) -> Self {
+    Self::ChainError(err)
+  }
+}
+
+impl From<storage::error::Error> for Error {
+  fn from(err: storage::error::Error) -> Self {
+    Self::StorageError(err)
+  }
+}
+
+impl From<std::io::Error> for Error {
+  fn from(err: std::io::Error) -> Self {
+    Self::StorageError(err.into())
+  }
+}
+
+impl From<bincode::Error> for Error {
+  fn from(err: bincode::Error) -> Self {
+    Self::StorageError(err.into())
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Coinbase => {
+        write!(f, "Attempted to add coinbase transaction to mempool")
+      },
+      Error::AlreadyInMempool(hash) => {
+        write!(f, "Transaction {} is already in the mempool", hex(hash))
+      },
+      Error::AlreadyConfirmed(hash) => {
+        write!(f, "Transaction {} is already confirmed", hex(hash))
+      },
+      Error::DuplicateInput(outpoint) => write!(
+        f,
+        "Attempted to add transaction spending {}:{} twice",
+        hex(&outpoint.txn_hash),
+        outpoint.index
+      ),
+      Error::Conflict(outpoint, spender) => write!(
+        f,
+        "Attempted to add transaction spending {}:{}, already spent by \
+         mempool transaction {}",
+        hex(&outpoint.txn_hash),
+        outpoint.index,
+        hex(spender)
+      ),
+      Error::MissingInput(outpoint) => write!(
+        f,
+        "Attempted to add transaction spending missing or spent output {}:{}",
+        hex(&outpoint.txn_hash),
+        outpoint.index
+      ),
+      Error::ImmatureCoinbaseSpend(outpoint) => write!(
+        f,
+        "Attempted to add transaction spending immature coinbase output {}:{}",
+        hex(&outpoint.txn_hash),
+        outpoint.index
+      ),
+      Error::ValueOverflow => {
+        write!(f, "Attempted to add transaction with overflowing values")
+      },
+      Error::OutputsExceedInputs { input_value, output_value } => write!(
+        f,
+        "Attempted to add transaction creating {} from inputs worth {}",
+        output_value, input_value
+      ),
+      Error::FeeTooLow { fee_rate, min_fee_rate } => write!(
+        f,
+        "Attempted to add transaction paying {} per kB, below the minimum of \
+         {} per kB",
+        fee_rate, min_fee_rate
+      ),
+      Error::MempoolFull => {
+        write!(f, "Attempted to add transaction to full mempool")
+      },
+      Error::TooManyAncestors { count, max } => write!(
+        f,
+        "Attempted to add transaction with {} mempool ancestors, more than \
+         the maximum of {}",
+        count, max
+      ),
+      Error::TooManyDescendants { ancestor, count, max } => write!(
+        f,
+        "Attempted to give mempool transaction {} {} descendants, more than \
+         the maximum of {}",
+        hex(ancestor),
+        count,
+        max
+      ),
+      Error::TooManyReplacements { count, max } => write!(
+        f,
+        "Attempted to replace {} mempool transactions, more than the maximum \
+         of {}",
+        count, max
+      ),
+      Error::SpendsReplacedOutput(outpoint) => write!(
+        f,
+        "Attempted to replace mempool transaction {} while spending its \
+         output {}",
+        hex(&outpoint.txn_hash),
+        outpoint.index
+      ),
+      Error::NewUnconfirmedInput(outpoint) => write!(
+        f,
+        "Attempted to replace mempool transactions while spending new \
+         unconfirmed output {}:{}",
+        hex(&outpoint.txn_hash),
+        outpoint.index
+      ),
+      Error::ReplacementFeeRateTooLow { fee_rate, replaced_fee_rate } => {
+        write!(
+          f,
+          "Attempted to replace mempool transaction paying {} per kB with one \
+           paying {} per kB",
+          replaced_fee_rate, fee_rate
+        )
+      },
+      Error::InsufficientReplacementFee { fee, min_fee } => write!(
+        f,
+        "Attempted to replace mempool transactions with one paying {}, below \
+         the minimum of {}",
+        fee, min_fee
+      ),
+      Error::UnsupportedDumpVersion(version) => {
+        write!(f, "Unsupported dump version {}", version)
+      },
+      Error::ChainError(err) => write!(f, "ChainError: {}", err),
+      Error::StorageError(err) => write!(f, "StorageError: {}", err),
+    }
+  }
+}
@@ -0,0 +1,266 @@
+use std::{
+  collections::HashMap,
+  fs::{self, File},
+  io::{BufReader, BufWriter, ErrorKind, Write},
+  path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+  constants::{
+    FEE_BUCKET_SPACING, FEE_ESTIMATES_FILE, FEE_ESTIMATES_VERSION,
+    FEE_STATS_DECAY, MAX_BUCKET_FEE_RATE, MAX_CONFIRM_TARGET,
+    MIN_RELAY_FEE_RATE, SUFFICIENT_FEE_TXNS,
+  },
+  error::Error,
+};
+use crate::{storage, util::constants::SHA256_HASH_SIZE};
+
+/// How sure a fee estimate is to get a transaction confirmed in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Confidence {
+  /// At least 60% of transactions paying the estimate confirmed in time.
+  Low,
+
+  /// At least 85% of transactions paying the estimate confirmed in time.
+  Medium,
+
+  /// At least 95% of transactions paying the estimate confirmed in time.
+  High,
+}
+
+impl Confidence {
+  /// Return the share of transactions that must have confirmed in time.
+  pub fn threshold(&self) -> f64 {
+    match self {
+      Confidence::Low => 0.60,
+      Confidence::Medium => 0.85,
+      Confidence::High => 0.95,
+    }
+  }
+}
+
+/// How many blocks the transactions in each fee rate bucket took to confirm,
+/// as exponentially decaying counts, so that recent blocks weigh the most.
+#[derive(Debug, Deserialize, Serialize)]
+struct FeeStats {
+  /// The height of the last block recorded.
+  height: u32,
+
+  /// The number of transactions confirmed per bucket.
+  confirmed: Vec<f64>,
+
+  /// The number of transactions confirmed within `target` blocks per bucket,
+  /// indexed by `target - 1`.
+  confirmed_within: Vec<Vec<f64>>,
+
+  /// The number of transactions that left the mempool unconfirmed after
+  /// waiting at least `target` blocks per bucket, indexed by `target - 1`.
+  failed_within: Vec<Vec<f64>>,
+}
+
+impl FeeStats {
+  /// Initialize empty stats for the given number of buckets.
+  fn new(buckets: usize) -> Self {
+    let targets = MAX_CONFIRM_TARGET as usize;
+    Self {
+      height: 0,
+      confirmed: vec![0.0; buckets],
+      confirmed_within: vec![vec![0.0; buckets]; targets],
+      failed_within: vec![vec![0.0; buckets]; targets],
+    }
+  }
+}
+
+/// Estimates the fee rate a transaction must pay to confirm within a number
+/// of blocks, from how long mempool transactions paying each rate took.
+///
+/// Fee rates are grouped into buckets growing by `FEE_BUCKET_SPACING` from
+/// `MIN_RELAY_FEE_RATE` to `MAX_BUCKET_FEE_RATE`. Every transaction entering
+/// the mempool is tracked until it is confirmed, counting how many blocks it
+/// took, or leaves unconfirmed, counting it as failing every target it
+/// waited past. Transactions still waiting count as failures too.
+pub struct FeeEstimator {
+  buckets: Vec<u64>,
+  stats: FeeStats,
+  tracked: HashMap<[u8; SHA256_HASH_SIZE], (u32, usize)>,
+}
+
+impl Default for FeeEstimator {
+  fn default() -> Self {
+    let mut buckets = vec![MIN_RELAY_FEE_RATE];
+    let mut fee_rate = MIN_RELAY_FEE_RATE as f64;
+    while fee_rate * FEE_BUCKET_SPACING <= MAX_BUCKET_FEE_RATE as f64 {
+      fee_rate *= FEE_BUCKET_SPACING;
+      buckets.push(fee_rate as u64);
+    }
+    Self {
+      stats: FeeStats::new(buckets.len()),
+      buckets,
+      tracked: HashMap::new(),
+    }
+  }
+}
+
+impl FeeEstimator {
+  /// Initialize an estimator with no history.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Start tracking a transaction paying `fee_rate` that entered the mempool
+  /// when the chain was at `height`.
+  pub fn track(
+    &mut self,
+    hash: [u8; SHA256_HASH_SIZE],
+    height: u32,
+    fee_rate: u64,
+  ) {
+    let bucket = self.bucket(fee_rate);
+    self.tracked.insert(hash, (height, bucket));
+  }
+
+  /// Record a block at `height` confirming the transactions with the given
+  /// hashes, after decaying the counts of earlier blocks.
+  pub fn confirm_block(
+    &mut self,
+    height: u32,
+    hashes: &[[u8; SHA256_HASH_SIZE]],
+  ) {
+    let stats = &mut self.stats;
+    stats
+      .confirmed
+      .iter_mut()
+      .for_each(|count| *count *= FEE_STATS_DECAY);
+    for counts in stats
+      .confirmed_within
+      .iter_mut()
+      .chain(stats.failed_within.iter_mut())
+    {
+      counts
+        .iter_mut()
+        .for_each(|count| *count *= FEE_STATS_DECAY);
+    }
+    stats.height = height;
+
+    for hash in hashes {
+      let (entry_height, bucket) = match self.tracked.remove(hash) {
+        Some(tracked) => tracked,
+        None => continue,
+      };
+      let blocks = height.saturating_sub(entry_height).max(1);
+      stats.confirmed[bucket] += 1.0;
+      for target in blocks..=MAX_CONFIRM_TARGET {
+        stats.confirmed_within[target as usize - 1][bucket] += 1.0;
+      }
+    }
+  }
+
+  /// Stop tracking a transaction that left the mempool unconfirmed, counting
+  /// it as failing every target it waited past.
+  pub fn untrack(&mut self, hash: &[u8; SHA256_HASH_SIZE]) {
+    let (entry_height, bucket) = match self.tracked.remove(hash) {
+      Some(tracked) => tracked,
+      None => return,
+    };
+    let waited = self.stats.height.saturating_sub(entry_height);
+    for target in 1..=waited.min(MAX_CONFIRM_TARGET) {
+      self.stats.failed_within[target as usize - 1][bucket] += 1.0;
+    }
+  }
+
+  /// Return the lowest fee rate, in nanoRBTC per 1000 bytes, at which the
+  /// share of transactions confirmed within `target` blocks meets the given
+  /// confidence, or `None` without enough history or for a target outside
+  /// 1 to `MAX_CONFIRM_TARGET`.
+  ///
+  /// Buckets are grouped from the highest fee rate down until each group
+  /// holds `SUFFICIENT_FEE_TXNS` transactions, and the estimate is the lower
+  /// bound of the last group to succeed before the first to fail.
+  pub fn estimate(&self, target: u32, confidence: Confidence) -> Option<u64> {
+    if target == 0 || target > MAX_CONFIRM_TARGET {
+      return None;
+    }
+    let index = target as usize - 1;
+    let mut waiting = vec![0.0; self.buckets.len()];
+    for (entry_height, bucket) in self.tracked.values() {
+      if self.stats.height.saturating_sub(*entry_height) >= target {
+        waiting[*bucket] += 1.0;
+      }
+    }
+
+    let mut estimate = None;
+    let (mut confirmed, mut total) = (0.0, 0.0);
+    for bucket in (0..self.buckets.len()).rev() {
+      confirmed += self.stats.confirmed_within[index][bucket];
+      total += self.stats.confirmed[bucket]
+        + self.stats.failed_within[index][bucket]
+        + waiting[bucket];
+      if total < SUFFICIENT_FEE_TXNS {
+        continue;
+      }
+      if confirmed / total < confidence.threshold() {
+        break;
+      }
+      estimate = Some(self.buckets[bucket]);
+      confirmed = 0.0;
+      total = 0.0;
+    }
+    estimate
+  }
+
+  /// Write the recorded history to `FEE_ESTIMATES_FILE` in `data_dir`,
+  /// replacing any earlier file atomically. Tracked transactions are not
+  /// written, as they are tracked again when the mempool is loaded.
+  pub fn dump(&self, data_dir: &Path) -> Result<(), Error> {
+    let path = data_dir.join(FEE_ESTIMATES_FILE);
+    let tmp_path = path.with_extension("tmp");
+    let mut tmp = BufWriter::new(File::create(&tmp_path)?);
+    bincode::serialize_into(&mut tmp, &FEE_ESTIMATES_VERSION)?;
+    bincode::serialize_into(&mut tmp, &self.stats)?;
+    tmp.flush()?;
+    tmp.get_ref().sync_all()?;
+    fs::rename(&tmp_path, &path)?;
+    Ok(())
+  }
+
+  /// Read back the history written by `dump` to `data_dir`, if any,
+  /// replacing the recorded history.
+  pub fn load(&mut self, data_dir: &Path) -> Result<(), Error> {
+    let file = match File::open(data_dir.join(FEE_ESTIMATES_FILE)) {
+      Ok(file) => file,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+      Err(err) => return Err(err.into()),
+    };
+    let mut reader = BufReader::new(file);
+    let version: u32 = bincode::deserialize_from(&mut reader)?;
+    if version != FEE_ESTIMATES_VERSION {
+      return Err(Error::UnsupportedDumpVersion(version));
+    }
+    let stats: FeeStats = bincode::deserialize_from(&mut reader)?;
+    let buckets = self.buckets.len();
+    let is_shaped = |counts: &Vec<Vec<f64>>| {
+      counts.len() == MAX_CONFIRM_TARGET as usize
+        && counts.iter().all(|counts| counts.len() == buckets)
+    };
+    if stats.confirmed.len() != buckets
+      || !is_shaped(&stats.confirmed_within)
+      || !is_shaped(&stats.failed_within)
+    {
+      return Err(
+        storage::error::Error::Corrupt("fee estimate buckets".into()).into(),
+      );
+    }
+    self.stats = stats;
+    Ok(())
+  }
+
+  /// Return the index of the bucket holding the given fee rate.
+  fn bucket(&self, fee_rate: u64) -> usize {
+    self
+      .buckets
+      .partition_point(|bound| *bound <= fee_rate)
+      .saturating_sub(1)
+  }
+}
@@ -0,0 +1,5 @@
+pub mod constants;
+pub mod error;
+pub mod fees;
+pub mod orphans;
+pub mod pool;
@@ -0,0 +1,178 @@
+use std::{
+  collections::{HashMap, HashSet},
+  net::Ipv4Addr,
+};
+
+use super::constants::{
+  MAX_ORPHAN_TXNS, MAX_ORPHAN_TXNS_PER_PEER, ORPHAN_TXN_EXPIRY,
+};
+use crate::util::{
+  constants::SHA256_HASH_SIZE,
+  types::{coin::OutPoint, txn::Txn},
+};
+
+/// A transaction spending outputs not yet known, held until the transactions
+/// creating them arrive.
+#[derive(Debug)]
+pub struct OrphanTxn {
+  /// The orphaned transaction.
+  pub txn: Txn,
+
+  /// The peer the transaction was received from.
+  pub peer: Ipv4Addr,
+
+  /// The time the transaction was received.
+  pub received: u32,
+
+  /// The outpoints the transaction spends that were missing when it was
+  /// received.
+  pub missing: Vec<OutPoint>,
+}
+
+/// A bounded pool of orphan transactions, indexed by the outpoints they are
+/// missing. Each peer may hold at most `MAX_ORPHAN_TXNS_PER_PEER` orphans, so
+/// that no one peer can push out everyone else's.
+#[derive(Debug, Default)]
+pub struct OrphanTxnPool {
+  orphans: HashMap<[u8; SHA256_HASH_SIZE], OrphanTxn>,
+  by_outpoint: HashMap<OutPoint, Vec<[u8; SHA256_HASH_SIZE]>>,
+  per_peer: HashMap<Ipv4Addr, usize>,
+}
+
+impl OrphanTxnPool {
+  /// Initialize an empty orphan pool.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Return the number of orphans in the pool.
+  pub fn len(&self) -> usize {
+    self.orphans.len()
+  }
+
+  /// Return whether the pool is empty.
+  pub fn is_empty(&self) -> bool {
+    self.orphans.is_empty()
+  }
+
+  /// Return whether the transaction with the given hash is in the pool.
+  pub fn contains(&self, hash: &[u8; SHA256_HASH_SIZE]) -> bool {
+    self.orphans.contains_key(hash)
+  }
+
+  /// Add a transaction received from `peer` at time `now`, missing the given
+  /// outpoints, to the pool.
+  ///
+  /// Orphans older than `ORPHAN_TXN_EXPIRY` are evicted first, then the
+  /// peer's oldest orphan if it holds `MAX_ORPHAN_TXNS_PER_PEER`, then the
+  /// oldest orphans until the pool is back under `MAX_ORPHAN_TXNS`.
+  pub fn insert(
+    &mut self,
+    txn: Txn,
+    peer: Ipv4Addr,
+    missing: Vec<OutPoint>,
+    now: u32,
+  ) {
+    let hash = txn.hash();
+    if self.orphans.contains_key(&hash) {
+      return;
+    }
+    self.expire(now);
+    if self.per_peer.get(&peer).copied().unwrap_or(0)
+      >= MAX_ORPHAN_TXNS_PER_PEER
+    {
+      self.evict_oldest(|orphan| orphan.peer == peer);
+    }
+    while self.orphans.len() >= MAX_ORPHAN_TXNS {
+      self.evict_oldest(|_| true);
+    }
+
+    for outpoint in &missing {
+      self.by_outpoint.entry(*outpoint).or_default().push(hash);
+    }
+    *self.per_peer.entry(peer).or_default() += 1;
+    self
+      .orphans
+      .insert(hash, OrphanTxn { txn, peer, received: now, missing });
+  }
+
+  /// Remove and return every orphan missing an output of the transaction
+  /// with the given hash, which has `txo_count` outputs.
+  pub fn take_spenders(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    txo_count: usize,
+  ) -> Vec<OrphanTxn> {
+    let spenders: HashSet<_> = (0..txo_count)
+      .filter_map(|index| self.by_outpoint.get(&OutPoint::new(*hash, index)))
+      .flatten()
+      .copied()
+      .collect();
+    spenders
+      .iter()
+      .filter_map(|spender| self.remove(spender))
+      .collect()
+  }
+
+  /// Evict every orphan received from the given peer.
+  pub fn remove_for_peer(&mut self, peer: &Ipv4Addr) {
+    let orphans: Vec<_> = self
+      .orphans
+      .iter()
+      .filter(|(_, orphan)| orphan.peer == *peer)
+      .map(|(hash, _)| *hash)
+      .collect();
+    for hash in orphans {
+      self.remove(&hash);
+    }
+  }
+
+  /// Evict every orphan received more than `ORPHAN_TXN_EXPIRY` seconds before
+  /// `now`.
+  pub fn expire(&mut self, now: u32) {
+    let expired: Vec<_> = self
+      .orphans
+      .iter()
+      .filter(|(_, orphan)| {
+        now.saturating_sub(orphan.received) > ORPHAN_TXN_EXPIRY
+      })
+      .map(|(hash, _)| *hash)
+      .collect();
+    for hash in expired {
+      self.remove(&hash);
+    }
+  }
+
+  /// Evict the orphan that was received first among those matching `filter`.
+  fn evict_oldest(&mut self, filter: impl Fn(&OrphanTxn) -> bool) {
+    let oldest = self
+      .orphans
+      .iter()
+      .filter(|(_, orphan)| filter(orphan))
+      .min_by_key(|(_, orphan)| orphan.received)
+      .map(|(hash, _)| *hash);
+    if let Some(hash) = oldest {
+      self.remove(&hash);
+    }
+  }
+
+  /// Remove the orphan with the given hash from the pool and its indexes.
+  fn remove(&mut self, hash: &[u8; SHA256_HASH_SIZE]) -> Option<OrphanTxn> {
+    let orphan = self.orphans.remove(hash)?;
+    for outpoint in &orphan.missing {
+      if let Some(spenders) = self.by_outpoint.get_mut(outpoint) {
+        spenders.retain(|spender| spender != hash);
+        if spenders.is_empty() {
+          self.by_outpoint.remove(outpoint);
+        }
+      }
+    }
+    if let Some(count) = self.per_peer.get_mut(&orphan.peer) {
+      *count -= 1;
+      if *count == 0 {
+        self.per_peer.remove(&orphan.peer);
+      }
+    }
+    Some(orphan)
+  }
+}
@@ -0,0 +1,1022 @@
+use std::{
+  cmp::Reverse,
+  collections::{BTreeSet, HashMap, HashSet},
+  fs::{self, File},
+  io::{BufReader, BufWriter, ErrorKind, Write},
+  path::Path,
+  sync::Arc,
+};
+
+use super::{
+  constants::{
+    COINBASE_MATURITY, MAX_ANCESTORS, MAX_DESCENDANTS,
+    MAX_REPLACEMENT_EVICTIONS, MEMPOOL_DUMP_FILE, MEMPOOL_DUMP_VERSION,
+    MIN_FEE_RATE_HALF_LIFE, MIN_RELAY_FEE_RATE,
+  },
+  error::Error,
+  fees::FeeEstimator,
+};
+use crate::{
+  events::validation::{ValidationEvent, ValidationEvents},
+  logln,
+  util::{
+    config::Config,
+    constants::SHA256_HASH_SIZE,
+    hashes::hex,
+    types::{
+      block::Block,
+      chain::ActiveChain,
+      coin::{Coin, OutPoint},
+      txn::Txn,
+    },
+  },
+};
+
+/// Why a transaction left the mempool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemovalReason {
+  /// The transaction was confirmed in a block.
+  Block,
+
+  /// The transaction, or one of its ancestors, spent an output also spent by
+  /// a transaction confirmed in a block.
+  Conflict,
+
+  /// The transaction, or one of its ancestors, was replaced by a conflicting
+  /// transaction paying a higher fee.
+  Replaced,
+
+  /// The transaction, or one of its ancestors, was in the mempool for longer
+  /// than its configured expiry.
+  Expiry,
+
+  /// The transaction, or one of its ancestors, was evicted to keep the
+  /// mempool within its configured size, paying less than everything else.
+  SizeLimit,
+
+  /// The transaction, or one of its ancestors, spent an output that a reorg
+  /// removed from the UTXO set or made an immature coinbase output again.
+  Reorg,
+}
+
+/// A transaction in the mempool, with what it pays and when it arrived.
+///
+/// Each entry also tracks its package of in-mempool ancestors and that of its
+/// in-mempool descendants: the number of transactions in each, counting the
+/// entry itself, and their total size and fees.
+#[derive(Clone, Debug)]
+pub struct MempoolEntry {
+  txn: Arc<Txn>,
+  hash: [u8; SHA256_HASH_SIZE],
+  fee: u64,
+  size: usize,
+  time: u32,
+  height: u32,
+  sequence: u64,
+  ancestors: Package,
+  descendants: Package,
+}
+
+/// An entry's place in an index ordered by the fee rate of one of its
+/// packages, then by age, newest first, then by hash.
+type FeeRateKey = (u64, Reverse<u64>, [u8; SHA256_HASH_SIZE]);
+
+/// The number of transactions in a package, and their total size and fees.
+#[derive(Clone, Copy, Debug, Default)]
+struct Package {
+  count: usize,
+  size: usize,
+  fee: u64,
+}
+
+impl Package {
+  /// Return the package of a single transaction.
+  fn of(entry: &MempoolEntry) -> Self {
+    Self { count: 1, size: entry.size, fee: entry.fee }
+  }
+
+  /// Add the transactions of another package.
+  fn add(&mut self, other: Package) {
+    self.count += other.count;
+    self.size += other.size;
+    self.fee += other.fee;
+  }
+
+  /// Remove the transactions of another package, which must be included in
+  /// this one.
+  fn sub(&mut self, other: Package) {
+    self.count -= other.count;
+    self.size -= other.size;
+    self.fee -= other.fee;
+  }
+
+  /// Return the fee rate the package pays, in nanoRBTC per 1000 bytes.
+  fn fee_rate(&self) -> u64 {
+    fee_rate(self.fee, self.size)
+  }
+}
+
+impl MempoolEntry {
+  /// Return the transaction.
+  pub fn txn(&self) -> &Arc<Txn> {
+    &self.txn
+  }
+
+  /// Return the hash of the transaction.
+  pub fn hash(&self) -> [u8; SHA256_HASH_SIZE] {
+    self.hash
+  }
+
+  /// Return the fee the transaction pays: the value of its inputs less that
+  /// of its outputs.
+  pub fn fee(&self) -> u64 {
+    self.fee
+  }
+
+  /// Return the size of the transaction's serialized data, in bytes.
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
+  /// Return the fee rate the transaction pays, in nanoRBTC per 1000 bytes.
+  pub fn fee_rate(&self) -> u64 {
+    fee_rate(self.fee, self.size)
+  }
+
+  /// Return the time the transaction entered the mempool.
+  pub fn time(&self) -> u32 {
+    self.time
+  }
+
+  /// Return the height of the active chain when the transaction entered the
+  /// mempool.
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  /// Return the number of in-mempool ancestors of the transaction, counting
+  /// itself.
+  pub fn ancestor_count(&self) -> usize {
+    self.ancestors.count
+  }
+
+  /// Return the total size of the transaction and its in-mempool ancestors.
+  pub fn ancestor_size(&self) -> usize {
+    self.ancestors.size
+  }
+
+  /// Return the total fee of the transaction and its in-mempool ancestors.
+  pub fn ancestor_fee(&self) -> u64 {
+    self.ancestors.fee
+  }
+
+  /// Return the fee rate the transaction and its in-mempool ancestors pay
+  /// together, in nanoRBTC per 1000 bytes. Mining the transaction means
+  /// mining them too, so this is what it is worth to a miner.
+  pub fn ancestor_fee_rate(&self) -> u64 {
+    fee_rate(self.ancestors.fee, self.ancestors.size)
+  }
+
+  /// Return the number of in-mempool descendants of the transaction, counting
+  /// itself.
+  pub fn descendant_count(&self) -> usize {
+    self.descendants.count
+  }
+
+  /// Return the total size of the transaction and its in-mempool
+  /// descendants.
+  pub fn descendant_size(&self) -> usize {
+    self.descendants.size
+  }
+
+  /// Return the total fee of the transaction and its in-mempool descendants.
+  pub fn descendant_fee(&self) -> u64 {
+    self.descendants.fee
+  }
+
+  /// Return the entry's place in an index ordered by the given package's fee
+  /// rate.
+  fn key(&self, package: &Package) -> FeeRateKey {
+    (package.fee_rate(), Reverse(self.sequence), self.hash)
+  }
+}
+
+/// Unconfirmed transactions that would be valid in the next block, shared
+/// between the miner, which mines them, and the networking thread, which
+/// accepts them from peers and relays them.
+///
+/// Each entry's inputs spend outputs unspent in the UTXO set or created by
+/// other entries, and no two entries spend the same output. Entries are
+/// indexed by hash, by the outpoints they spend, by the fee rates of their
+/// ancestor and descendant packages, for mining and eviction, and by the time
+/// they entered, for expiry.
+///
+/// A transaction conflicting with entries may replace them, along with their
+/// descendants, if each conflicting entry opts in to replacement (or the
+/// mempool is configured for full replace-by-fee) and the replacement pays
+/// more, as in BIP 125.
+///
+/// The mempool holds transactions totalling at most its configured size.
+/// Past that, it evicts those whose package of descendants pays the lowest
+/// fee rate, and raises its minimum fee rate above theirs so they do not just
+/// come back. The raised minimum halves every `MIN_FEE_RATE_HALF_LIFE`.
+/// Transactions are also dropped once older than the configured expiry.
+///
+/// Adding a transaction reads the chain's UTXO set, so whoever holds both
+/// locks must take the chain's first.
+#[derive(Default)]
+pub struct Mempool {
+  entries: HashMap<[u8; SHA256_HASH_SIZE], MempoolEntry>,
+  spenders: HashMap<OutPoint, [u8; SHA256_HASH_SIZE]>,
+  by_ancestor_fee_rate: BTreeSet<FeeRateKey>,
+  by_descendant_fee_rate: BTreeSet<FeeRateKey>,
+  by_time: BTreeSet<(u32, [u8; SHA256_HASH_SIZE])>,
+  next_sequence: u64,
+  size: usize,
+  max_size: usize,
+  expiry: u32,
+  full_rbf: bool,
+  raised_min_fee_rate: u64,
+  raised_at: u32,
+  fee_estimator: FeeEstimator,
+  events: Option<ValidationEvents>,
+  unannounced: Option<[u8; SHA256_HASH_SIZE]>,
+}
+
+impl Mempool {
+  /// Initialize an empty mempool with the given node settings.
+  pub fn new(config: &Config) -> Self {
+    Self {
+      max_size: config.mempool_max_size,
+      expiry: config.mempool_expiry,
+      full_rbf: config.mempool_full_rbf,
+      ..Self::default()
+    }
+  }
+
+  /// Set the handle through which transactions entering and leaving the
+  /// mempool are published.
+  pub fn set_validation_events(&mut self, events: ValidationEvents) {
+    self.events = Some(events);
+  }
+
+  /// Validate a transaction against the chain's UTXO set and the mempool, and
+  /// add it at the given time. Return its hash.
+  ///
+  /// Returns `Coinbase` for a coinbase transaction, `AlreadyInMempool` or
+  /// `AlreadyConfirmed` for a known transaction, `DuplicateInput` or
+  /// `MissingInput` if its inputs are not each unspent by anything else,
+  /// `ImmatureCoinbaseSpend` if it spends a coinbase output too soon,
+  /// `ValueOverflow` or `OutputsExceedInputs` if its values do not add up,
+  /// `FeeTooLow` if its fee rate is below `min_fee_rate`, `MempoolFull` if it
+  /// is evicted right away to keep the mempool within its size, and
+  /// `TooManyAncestors` or `TooManyDescendants` if it would make a chain of
+  /// mempool transactions longer than `MAX_ANCESTORS` or `MAX_DESCENDANTS`.
+  ///
+  /// A transaction spending outputs already spent by mempool transactions
+  /// replaces them and their descendants. Returns `Conflict` if one of them
+  /// does not signal replacement and full replace-by-fee is off, or an error
+  /// from `check_replacement` if the replacement is not worth it.
+  pub fn accept(
+    &mut self,
+    txn: Txn,
+    chain: &mut ActiveChain,
+    time: u32,
+  ) -> Result<[u8; SHA256_HASH_SIZE], Error> {
+    self.accept_at(txn, chain, time, time)
+  }
+
+  /// Validate and add a transaction as `accept` does at the given time, but
+  /// record it as having entered the mempool at `entry_time`, e.g. when it
+  /// is read back from a dump.
+  fn accept_at(
+    &mut self,
+    txn: Txn,
+    chain: &mut ActiveChain,
+    time: u32,
+    entry_time: u32,
+  ) -> Result<[u8; SHA256_HASH_SIZE], Error> {
+    if txn.is_coinbase() {
+      return Err(Error::Coinbase);
+    }
+    let hash = txn.hash();
+    if self.entries.contains_key(&hash) {
+      return Err(Error::AlreadyInMempool(hash));
+    }
+    for index in 0..txn.txo_list().len() {
+      if chain.coin(&OutPoint::new(hash, index))?.is_some() {
+        return Err(Error::AlreadyConfirmed(hash));
+      }
+    }
+
+    let height = chain.height();
+    let mut spent = HashSet::new();
+    let mut conflicts = HashSet::new();
+    let mut input_value = 0u64;
+    for txi in txn.txi_list() {
+      let outpoint = OutPoint::spent_by(txi);
+      if !spent.insert(outpoint) {
+        return Err(Error::DuplicateInput(outpoint));
+      }
+      if let Some(spender) = self.spenders.get(&outpoint) {
+        if !self.full_rbf && !self.entries[spender].txn.signals_rbf() {
+          return Err(Error::Conflict(outpoint, *spender));
+        }
+        conflicts.insert(*spender);
+      }
+      let value = match self.entries.get(&outpoint.txn_hash) {
+        Some(parent) => parent
+          .txn
+          .txo_list()
+          .get(outpoint.index)
+          .ok_or(Error::MissingInput(outpoint))?
+          .value(),
+        None => {
+          let coin = chain
+            .coin(&outpoint)?
+            .ok_or(Error::MissingInput(outpoint))?;
+          if is_immature(&coin, height) {
+            return Err(Error::ImmatureCoinbaseSpend(outpoint));
+          }
+          coin.txo.value()
+        },
+      };
+      input_value =
+        input_value.checked_add(value).ok_or(Error::ValueOverflow)?;
+    }
+
+    let mut output_value = 0u64;
+    for txo in txn.txo_list() {
+      output_value = output_value
+        .checked_add(txo.value())
+        .ok_or(Error::ValueOverflow)?;
+    }
+    if output_value > input_value {
+      return Err(Error::OutputsExceedInputs { input_value, output_value });
+    }
+    let fee = input_value - output_value;
+    let size = txn.size();
+    let min_fee_rate = self.min_fee_rate(time);
+    if fee_rate(fee, size) < min_fee_rate {
+      return Err(Error::FeeTooLow {
+        fee_rate: fee_rate(fee, size),
+        min_fee_rate,
+      });
+    }
+
+    if !conflicts.is_empty() {
+      self.check_replacement(&txn, &conflicts, fee, size)?;
+    }
+    let ancestors = self.ancestors(&txn);
+    self.check_package_limits(&ancestors)?;
+    for conflict in &conflicts {
+      self.remove_with_descendants(conflict, RemovalReason::Replaced);
+    }
+
+    let txn = Arc::new(txn);
+    for outpoint in spent {
+      self.spenders.insert(outpoint, hash);
+    }
+    let mut entry = MempoolEntry {
+      txn: txn.clone(),
+      hash,
+      fee,
+      size,
+      time: entry_time,
+      height,
+      sequence: self.next_sequence,
+      ancestors: Package::default(),
+      descendants: Package::default(),
+    };
+    entry.ancestors = Package::of(&entry);
+    entry.descendants = Package::of(&entry);
+    for ancestor in &ancestors {
+      self.update_packages(ancestor, |ancestor| {
+        ancestor.descendants.add(Package::of(&entry))
+      });
+      entry.ancestors.add(Package::of(&self.entries[ancestor]));
+    }
+    self.next_sequence += 1;
+    self.size += size;
+    self.insert_entry(entry);
+    self.fee_estimator.track(hash, height, fee_rate(fee, size));
+
+    // A transaction returned from a disconnected block may already have
+    // children in the mempool, which join its package and those of its
+    // ancestors.
+    let has_children = (0..txn.txo_list().len())
+      .any(|index| self.spenders.contains_key(&OutPoint::new(hash, index)));
+    if has_children {
+      let mut affected = self.descendants(&hash);
+      affected.extend(ancestors);
+      self.recompute_packages(&affected);
+    }
+
+    // Only announce the transaction once it has survived making room for
+    // it, and say nothing of its removal if it did not.
+    self.unannounced = Some(hash);
+    self.expire(time);
+    self.trim(time);
+    self.unannounced = None;
+    if !self.entries.contains_key(&hash) {
+      return Err(Error::MempoolFull);
+    }
+    self.notify(|| ValidationEvent::TransactionAddedToMempool(txn));
+    Ok(hash)
+  }
+
+  /// Return the lowest fee rate, in nanoRBTC per 1000 bytes, a transaction
+  /// must pay to enter the mempool at the given time: `MIN_RELAY_FEE_RATE`,
+  /// or the rate last raised by evictions, decayed since, if higher.
+  pub fn min_fee_rate(&self, time: u32) -> u64 {
+    let half_lives = time.saturating_sub(self.raised_at) as f64
+      / MIN_FEE_RATE_HALF_LIFE as f64;
+    let decayed = self.raised_min_fee_rate as f64 / half_lives.exp2();
+    MIN_RELAY_FEE_RATE.max(decayed as u64)
+  }
+
+  /// Remove every transaction that entered the mempool more than the
+  /// configured expiry before the given time, along with its descendants.
+  pub fn expire(&mut self, time: u32) {
+    while let Some(&(entry_time, hash)) = self.by_time.first() {
+      if time.saturating_sub(entry_time) <= self.expiry {
+        break;
+      }
+      self.remove_with_descendants(&hash, RemovalReason::Expiry);
+    }
+  }
+
+  /// Evict transactions until the mempool is within its configured size,
+  /// each time the one whose package of descendants pays the lowest fee rate,
+  /// along with those descendants. Raise the minimum fee rate to above the
+  /// highest rate evicted.
+  fn trim(&mut self, time: u32) {
+    while self.size > self.max_size {
+      let (evicted_fee_rate, _, hash) = *self
+        .by_descendant_fee_rate
+        .first()
+        .expect("Mempool over its size with no entries");
+      self.raised_min_fee_rate = self
+        .min_fee_rate(time)
+        .max(evicted_fee_rate + MIN_RELAY_FEE_RATE);
+      self.raised_at = time;
+      self.remove_with_descendants(&hash, RemovalReason::SizeLimit);
+    }
+  }
+
+  /// Return the outpoints a transaction spends that are neither created by a
+  /// mempool transaction nor unspent in the chain's UTXO set, e.g. because
+  /// the transaction creating them has not arrived yet.
+  pub fn missing_inputs(
+    &self,
+    txn: &Txn,
+    chain: &mut ActiveChain,
+  ) -> Result<Vec<OutPoint>, Error> {
+    let mut missing = Vec::new();
+    for txi in txn.txi_list() {
+      let outpoint = OutPoint::spent_by(txi);
+      let in_mempool = self
+        .entries
+        .get(&outpoint.txn_hash)
+        .is_some_and(|parent| outpoint.index < parent.txn.txo_list().len());
+      if !in_mempool && chain.coin(&outpoint)?.is_none() {
+        missing.push(outpoint);
+      }
+    }
+    Ok(missing)
+  }
+
+  /// Check that a transaction with the given in-mempool ancestors would have
+  /// no more than `MAX_ANCESTORS` of them, counting itself, and give none of
+  /// them more than `MAX_DESCENDANTS` descendants.
+  fn check_package_limits(
+    &self,
+    ancestors: &HashSet<[u8; SHA256_HASH_SIZE]>,
+  ) -> Result<(), Error> {
+    if ancestors.len() + 1 > MAX_ANCESTORS {
+      return Err(Error::TooManyAncestors {
+        count: ancestors.len() + 1,
+        max: MAX_ANCESTORS,
+      });
+    }
+    for ancestor in ancestors {
+      let count = self.entries[ancestor].descendants.count + 1;
+      if count > MAX_DESCENDANTS {
+        return Err(Error::TooManyDescendants {
+          ancestor: *ancestor,
+          count,
+          max: MAX_DESCENDANTS,
+        });
+      }
+    }
+    Ok(())
+  }
+
+  /// Check that a transaction paying `fee` for `size` bytes may replace the
+  /// given conflicting entries and their descendants.
+  ///
+  /// Returns `TooManyReplacements` if more than `MAX_REPLACEMENT_EVICTIONS`
+  /// entries would be evicted, `SpendsReplacedOutput` if the transaction
+  /// spends an output of one of them, `NewUnconfirmedInput` if it spends an
+  /// output of another entry that no conflicting entry spends,
+  /// `ReplacementFeeRateTooLow` if its fee rate does not exceed each
+  /// conflicting entry's, and `InsufficientReplacementFee` if its fee does not
+  /// cover the evicted entries' fees plus its own size at
+  /// `MIN_RELAY_FEE_RATE`.
+  fn check_replacement(
+    &self,
+    txn: &Txn,
+    conflicts: &HashSet<[u8; SHA256_HASH_SIZE]>,
+    fee: u64,
+    size: usize,
+  ) -> Result<(), Error> {
+    let mut evicted = HashSet::new();
+    for conflict in conflicts {
+      evicted.extend(self.descendants(conflict));
+    }
+    if evicted.len() > MAX_REPLACEMENT_EVICTIONS {
+      return Err(Error::TooManyReplacements {
+        count: evicted.len(),
+        max: MAX_REPLACEMENT_EVICTIONS,
+      });
+    }
+
+    let replaced_inputs: HashSet<_> = conflicts
+      .iter()
+      .flat_map(|conflict| self.entries[conflict].txn.txi_list())
+      .map(OutPoint::spent_by)
+      .collect();
+    for txi in txn.txi_list() {
+      let outpoint = OutPoint::spent_by(txi);
+      if evicted.contains(&outpoint.txn_hash) {
+        return Err(Error::SpendsReplacedOutput(outpoint));
+      }
+      if self.entries.contains_key(&outpoint.txn_hash)
+        && !replaced_inputs.contains(&outpoint)
+      {
+        return Err(Error::NewUnconfirmedInput(outpoint));
+      }
+    }
+
+    for conflict in conflicts {
+      let conflict = &self.entries[conflict];
+      if fee_rate(fee, size) <= conflict.fee_rate() {
+        return Err(Error::ReplacementFeeRateTooLow {
+          fee_rate: fee_rate(fee, size),
+          replaced_fee_rate: conflict.fee_rate(),
+        });
+      }
+    }
+
+    let evicted_fee: u64 =
+      evicted.iter().map(|hash| self.entries[hash].fee).sum();
+    let min_fee =
+      evicted_fee + (MIN_RELAY_FEE_RATE as u128 * size as u128 / 1000) as u64;
+    if fee < min_fee {
+      return Err(Error::InsufficientReplacementFee { fee, min_fee });
+    }
+    Ok(())
+  }
+
+  /// Return the transactions of blocks disconnected from the active chain,
+  /// given tip first, to the mempool at the given time. Then remove every
+  /// entry spending an output the chain no longer has unspent, or a coinbase
+  /// output no longer mature, along with its descendants.
+  ///
+  /// Transactions confirmed again by the new chain, or conflicting with it,
+  /// are turned away, since the outputs they spend are gone.
+  pub fn update_for_disconnected(
+    &mut self,
+    blocks: &[Arc<Block>],
+    chain: &mut ActiveChain,
+    time: u32,
+  ) -> Result<(), Error> {
+    for block in blocks.iter().rev() {
+      for txn in block.txns().iter().filter(|txn| !txn.is_coinbase()) {
+        if let Err(Error::ChainError(err)) =
+          self.accept(txn.clone(), chain, time)
+        {
+          return Err(err.into());
+        }
+      }
+    }
+
+    let height = chain.height();
+    let mut invalid = Vec::new();
+    for entry in self.entries.values() {
+      for txi in entry.txn.txi_list() {
+        let outpoint = OutPoint::spent_by(txi);
+        if self.entries.contains_key(&outpoint.txn_hash) {
+          continue;
+        }
+        let is_spendable = match chain.coin(&outpoint)? {
+          Some(coin) => !is_immature(&coin, height),
+          None => false,
+        };
+        if !is_spendable {
+          invalid.push(entry.hash);
+          break;
+        }
+      }
+    }
+    for hash in invalid {
+      self.remove_with_descendants(&hash, RemovalReason::Reorg);
+    }
+    Ok(())
+  }
+
+  /// Remove the transactions confirmed by a block at the given height, and
+  /// those conflicting with them along with their descendants. Record how
+  /// long the confirmed transactions waited for the fee estimator.
+  pub fn remove_for_block(&mut self, block: &Block, height: u32) {
+    let hashes: Vec<_> = block.txns().iter().map(Txn::hash).collect();
+    self.fee_estimator.confirm_block(height, &hashes);
+    for txn in block.txns() {
+      let hash = txn.hash();
+      if self.entries.contains_key(&hash) {
+        self.remove(&hash, RemovalReason::Block);
+      }
+      for txi in txn.txi_list() {
+        if let Some(spender) = self.spenders.get(&OutPoint::spent_by(txi)) {
+          let spender = *spender;
+          self.remove_with_descendants(&spender, RemovalReason::Conflict);
+        }
+      }
+    }
+  }
+
+  /// Remove a transaction and every mempool transaction spending its outputs,
+  /// directly or indirectly.
+  pub fn remove_with_descendants(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    reason: RemovalReason,
+  ) {
+    for hash in self.descendants(hash) {
+      self.remove(&hash, reason);
+    }
+  }
+
+  /// Return the hashes of every mempool transaction whose outputs the given
+  /// transaction spends, directly or indirectly.
+  pub fn ancestors(&self, txn: &Txn) -> HashSet<[u8; SHA256_HASH_SIZE]> {
+    let mut ancestors = HashSet::new();
+    let mut stack: Vec<_> = txn
+      .txi_list()
+      .iter()
+      .map(|txi| txi.prev_txn_hash())
+      .collect();
+    while let Some(hash) = stack.pop() {
+      if let Some(entry) = self.entries.get(&hash) {
+        if ancestors.insert(hash) {
+          stack
+            .extend(entry.txn.txi_list().iter().map(|txi| txi.prev_txn_hash()));
+        }
+      }
+    }
+    ancestors
+  }
+
+  /// Return the hashes of the mempool transaction with the given hash and
+  /// every mempool transaction spending its outputs, directly or indirectly,
+  /// each before its descendants.
+  pub fn descendants(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Vec<[u8; SHA256_HASH_SIZE]> {
+    // Each transaction is finished after every transaction spending its
+    // outputs, so the reverse of the order they finish in lists parents
+    // first, even where a descendant spends from several of the others.
+    let mut finished = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![(*hash, false)];
+    while let Some((hash, children_done)) = stack.pop() {
+      if children_done {
+        finished.push(hash);
+        continue;
+      }
+      let entry = match self.entries.get(&hash) {
+        Some(entry) if seen.insert(hash) => entry,
+        _ => continue,
+      };
+      stack.push((hash, true));
+      for index in 0..entry.txn.txo_list().len() {
+        if let Some(child) = self.spenders.get(&OutPoint::new(hash, index)) {
+          stack.push((*child, false));
+        }
+      }
+    }
+    finished.reverse();
+    finished
+  }
+
+  /// Recompute the ancestor and descendant packages of the given entries
+  /// from scratch.
+  fn recompute_packages(&mut self, hashes: &[[u8; SHA256_HASH_SIZE]]) {
+    for hash in hashes {
+      let entry = &self.entries[hash];
+      let mut ancestors = Package::of(entry);
+      for ancestor in self.ancestors(&entry.txn) {
+        ancestors.add(Package::of(&self.entries[&ancestor]));
+      }
+      let mut descendants = Package::default();
+      for descendant in self.descendants(hash) {
+        descendants.add(Package::of(&self.entries[&descendant]));
+      }
+      self.update_packages(hash, |entry| {
+        entry.ancestors = ancestors;
+        entry.descendants = descendants;
+      });
+    }
+  }
+
+  /// Remove a single transaction, taking it out of the packages of its
+  /// ancestors and descendants. Its descendants, if any, are left spending
+  /// outputs that are no longer in the mempool.
+  fn remove(&mut self, hash: &[u8; SHA256_HASH_SIZE], reason: RemovalReason) {
+    let descendants = self.descendants(hash);
+    let entry = match self.remove_entry(hash) {
+      Some(entry) => entry,
+      None => return,
+    };
+    for txi in entry.txn.txi_list() {
+      self.spenders.remove(&OutPoint::spent_by(txi));
+    }
+    self.size -= entry.size;
+    self.fee_estimator.untrack(hash);
+    for ancestor in self.ancestors(&entry.txn) {
+      self.update_packages(&ancestor, |ancestor| {
+        ancestor.descendants.sub(Package::of(&entry))
+      });
+    }
+    for descendant in &descendants[1..] {
+      self.update_packages(descendant, |descendant| {
+        descendant.ancestors.sub(Package::of(&entry))
+      });
+    }
+    if self.unannounced != Some(*hash) {
+      let txn = entry.txn;
+      self
+        .notify(|| ValidationEvent::TransactionRemovedFromMempool(txn, reason));
+    }
+  }
+
+  /// Add an entry, and its place in each index.
+  fn insert_entry(&mut self, entry: MempoolEntry) {
+    self
+      .by_ancestor_fee_rate
+      .insert(entry.key(&entry.ancestors));
+    self
+      .by_descendant_fee_rate
+      .insert(entry.key(&entry.descendants));
+    self.by_time.insert((entry.time, entry.hash));
+    self.entries.insert(entry.hash, entry);
+  }
+
+  /// Remove and return the entry with the given hash, and its place in each
+  /// index.
+  fn remove_entry(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Option<MempoolEntry> {
+    let entry = self.entries.remove(hash)?;
+    self
+      .by_ancestor_fee_rate
+      .remove(&entry.key(&entry.ancestors));
+    self
+      .by_descendant_fee_rate
+      .remove(&entry.key(&entry.descendants));
+    self.by_time.remove(&(entry.time, entry.hash));
+    Some(entry)
+  }
+
+  /// Change the packages of the entry with the given hash with `update`,
+  /// moving it to its new place in the fee rate indexes.
+  fn update_packages(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    update: impl FnOnce(&mut MempoolEntry),
+  ) {
+    let entry = self.entries.get_mut(hash).expect("Missing entry");
+    self
+      .by_ancestor_fee_rate
+      .remove(&entry.key(&entry.ancestors));
+    self
+      .by_descendant_fee_rate
+      .remove(&entry.key(&entry.descendants));
+    update(entry);
+    self
+      .by_ancestor_fee_rate
+      .insert(entry.key(&entry.ancestors));
+    self
+      .by_descendant_fee_rate
+      .insert(entry.key(&entry.descendants));
+  }
+
+  /// Get the entry of the transaction with the given hash.
+  pub fn get(&self, hash: &[u8; SHA256_HASH_SIZE]) -> Option<&MempoolEntry> {
+    self.entries.get(hash)
+  }
+
+  /// Return whether the transaction with the given hash is in the mempool.
+  pub fn contains(&self, hash: &[u8; SHA256_HASH_SIZE]) -> bool {
+    self.entries.contains_key(hash)
+  }
+
+  /// Return the hash of the mempool transaction spending the given outpoint.
+  pub fn spender(&self, outpoint: &OutPoint) -> Option<[u8; SHA256_HASH_SIZE]> {
+    self.spenders.get(outpoint).copied()
+  }
+
+  /// Return the number of transactions in the mempool.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Return the total size of the transactions in the mempool, in bytes.
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
+  /// Return the fee estimator, which learns from the transactions entering
+  /// and leaving the mempool.
+  pub fn fee_estimator(&self) -> &FeeEstimator {
+    &self.fee_estimator
+  }
+
+  /// Return the fee estimator, e.g. to load its history.
+  pub fn fee_estimator_mut(&mut self) -> &mut FeeEstimator {
+    &mut self.fee_estimator
+  }
+
+  /// Return whether the mempool is empty.
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Return an iterator over every entry, in no particular order.
+  pub fn entries(&self) -> impl Iterator<Item = &MempoolEntry> {
+    self.entries.values()
+  }
+
+  /// Return the transactions to include in a block, totalling at most
+  /// `max_size` bytes, each after the mempool transactions it spends from.
+  ///
+  /// Transactions are chosen by the fee rate of their package of ancestors
+  /// not chosen yet, so a child paying a high fee pulls in a parent paying
+  /// too little to be mined on its own.
+  pub fn block_txns(&self, max_size: usize) -> Vec<Txn> {
+    // The packages of unchosen ancestors of entries some of whose ancestors
+    // are chosen, also ordered by fee rate. Any other entry's package is its
+    // ancestor package, and its place is in `by_ancestor_fee_rate`.
+    let mut modified: HashMap<_, Package> = HashMap::new();
+    let mut by_modified_fee_rate = BTreeSet::new();
+    let mut unmodified = self.by_ancestor_fee_rate.iter().rev().peekable();
+    let mut chosen = HashSet::new();
+    let mut skipped = HashSet::new();
+    let mut txns = Vec::new();
+    let mut size = 0;
+    loop {
+      while let Some((_, _, hash)) = unmodified.peek() {
+        if !chosen.contains(hash)
+          && !skipped.contains(hash)
+          && !modified.contains_key(hash)
+        {
+          break;
+        }
+        unmodified.next();
+      }
+      let best = match (unmodified.peek(), by_modified_fee_rate.last()) {
+        (Some(key), Some(modified_key)) if modified_key > *key => {
+          by_modified_fee_rate.pop_last()
+        },
+        (Some(_), _) => unmodified.next().copied(),
+        (None, _) => by_modified_fee_rate.pop_last(),
+      };
+      let entry = match best {
+        Some((_, _, hash)) => &self.entries[&hash],
+        None => return txns,
+      };
+      let package = modified.remove(&entry.hash).unwrap_or(entry.ancestors);
+      if size + package.size > max_size {
+        skipped.insert(entry.hash);
+        continue;
+      }
+
+      // Add the package's transactions, parents first, and take each out of
+      // its unchosen descendants' packages.
+      let mut package: Vec<_> = self
+        .ancestors(&entry.txn)
+        .into_iter()
+        .filter(|ancestor| !chosen.contains(ancestor))
+        .map(|ancestor| &self.entries[&ancestor])
+        .chain([entry])
+        .collect();
+      package.sort_by_key(|entry| (entry.ancestors.count, entry.sequence));
+      for entry in &package {
+        chosen.insert(entry.hash);
+        if let Some(package) = modified.remove(&entry.hash) {
+          by_modified_fee_rate.remove(&entry.key(&package));
+        }
+      }
+      for entry in package {
+        for descendant in &self.descendants(&entry.hash)[1..] {
+          if chosen.contains(descendant) || skipped.contains(descendant) {
+            continue;
+          }
+          let descendant = &self.entries[descendant];
+          let package = modified
+            .entry(descendant.hash)
+            .or_insert(descendant.ancestors);
+          by_modified_fee_rate.remove(&descendant.key(package));
+          package.sub(Package::of(entry));
+          by_modified_fee_rate.insert(descendant.key(package));
+        }
+        size += entry.size;
+        txns.push((*entry.txn).clone());
+      }
+    }
+  }
+
+  /// Write every transaction in the mempool, with the time it entered, to
+  /// `MEMPOOL_DUMP_FILE` in `data_dir`, each after the mempool transactions
+  /// it spends from. Replace any earlier dump atomically.
+  pub fn dump(&self, data_dir: &Path) -> Result<(), Error> {
+    let mut entries: Vec<_> = self.entries.values().collect();
+    entries.sort_by_key(|entry| (entry.ancestors.count, entry.sequence));
+    let entries: Vec<_> = entries
+      .into_iter()
+      .map(|entry| (&*entry.txn, entry.time))
+      .collect();
+
+    let path = data_dir.join(MEMPOOL_DUMP_FILE);
+    let tmp_path = path.with_extension("tmp");
+    let mut tmp = BufWriter::new(File::create(&tmp_path)?);
+    bincode::serialize_into(&mut tmp, &MEMPOOL_DUMP_VERSION)?;
+    bincode::serialize_into(&mut tmp, &entries)?;
+    tmp.flush()?;
+    tmp.get_ref().sync_all()?;
+    fs::rename(&tmp_path, &path)?;
+    Ok(())
+  }
+
+  /// Read back the transactions written by `dump` to `data_dir`, if any, and
+  /// add each as having entered the mempool when it first did. Each is
+  /// validated against the chain and the mempool's limits anew at the given
+  /// time, and dropped if no longer valid or past expiry. Return the number
+  /// of transactions added.
+  pub fn load(
+    &mut self,
+    data_dir: &Path,
+    chain: &mut ActiveChain,
+    time: u32,
+  ) -> Result<usize, Error> {
+    let file = match File::open(data_dir.join(MEMPOOL_DUMP_FILE)) {
+      Ok(file) => file,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+      Err(err) => return Err(err.into()),
+    };
+    let mut reader = BufReader::new(file);
+    let version: u32 = bincode::deserialize_from(&mut reader)?;
+    if version != MEMPOOL_DUMP_VERSION {
+      return Err(Error::UnsupportedDumpVersion(version));
+    }
+    let entries: Vec<(Txn, u32)> = bincode::deserialize_from(&mut reader)?;
+
+    let mut loaded = 0;
+    for (txn, entry_time) in entries {
+      if time.saturating_sub(entry_time) > self.expiry {
+        continue;
+      }
+      let hash = txn.hash();
+      match self.accept_at(txn, chain, time, entry_time) {
+        Ok(_) => loaded += 1,
+        Err(err) => {
+          logln!("Dropped dumped mempool transaction {}: {}", hex(&hash), err)
+        },
+      }
+    }
+    Ok(loaded)
+  }
+
+  /// Publish the event made by `event`, if a handle to publish through is
+  /// set.
+  fn notify(&self, event: impl FnOnce() -> ValidationEvent) {
+    if let Some(events) = &self.events {
+      events.publish(event());
+    }
+  }
+}
+
+/// Return whether a coin is a coinbase output that may not be spent in the
+/// block after the one at `height`, being less than `COINBASE_MATURITY`
+/// blocks deep.
+fn is_immature(coin: &Coin, height: u32) -> bool {
+  coin.is_coinbase && height + 1 - coin.height < COINBASE_MATURITY
+}
+
+/// Return the fee rate, in nanoRBTC per 1000 bytes, of a transaction of the
+/// given size paying the given fee.
+pub fn fee_rate(fee: u64, size: usize) -> u64 {
+  (fee as u128 * 1000 / size.max(1) as u128) as u64
+}
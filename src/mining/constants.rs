@@ -3,11 +3,27 @@ use std::time::Duration;
 /// The number of blocks to process between difficulty adjustments.
 pub const CYCLE_BLOCK_LIMIT: u32 = 500;
 
-/// The target block time, in seconds.
-pub const TARGET_BLOCK_TIME: Duration = Duration::from_millis(2);
+/// The target block time, in seconds. Header timestamps have one-second
+/// resolution, so this must be a whole number of seconds.
+pub const TARGET_BLOCK_TIME: Duration = Duration::from_secs(2);
 
 /// The minimum factor by which to multiply the target per adjustment.
 pub const MIN_FACTOR: f64 = 0.25;
 
 /// The maximum factor by which to multiply the target per adjustment.
 pub const MAX_FACTOR: f64 = 4.00;
+
+/// The number of past blocks averaged by the LWMA difficulty algorithm.
+pub const LWMA_WINDOW: u32 = 45;
+
+/// The time over which the ASERT difficulty algorithm halves or doubles the
+/// target when blocks run ahead of or behind schedule.
+pub const ASERT_HALF_LIFE: Duration = Duration::from_secs(60 * 60);
+
+/// The maximum total size, in bytes, of the mempool transactions included in
+/// a mined block.
+pub const MAX_BLOCK_TXNS_SIZE: usize = 1_000_000;
+
+/// How long to wait before checking again whether the local chain has caught
+/// up with the best known header.
+pub const SYNC_WAIT_INTERVAL: Duration = Duration::from_millis(500);
@@ -0,0 +1,233 @@
+use ethnum::u256;
+
+use super::constants::{
+  ASERT_HALF_LIFE, CYCLE_BLOCK_LIMIT, LWMA_WINDOW, MAX_FACTOR, MIN_FACTOR,
+  TARGET_BLOCK_TIME,
+};
+use crate::util::{
+  constants::INITIAL_TARGET_BITS,
+  types::header::{bits_to_target, target_to_bits, Header},
+};
+
+/// A difficulty adjustment algorithm. Chain validation uses it to decide the
+/// `bits` a block must have, and the miner to decide the `bits` to mine at.
+pub trait DifficultyAlgorithm: Send + Sync {
+  /// Return the `bits` required of the block following the block at
+  /// `prev_height`.
+  ///
+  /// `header_at` must return the header at a given height, no greater than
+  /// `prev_height`, on the branch being extended.
+  fn next_bits<'a>(
+    &self,
+    prev_height: u32,
+    header_at: &dyn Fn(u32) -> &'a Header,
+  ) -> u32;
+}
+
+/// Epoch-based retargeting. The target only changes on the first block of each
+/// `window`-block epoch, scaled by how long the previous epoch took.
+#[derive(Clone, Debug)]
+pub struct Epoch {
+  /// The number of blocks between difficulty adjustments.
+  pub window: u32,
+
+  /// The target block time, in seconds.
+  pub target_block_time: u64,
+
+  /// The minimum factor by which to multiply the target per adjustment.
+  pub min_factor: f64,
+
+  /// The maximum factor by which to multiply the target per adjustment.
+  pub max_factor: f64,
+}
+
+impl Epoch {
+  /// Scale the target in `prev_bits` by (actual timespan / target timespan),
+  /// clamped to [`min_factor`, `max_factor`] and to the proof-of-work limit.
+  pub fn retarget(&self, prev_bits: u32, actual_timespan: u64) -> u32 {
+    let target_timespan = self.target_block_time * self.window as u64;
+    let timespan = actual_timespan.clamp(
+      (target_timespan as f64 * self.min_factor) as u64,
+      (target_timespan as f64 * self.max_factor) as u64,
+    );
+    scale_target(bits_to_target(prev_bits), timespan, target_timespan)
+  }
+}
+
+impl Default for Epoch {
+  fn default() -> Self {
+    Self {
+      window: CYCLE_BLOCK_LIMIT,
+      target_block_time: TARGET_BLOCK_TIME.as_secs(),
+      min_factor: MIN_FACTOR,
+      max_factor: MAX_FACTOR,
+    }
+  }
+}
+
+impl DifficultyAlgorithm for Epoch {
+  fn next_bits<'a>(
+    &self,
+    prev_height: u32,
+    header_at: &dyn Fn(u32) -> &'a Header,
+  ) -> u32 {
+    let prev = header_at(prev_height);
+    let height = prev_height + 1;
+    if !height.is_multiple_of(self.window) {
+      return prev.bits();
+    }
+
+    // Measure the time taken to mine the epoch that `prev` closes.
+    let first = header_at(height - self.window);
+    let actual_timespan = prev.timestamp().saturating_sub(first.timestamp());
+    self.retarget(prev.bits(), actual_timespan as u64)
+  }
+}
+
+/// Linearly-weighted moving average. Every block's target is the average
+/// target of the last `window` blocks, scaled by their solve times with recent
+/// solve times weighted more heavily.
+#[derive(Clone, Debug)]
+pub struct Lwma {
+  /// The number of past blocks to average over.
+  pub window: u32,
+
+  /// The target block time, in seconds.
+  pub target_block_time: u64,
+}
+
+impl Default for Lwma {
+  fn default() -> Self {
+    Self { window: LWMA_WINDOW, target_block_time: TARGET_BLOCK_TIME.as_secs() }
+  }
+}
+
+impl DifficultyAlgorithm for Lwma {
+  fn next_bits<'a>(
+    &self,
+    prev_height: u32,
+    header_at: &dyn Fn(u32) -> &'a Header,
+  ) -> u32 {
+    let (n, t) = (self.window as u64, self.target_block_time);
+    if prev_height < self.window {
+      return header_at(prev_height).bits();
+    }
+
+    // Sum solve times weighted by their position in the window, and average
+    // the window's targets. Timestamps are forced to increase and solve times
+    // are capped at six target block times so that a single bad timestamp
+    // cannot swing the result.
+    let first_height = prev_height - self.window;
+    let mut prev_timestamp = header_at(first_height).timestamp() as u64;
+    let mut weighted_solve_times = 0u64;
+    let mut avg_target = u256::ZERO;
+    for (weight, height) in (first_height + 1..=prev_height).enumerate() {
+      let header = header_at(height);
+      let timestamp = (header.timestamp() as u64).max(prev_timestamp + 1);
+      let solve_time = (timestamp - prev_timestamp).min(6 * t);
+      prev_timestamp = timestamp;
+
+      weighted_solve_times += (weight as u64 + 1) * solve_time;
+      avg_target += header.target() / u256::from(n);
+    }
+
+    // With weights summing to k, the target time of the weighted sum is k * t.
+    // Bound how quickly the target may fall after a burst of fast blocks.
+    let k = n * (n + 1) / 2;
+    scale_target(avg_target, weighted_solve_times.max(k * t / 10), k * t)
+  }
+}
+
+/// Absolutely-scheduled exponentially-rising targets. The target is the
+/// anchor block's target, doubled for every `half_life` seconds the chain runs
+/// behind its ideal schedule and halved for every `half_life` ahead of it.
+#[derive(Clone, Debug)]
+pub struct Asert {
+  /// The height of the block that the schedule is measured from.
+  pub anchor_height: u32,
+
+  /// The number of seconds behind schedule that doubles the target.
+  pub half_life: u64,
+
+  /// The target block time, in seconds.
+  pub target_block_time: u64,
+}
+
+impl Default for Asert {
+  fn default() -> Self {
+    Self {
+      anchor_height: 0,
+      half_life: ASERT_HALF_LIFE.as_secs(),
+      target_block_time: TARGET_BLOCK_TIME.as_secs(),
+    }
+  }
+}
+
+impl DifficultyAlgorithm for Asert {
+  fn next_bits<'a>(
+    &self,
+    prev_height: u32,
+    header_at: &dyn Fn(u32) -> &'a Header,
+  ) -> u32 {
+    if prev_height < self.anchor_height {
+      return header_at(prev_height).bits();
+    }
+    let anchor = header_at(self.anchor_height);
+    let prev = header_at(prev_height);
+
+    // Compute how far `prev` is behind schedule, in 16.16 fixed-point
+    // half-lives.
+    let time_delta = prev.timestamp() as i64 - anchor.timestamp() as i64;
+    let height_delta = (prev_height - self.anchor_height) as i64;
+    let schedule_delta =
+      time_delta - self.target_block_time as i64 * height_delta;
+    let exponent = (schedule_delta * 65536).div_euclid(self.half_life as i64);
+
+    // Split the exponent into whole halvings and a fraction, and approximate
+    // 2^frac with a cubic polynomial scaled by 2^16.
+    let shifts = exponent >> 16;
+    let frac = (exponent & 0xffff) as u128;
+    let factor = 65536
+      + ((195_766_423_245_049 * frac
+        + 971_821_376 * frac.pow(2)
+        + 5_127 * frac.pow(3)
+        + (1 << 47))
+        >> 48) as u64;
+
+    // Apply the whole halvings first so that the multiplication below cannot
+    // overflow for any target within the proof-of-work limit.
+    let target = anchor.target();
+    let target = if shifts < 0 {
+      target >> shifts.unsigned_abs().min(255) as u32
+    } else if target.leading_zeros() as i64 > shifts {
+      target << shifts as u32
+    } else {
+      pow_limit()
+    };
+    scale_target(target, factor, 65536)
+  }
+}
+
+/// Return `target * numerator / denominator` in `bits` form, kept between one
+/// and the proof-of-work limit.
+pub(crate) fn scale_target(
+  target: u256,
+  numerator: u64,
+  denominator: u64,
+) -> u32 {
+  let (numerator, denominator) =
+    (u256::from(numerator), u256::from(denominator));
+
+  // Divide first if multiplying first would overflow; the precision lost is
+  // far below what `bits` can represent anyway.
+  let scaled = match target.checked_mul(numerator) {
+    Some(product) => product / denominator,
+    None => (target / denominator).saturating_mul(numerator),
+  };
+  target_to_bits(scaled.clamp(u256::ONE, pow_limit()))
+}
+
+/// Return the proof-of-work limit, i.e. the easiest target a block may have.
+pub fn pow_limit() -> u256 {
+  bits_to_target(INITIAL_TARGET_BITS)
+}
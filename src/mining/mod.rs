@@ -1,3 +1,4 @@
 pub mod constants;
+pub mod difficulty;
 pub mod error;
 pub mod thread;
@@ -1,137 +1,185 @@
 use std::{
   process,
-  time::{Duration, Instant},
+  sync::{Arc, Mutex},
 };
 
-use async_std::channel::{Receiver, Sender, TryRecvError};
-use ethnum::u256;
+use async_std::{
+  channel::{Receiver, Sender, TryRecvError},
+  task,
+};
 
-use super::constants::CYCLE_BLOCK_LIMIT;
+use super::constants::{MAX_BLOCK_TXNS_SIZE, SYNC_WAIT_INTERVAL};
 use crate::{
-  mining::constants::{MAX_FACTOR, MIN_FACTOR, TARGET_BLOCK_TIME},
+  events::validation::{ValidationEvent, ValidationEvents},
+  mempool::pool::Mempool,
   util::{
     constants::SHA256_HASH_SIZE,
-    hashes::sha256,
-    types::{block::Block, txn::Txn},
+    shutdown::Shutdown,
+    time::TimeSource,
+    types::{
+      block::{merkle_root, Block},
+      chain::{ActiveChain, PushOutcome},
+      header::Header,
+    },
   },
 };
 
 /// # Mining thread
-/// Mines blocks. Before each hash attempt, check if thread has received an
-/// incoming transaction or block from networking thread.
+/// Mines blocks of the transactions in the mempool on the chain, both shared
+/// with the networking thread. Before each hash attempt, check if validation
+/// events report that the chain's tip has moved or the mempool has changed,
+/// and return once shutdown is requested.
 pub async fn start_mining(
-  blks_from_network: Receiver<Block>,
-  txns_from_network: Receiver<Txn>,
+  chain: Arc<Mutex<ActiveChain>>,
+  mempool: Arc<Mutex<Mempool>>,
+  time: Arc<Mutex<TimeSource>>,
+  events: ValidationEvents,
   blks_to_network: Sender<Block>,
-  txns_to_network: Sender<Txn>,
+  shutdown: Shutdown,
 ) {
-  // Initialize block counter, hash target, and mempool.
-  let mut block_count = 0;
-  let mut target = u256::MAX;
-
-  // Measure start time of first block in cycle.
-  let mut start_time = Instant::now();
+  let events = events.subscribe();
 
-  // Mine a block or update the local chain.
-  loop {
-    // Initialize previous block hash, mempool and nonce.
-    let mut _prev_block_hash = [0u8; SHA256_HASH_SIZE];
-    let mempool = Vec::<Txn>::new();
+  // Mine a block on the local chain's tip.
+  'mining: while !shutdown.is_requested() {
+    // Initialize nonce and a candidate block of the mempool's transactions
+    // extending the local chain. Its blocks and the chain parameters'
+    // difficulty algorithm determine the target.
     let mut nonce: u32 = 0;
-
-    // Concatenate mempool and nonce.
-    let mut mempool_and_nonce = [
-      bincode::serialize(&mempool).expect("Failed to serialize mempool"),
-      nonce.to_le_bytes().to_vec(),
-    ]
-    .concat();
+    // Wait while the chain catches up with headers already known to have
+    // more work, since any block mined meanwhile would be stale. Events until
+    // now are about tips and transactions no newer than those read below, and
+    // must be handled meanwhile so as not to hold up their delivery.
+    handle_events(&events, &chain, &mempool, &time, None);
+    if is_syncing(&chain) {
+      task::sleep(SYNC_WAIT_INTERVAL).await;
+      continue 'mining;
+    }
+    let txns = mempool
+      .lock()
+      .expect("Poisoned mempool lock")
+      .block_txns(MAX_BLOCK_TXNS_SIZE);
+    let (tip, header) = {
+      let chain = chain.lock().expect("Poisoned chain lock");
+      let tip = chain.last_block_hash();
+      let header = Header::new(
+        0,
+        tip,
+        merkle_root(&txns),
+        adjusted_time(&time).max(chain.median_time_past() + 1),
+        chain.next_bits(),
+        nonce,
+      );
+      (tip, header)
+    };
+    let mut block = Block::new(header, txns.len() as u32, txns);
 
     // Try hashes until hash meets target. Before each attmept, check for and
     // handle any incoming transactions or blocks.
-    while sha256(&mempool_and_nonce) > target.to_be_bytes() {
-      // Handle any incoming transactions.
-      match txns_from_network.try_recv() {
-        Ok(txn) => {
-          // TODO: Verify txn, then push to mempool and send to networking
-          // thread for re-broadcast.
-          println!("Verifying, pushing, and re-broadcasting txn: {:?}", txn);
-          txns_to_network
-            .send(txn)
-            .await
-            .expect("Failed to send transaction to networking thread");
-        },
-        Err(TryRecvError::Closed) => {
-          println!("Channel txns_from_network closed unexpectedly");
-          process::exit(1);
-        },
-        Err(TryRecvError::Empty) => {},
+    while block.verify_nonce().is_err() {
+      if shutdown.is_requested() {
+        return;
       }
 
-      // TODO: Handle any incoming blocks (reset hash_prev, prune mempool).
-      match blks_from_network.try_recv() {
-        Ok(blk) => {
-          println!("Handling incoming block: {:?}", blk);
-          // TODO:
-          // - Verify block before proceeding.
-          // - Prune mempool against incoming block's transactions.
-          _prev_block_hash = blk.hash();
-        },
-        Err(TryRecvError::Closed) => {
-          println!("Channel blks_from_network closed unexpectedly");
-          process::exit(1);
-        },
-        Err(TryRecvError::Empty) => {},
+      // Restart mining on the new tip once blocks from the network move the
+      // local chain, or with the new transactions once the mempool changes.
+      if handle_events(&events, &chain, &mempool, &time, Some(tip)) {
+        continue 'mining;
       }
 
-      // Increment nonce, then re-concatenate mempool and nonce.
+      // Increment nonce.
       nonce = nonce.checked_add(1).expect("Nonce overflowed");
-      mempool_and_nonce = [
-        bincode::serialize(&mempool).expect("Failed to serialize transaction"),
-        nonce.to_le_bytes().to_vec(),
-      ]
-      .concat();
+      block.set_nonce(nonce);
     }
 
-    // Create block and send to networking thread.
-    let block = Block::default();
-    blks_to_network
-      .send(block)
-      .await
-      .expect("Failed to send block to networking thread");
-
-    // TODO: Implement method for counting blocks since last target adjustment.
-
-    // Increment block counter.
-    block_count += 1;
-
-    // If counter has reached limit, adjust target and reset timer.
-    if block_count % CYCLE_BLOCK_LIMIT == 0 {
-      // Adjust target and reset cycle timer.
-      adjust_target(&mut target, start_time.elapsed());
-      start_time = Instant::now();
+    // Push block to the local chain and send to networking thread.
+    let result = chain
+      .lock()
+      .expect("Poisoned chain lock")
+      .validate_and_push(block.clone(), None, adjusted_time(&time));
+    match result {
+      Ok(PushOutcome::Connected(_)) => {},
+      Ok(_) => {
+        println!("Mined block was not connected to local chain");
+        continue;
+      },
+      Err(err) => {
+        println!("Failed to push mined block to local chain: {}", err);
+        continue;
+      },
+    }
+    // The networking thread stops receiving once it shuts down.
+    if blks_to_network.send(block).await.is_err() {
+      if shutdown.is_requested() {
+        return;
+      }
+      panic!("Failed to send block to networking thread");
     }
   }
 }
 
-fn adjust_target(target: &mut u256, elapsed: Duration) {
-  // Compute factor (elapsed time / target time), clamped to [0.25, 4].
-  let elapsed_time = elapsed.as_secs_f64();
-  let target_time = (TARGET_BLOCK_TIME * CYCLE_BLOCK_LIMIT).as_secs_f64();
-  let factor = (elapsed_time / target_time).clamp(MIN_FACTOR, MAX_FACTOR);
+/// Handle every validation event waiting on the given channel, removing the
+/// transactions of connected blocks from the mempool and returning those of
+/// disconnected blocks to it. Return whether the tip has moved from `tip` or
+/// the mempool has changed.
+fn handle_events(
+  events: &Receiver<ValidationEvent>,
+  chain: &Mutex<ActiveChain>,
+  mempool: &Mutex<Mempool>,
+  time: &Mutex<TimeSource>,
+  tip: Option<[u8; SHA256_HASH_SIZE]>,
+) -> bool {
+  let mut changed = false;
+  let mut disconnected = Vec::new();
+  loop {
+    match events.try_recv() {
+      Ok(ValidationEvent::BlockConnected { block, height }) => {
+        mempool
+          .lock()
+          .expect("Poisoned mempool lock")
+          .remove_for_block(&block, height);
+      },
+      Ok(ValidationEvent::BlockDisconnected { block, .. }) => {
+        disconnected.push(block);
+      },
+      Ok(ValidationEvent::TipUpdated { hash, .. }) => {
+        changed |= Some(hash) != tip;
+      },
+      Ok(
+        ValidationEvent::TransactionAddedToMempool(_)
+        | ValidationEvent::TransactionRemovedFromMempool(..),
+      ) => changed = true,
+      Err(TryRecvError::Empty) => break,
+      Err(TryRecvError::Closed) => {
+        println!("Validation event channel closed unexpectedly");
+        process::exit(1);
+      },
+    }
+  }
 
-  // Scale factor by 100, and target by 1/100.
-  // TODO: Check division implementation.
-  let scaled_factor = u256::from((factor * 100.0) as u64);
-  let scaled_target = *target / u256::from(100u64);
+  // The mempool is checked against the chain as it is now, which may be past
+  // the events handled, but never behind them.
+  if !disconnected.is_empty() {
+    let now = adjusted_time(time);
+    let mut chain = chain.lock().expect("Poisoned chain lock");
+    let result = mempool
+      .lock()
+      .expect("Poisoned mempool lock")
+      .update_for_disconnected(&disconnected, &mut chain, now);
+    if let Err(err) = result {
+      println!("Failed to update mempool for disconnected blocks: {}", err);
+    }
+  }
+  changed
+}
 
-  // Adjust target to (scaled_factor * scaled_target).
-  // TODO: Catch and handle overflow.
-  *target = scaled_factor * scaled_target;
+/// Return whether the chain has yet to download the blocks of headers with
+/// more work than its tip.
+fn is_syncing(chain: &Mutex<ActiveChain>) -> bool {
+  let chain = chain.lock().expect("Poisoned chain lock");
+  chain.tip().hash() != chain.best_header().hash()
+}
 
-  println!(
-    "Mined {} blocks in {:.3}s, adjusting target by {:+}%",
-    CYCLE_BLOCK_LIMIT,
-    elapsed_time,
-    scaled_factor.as_i16() - 100
-  );
+/// Return the current network-adjusted time.
+fn adjusted_time(time: &Mutex<TimeSource>) -> u32 {
+  time.lock().expect("Poisoned time lock").adjusted_time()
 }
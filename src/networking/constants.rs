@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+/// The maximum size of a serialized message, in bytes.
+pub const MAX_MSG_SIZE: usize = 32 << 20;
+
+/// The maximum number of headers sent in one `Headers` message. A full
+/// message means the sender may have more.
+pub const MAX_HEADERS_RESULTS: usize = 2000;
+
+/// The maximum number of new headers a peer may make us store on chains with
+/// less work than our active chain before it is disconnected. The count is
+/// kept across reconnects, and resets whenever the peer sends valid headers
+/// with more work than our active chain.
+pub const MAX_LOW_WORK_HEADERS: usize = 2 * MAX_HEADERS_RESULTS;
+
+/// The maximum number of blocks requested from one peer at a time.
+pub const MAX_BLOCKS_IN_FLIGHT_PER_PEER: usize = 16;
+
+/// The maximum number of blocks a `GetBlocks` message may request.
+pub const MAX_GET_BLOCKS: usize = 128;
+
+/// The maximum number of transactions a `GetTxns` message may request.
+pub const MAX_GET_TXNS: usize = 128;
+
+/// The maximum number of rejected transactions remembered, so that neither
+/// they nor orphans spending them are validated again, until the active
+/// chain's tip moves.
+pub const MAX_RECENT_REJECTS: usize = 10_000;
+
+/// How far ahead of the active chain, in blocks, blocks are downloaded.
+pub const BLOCK_DOWNLOAD_WINDOW: u32 = 1024;
+
+/// The number of seconds after which a block request is given up on and the
+/// block requested again, possibly from another peer.
+pub const BLOCK_DOWNLOAD_TIMEOUT: u32 = 60;
+
+/// The interval at which timed-out block requests are retried.
+pub const SYNC_TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The range of seconds to wait, chosen at random, before reconnecting to an
+/// outbound peer.
+pub const RECONNECT_DELAY_SECS: std::ops::RangeInclusive<u64> = 2..=10;
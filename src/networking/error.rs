@@ -5,6 +5,15 @@ use std::fmt::Display;
 pub enum Error {
   /// Wrapper type for `RecvError`.
   RecvError(async_std::channel::RecvError),
+
+  /// Wrapper type for `io::Error`.
+  IOError(std::io::Error),
+
+  /// Wrapper type for `bincode::Error`.
+  BincodeError(bincode::Error),
+
+  /// Indicates that a peer sent a message longer than `MAX_MSG_SIZE`.
+  MsgTooLarge(usize),
 }
 
 impl From<async_std::channel::RecvError> for Error {
@@ -13,10 +22,27 @@ impl From<async_std::channel::RecvError> for Error {
   }
 }
 
+impl From<std::io::Error> for Error {
+  fn from(err: std::io::Error) -> Self {
+    Self::IOError(err)
+  }
+}
+
+impl From<bincode::Error> for Error {
+  fn from(err: bincode::Error) -> Self {
+    Self::BincodeError(err)
+  }
+}
+
 impl Display for Error {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
       Error::RecvError(err) => write!(f, "{}", err),
+      Error::IOError(err) => write!(f, "{}", err),
+      Error::BincodeError(err) => write!(f, "{}", err),
+      Error::MsgTooLarge(size) => {
+        write!(f, "Message of {} bytes exceeds maximum size", size)
+      },
     }
   }
 }
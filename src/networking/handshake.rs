@@ -0,0 +1,18 @@
+use std::net::Ipv4Addr;
+
+use super::messages::Msg;
+use crate::util::time::TimeSource;
+
+/// Return the `Version` message this node sends to open a connection.
+pub fn version_msg(time: &TimeSource) -> Msg {
+  Msg::Version { timestamp: time.now() }
+}
+
+/// Handle a message received from `peer` while a connection is being opened.
+/// A `Version` message contributes the peer's clock to the network-adjusted
+/// time; any other message is ignored.
+pub fn handle_handshake_msg(time: &mut TimeSource, peer: Ipv4Addr, msg: &Msg) {
+  if let Msg::Version { timestamp } = msg {
+    time.add_sample(peer, *timestamp);
+  }
+}
@@ -1,41 +1,36 @@
-use std::{
-  io::Read,
-  net::{Ipv4Addr, SocketAddrV4, TcpListener},
-};
-
-use crate::{
-  log,
-  util::constants::{MSG_SIZE, RBTC_PORT},
-};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 
-/// Start listening for messages.
-/// # REWRITE
-pub fn start_inbound(local_ip_addr: &Ipv4Addr) -> std::io::Result<()> {
-  // Get local socket address from provided local IP address.
-  let local_socket_addr = SocketAddrV4::new(*local_ip_addr, RBTC_PORT);
+use async_std::{channel::Sender, net::TcpListener, task};
 
-  // Try binding a TCP listener to local address.
-  let listener = TcpListener::bind(local_socket_addr)?;
-
-  // Continuously handle incoming streams.
-  for stream_result in listener.incoming() {
-    // Log progress.
-    log!("\tEntered output loop");
+use super::{
+  peer::{run_peer, PeerSet},
+  sync::Event,
+};
+use crate::{logln, util::constants::RBTC_PORT};
 
-    // Extract stream and read to buffer.
-    let mut stream = stream_result?;
-    let mut buf = [0u8; MSG_SIZE];
-    stream.read_exact(&mut buf)?;
+/// Listen for connections from other nodes on `local_ip_addr`, and run each
+/// one as a peer connection.
+pub async fn start_inbound(
+  local_ip_addr: Ipv4Addr,
+  peers: PeerSet,
+  events: Sender<Event>,
+) -> std::io::Result<()> {
+  let listener =
+    TcpListener::bind(SocketAddrV4::new(local_ip_addr, RBTC_PORT)).await?;
+  logln!("Listening on {}", listener.local_addr()?);
 
-    // Print buffer.
-    log!(
-      "\tNode {} received message {:?} from node {}",
-      stream.local_addr()?.ip(),
-      buf,
-      stream.peer_addr()?.ip()
-    );
+  loop {
+    let (stream, addr) = listener.accept().await?;
+    match addr {
+      SocketAddr::V4(addr) => {
+        task::spawn(run_peer(
+          stream,
+          *addr.ip(),
+          peers.clone(),
+          events.clone(),
+        ));
+      },
+      SocketAddr::V6(addr) => logln!("Refusing IPv6 connection from {}", addr),
+    }
   }
-  log!("\tExited for loop, done listening for streams");
-
-  Ok(())
 }
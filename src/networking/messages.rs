@@ -2,6 +2,11 @@ use std::net::Ipv4Addr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::util::{
+  constants::SHA256_HASH_SIZE,
+  types::{block::Block, header::Header, txn::Txn},
+};
+
 /// A message that can be serialized and sent between RBTC nodes.
 /// # REWRITE
 #[derive(Debug, Deserialize, Serialize)]
@@ -14,4 +19,34 @@ pub enum Msg {
 
   /// Provides a list of known node IP addresses on the network.
   Addr(Vec<Ipv4Addr>),
+
+  /// Sent by each side when opening a connection. Carries the sender's clock,
+  /// used to compute the network-adjusted time.
+  Version { timestamp: u32 },
+
+  /// Requests the headers following the first hash in `locator` that is on
+  /// the receiver's active chain, up to and including `stop` (or as many as
+  /// fit in one `Headers` message if `stop` is all zeros).
+  GetHeaders {
+    locator: Vec<[u8; SHA256_HASH_SIZE]>,
+    stop: [u8; SHA256_HASH_SIZE],
+  },
+
+  /// Sent in response to `GetHeaders`, or to announce new blocks. Each header
+  /// is the child of the one before.
+  Headers(Vec<Header>),
+
+  /// Requests the blocks with the given hashes.
+  GetBlocks(Vec<[u8; SHA256_HASH_SIZE]>),
+
+  /// Sent in response to `GetBlocks`.
+  Block(Block),
+
+  /// Relays a transaction that entered the sender's mempool, or is sent in
+  /// response to `GetTxns`.
+  Txn(Txn),
+
+  /// Requests the mempool transactions with the given hashes, e.g. the
+  /// missing parents of an orphan transaction the receiver sent.
+  GetTxns(Vec<[u8; SHA256_HASH_SIZE]>),
 }
@@ -1,5 +1,9 @@
+pub mod constants;
 pub mod error;
+pub mod handshake;
 pub mod inbound;
 pub mod messages;
 pub mod outbound;
+pub mod peer;
+pub mod sync;
 pub mod thread;
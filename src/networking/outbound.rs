@@ -1,102 +1,102 @@
 use std::{
-  io::{stdin, Result, Write},
+  io::{Error, Result},
   net::{Ipv4Addr, SocketAddrV4, TcpStream},
-  process, thread,
+  thread,
   time::Duration,
 };
 
-use rand::{prelude::SliceRandom, thread_rng, Rng};
+use async_std::{
+  channel::{self, Sender},
+  task,
+};
+use rand::{thread_rng, Rng};
 use socket2::{Domain, Socket, Type};
 
+use super::{
+  constants::RECONNECT_DELAY_SECS,
+  peer::{run_peer, PeerSet},
+  sync::Event,
+};
 use crate::{
-  log,
-  util::constants::{BOOTSTRAP_IP_ADDRS, MSG_SIZE, RBTC_PORT, RBTC_PORT_RANGE},
+  logln,
+  util::constants::{
+    BOOTSTRAP_IP_ADDRS, CONNECT_TIMEOUT_SECS, RBTC_PORT, RBTC_PORT_RANGE,
+  },
 };
 
-/// Start sending messages on TCP streams.
-/// # REWRITE
-pub fn start_outbound(local_ip_addr: &Ipv4Addr) -> Result<()> {
-  // Initialize empty vector to hold streams.
-  let mut streams: Vec<TcpStream> = Vec::new();
+/// Keep a connection open to every bootstrap node other than this one.
+pub fn start_outbound(
+  local_ip_addr: Ipv4Addr,
+  peers: PeerSet,
+  events: Sender<Event>,
+) {
+  for peer in BOOTSTRAP_IP_ADDRS {
+    if peer != local_ip_addr {
+      task::spawn(maintain_connection(
+        local_ip_addr,
+        peer,
+        peers.clone(),
+        events.clone(),
+      ));
+    }
+  }
+}
 
-  // Keep trying to push streams from the bootstrap node IP addresses until at
-  // least one succeeds.
-  // TODO: This hangs indefinitely when trying to connect to a bootstrap node
-  // that doesn't yet exist. a) Make it time out, b) make it try several
-  // connections concurrently.
+/// Connect to `peer` and run the connection, reconnecting after a random
+/// delay whenever it is closed or fails to open, unless the peer has
+/// connected to us in the meantime.
+async fn maintain_connection(
+  local_ip_addr: Ipv4Addr,
+  peer: Ipv4Addr,
+  peers: PeerSet,
+  events: Sender<Event>,
+) {
   loop {
-    // DEBUG: Limit the search rate.
-    thread::sleep(Duration::from_secs_f64(1.0 / 60.0));
-    log!("Entered search loop");
-
-    // Choose a random bootstrap node from the bootstrap node IP address list.
-    let bootstrap_ip_addr = BOOTSTRAP_IP_ADDRS
-      .choose(&mut thread_rng())
-      .unwrap_or_else(|| {
-        log!("\tFailed to choose random bootstrap node for initial stream");
-        process::exit(1);
-      });
-    log!("Chose bootstrap IP address {}", bootstrap_ip_addr);
-
-    // Skip if this node finds its own address while iterating through the
-    // bootstrap node address list.
-    if bootstrap_ip_addr == local_ip_addr {
-      log!("Skipping own IP address");
-      continue;
+    if !peers
+      .lock()
+      .expect("Poisoned peer set lock")
+      .contains(&peer)
+    {
+      match connect_on_thread(local_ip_addr, peer).await {
+        Ok(stream) => {
+          logln!("Connected to {}", peer);
+          run_peer(stream.into(), peer, peers.clone(), events.clone()).await;
+        },
+        Err(err) => logln!("Failed to connect to {}: {}", peer, err),
+      }
     }
-
-    // Initialize local and peer socket addresses.
-    let local_socket_addr = SocketAddrV4::new(
-      *local_ip_addr,
-      thread_rng().gen_range(RBTC_PORT_RANGE),
-    );
-    let peer_socket_addr = SocketAddrV4::new(*bootstrap_ip_addr, RBTC_PORT);
-
-    // Initialize an empty socket, bind it to the local socket address, and
-    // connect it to the peer socket address.
-    let socket = Socket::new(Domain::IPV4, Type::STREAM, None)?;
-    socket.bind(&local_socket_addr.into())?;
-    socket.connect_timeout(&peer_socket_addr.into(), Duration::from_secs(1))?;
-
-    log!(
-      "\tConnected from {} to {}",
-      socket.local_addr()?.as_socket_ipv4().unwrap(),
-      socket.peer_addr()?.as_socket_ipv4().unwrap(),
-    );
-
-    // Convert the socket to a TcpStream and push it to the `streams` vector.
-    streams.push(socket.into());
-    break;
+    let delay = thread_rng().gen_range(RECONNECT_DELAY_SECS);
+    task::sleep(Duration::from_secs(delay)).await;
   }
+}
 
-  // DEBUG: At this point, the node has connected to one bootstrap node.
+/// Run `connect` on its own thread, so that a slow connection attempt does
+/// not hold up other tasks.
+async fn connect_on_thread(
+  local_ip_addr: Ipv4Addr,
+  peer: Ipv4Addr,
+) -> Result<TcpStream> {
+  let (result_sender, result) = channel::bounded(1);
+  thread::spawn(move || {
+    let _ = result_sender.try_send(connect(local_ip_addr, peer));
+  });
+  result
+    .recv()
+    .await
+    .unwrap_or_else(|_| Err(Error::other("connection thread panicked")))
+}
 
-  // Continuously get inputs from stdin and send them on each stream.
-  loop {
-    // Try reading input from stdin. If this fails, restart the input loop.
-    let mut buf_string = String::new();
-    log!("\tEntered input loop");
-    match stdin().read_line(&mut buf_string) {
-      Ok(_) => {
-        let buf_bytes = buf_string.trim_end().as_bytes();
-        if buf_bytes.len() != MSG_SIZE {
-          log!("\tExpected {} bytes, got {}", MSG_SIZE, buf_bytes.len());
-          continue;
-        }
-        match streams[0].write_all(buf_bytes) {
-          Ok(_) => match streams[0].flush() {
-            Ok(_) => log!("\tRead input, wrote stream, and flushed"),
-            Err(err) => log!(
-              "\tRead input and wrote to stream, but failed to flush, {}",
-              err
-            ),
-          },
-          Err(err) => {
-            log!("\tRead input but failed to write to stream, {}", err)
-          },
-        }
-      },
-      Err(err) => log!("\tFailed to read input to buffer, {}", err),
-    };
-  }
+/// Open a TCP connection from a random port on `local_ip_addr` to `peer`.
+fn connect(local_ip_addr: Ipv4Addr, peer: Ipv4Addr) -> Result<TcpStream> {
+  let local_socket_addr =
+    SocketAddrV4::new(local_ip_addr, thread_rng().gen_range(RBTC_PORT_RANGE));
+  let peer_socket_addr = SocketAddrV4::new(peer, RBTC_PORT);
+
+  let socket = Socket::new(Domain::IPV4, Type::STREAM, None)?;
+  socket.bind(&local_socket_addr.into())?;
+  socket.connect_timeout(
+    &peer_socket_addr.into(),
+    Duration::from_secs(CONNECT_TIMEOUT_SECS),
+  )?;
+  Ok(socket.into())
 }
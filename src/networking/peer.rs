@@ -0,0 +1,113 @@
+use std::{
+  collections::HashSet,
+  net::{Ipv4Addr, Shutdown},
+  sync::{Arc, Mutex},
+};
+
+use async_std::{
+  channel::{self, Receiver, Sender},
+  io::{ReadExt, WriteExt},
+  net::TcpStream,
+  task,
+};
+
+use super::{
+  constants::MAX_MSG_SIZE, error::Error, messages::Msg, sync::Event,
+};
+use crate::logln;
+
+/// The addresses of the peers this node has a connection to.
+pub type PeerSet = Arc<Mutex<HashSet<Ipv4Addr>>>;
+
+/// Run a connection to `peer` over `stream` until either side closes it.
+///
+/// The sync manager is sent a `Connected` event holding a channel for
+/// messages to the peer, then a `Message` event for each message received,
+/// then a `Disconnected` event. Dropping the channel closes the connection.
+/// A second connection to a peer already connected is closed at once.
+pub async fn run_peer(
+  stream: TcpStream,
+  peer: Ipv4Addr,
+  peers: PeerSet,
+  events: Sender<Event>,
+) {
+  if !peers.lock().expect("Poisoned peer set lock").insert(peer) {
+    let _ = stream.shutdown(Shutdown::Both);
+    return;
+  }
+
+  // Messages are written whole, so send each at once rather than waiting to
+  // fill a packet.
+  if let Err(err) = stream.set_nodelay(true) {
+    logln!("Failed to disable Nagle's algorithm for {}: {}", peer, err);
+  }
+
+  let (msgs_to_peer, msgs_from_sync) = channel::unbounded();
+  if events
+    .send(Event::Connected(peer, msgs_to_peer))
+    .await
+    .is_ok()
+  {
+    task::spawn(write_msgs(stream.clone(), msgs_from_sync));
+    let mut reader = stream.clone();
+    loop {
+      match read_msg(&mut reader).await {
+        Ok(msg) => {
+          if events.send(Event::Message(peer, msg)).await.is_err() {
+            break;
+          }
+        },
+        Err(err) => {
+          logln!("Closing connection to {}: {}", peer, err);
+          break;
+        },
+      }
+    }
+  }
+
+  // Report the disconnection before forgetting the peer, so that the sync
+  // manager never sees a new connection to it first.
+  let _ = stream.shutdown(Shutdown::Both);
+  let _ = events.send(Event::Disconnected(peer)).await;
+  peers.lock().expect("Poisoned peer set lock").remove(&peer);
+}
+
+/// Write each message received on `msgs` to `stream`, closing the stream once
+/// the channel closes or a write fails.
+async fn write_msgs(mut stream: TcpStream, msgs: Receiver<Msg>) {
+  while let Ok(msg) = msgs.recv().await {
+    if let Err(err) = write_msg(&mut stream, &msg).await {
+      logln!("Failed to send message: {}", err);
+      break;
+    }
+  }
+  let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Read a message from `stream`: its length as a little-endian `u32`, then
+/// the serialized message.
+pub async fn read_msg(stream: &mut TcpStream) -> Result<Msg, Error> {
+  let mut len = [0u8; 4];
+  stream.read_exact(&mut len).await?;
+  let len = u32::from_le_bytes(len) as usize;
+  if len > MAX_MSG_SIZE {
+    return Err(Error::MsgTooLarge(len));
+  }
+  let mut bytes = vec![0u8; len];
+  stream.read_exact(&mut bytes).await?;
+  Ok(bincode::deserialize(&bytes)?)
+}
+
+/// Write a message to `stream` in the form read by `read_msg`.
+pub async fn write_msg(stream: &mut TcpStream, msg: &Msg) -> Result<(), Error> {
+  let bytes = bincode::serialize(msg)?;
+  if bytes.len() > MAX_MSG_SIZE {
+    return Err(Error::MsgTooLarge(bytes.len()));
+  }
+  let mut frame = Vec::with_capacity(4 + bytes.len());
+  frame.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+  frame.extend_from_slice(&bytes);
+  stream.write_all(&frame).await?;
+  stream.flush().await?;
+  Ok(())
+}
@@ -0,0 +1,630 @@
+use std::{
+  collections::{HashMap, HashSet},
+  net::Ipv4Addr,
+  sync::{Arc, Mutex},
+};
+
+use async_std::channel::{Receiver, Sender};
+
+use super::{
+  constants::{
+    BLOCK_DOWNLOAD_TIMEOUT, BLOCK_DOWNLOAD_WINDOW,
+    MAX_BLOCKS_IN_FLIGHT_PER_PEER, MAX_GET_BLOCKS, MAX_GET_TXNS,
+    MAX_HEADERS_RESULTS, MAX_LOW_WORK_HEADERS, MAX_RECENT_REJECTS,
+  },
+  handshake::{handle_handshake_msg, version_msg},
+  messages::Msg,
+};
+use crate::{
+  events::{constants::MAX_PENDING_EVENTS, validation::ValidationEvents},
+  logln,
+  mempool::{self, orphans::OrphanTxnPool, pool::Mempool},
+  util::{
+    constants::SHA256_HASH_SIZE,
+    hashes::hex,
+    shutdown::Shutdown,
+    time::TimeSource,
+    types::{
+      block::Block,
+      chain::{self, ActiveChain, PushOutcome},
+      header::Header,
+      txn::Txn,
+    },
+  },
+};
+
+/// Something the sync manager must handle.
+#[derive(Debug)]
+pub enum Event {
+  /// A connection to a peer opened. Holds the channel for messages to it.
+  Connected(Ipv4Addr, Sender<Msg>),
+
+  /// A peer sent a message.
+  Message(Ipv4Addr, Msg),
+
+  /// A connection to a peer closed.
+  Disconnected(Ipv4Addr),
+
+  /// The miner found a block, which is already on the active chain.
+  MinedBlock(Block),
+
+  /// The chain is missing an ancestor of an orphan block received from the
+  /// given peer, if any.
+  BlockRequest(Option<Ipv4Addr>, [u8; SHA256_HASH_SIZE]),
+
+  /// Time passed; retry timed-out block requests.
+  Tick,
+}
+
+/// What the sync manager knows about a peer.
+#[derive(Debug)]
+struct PeerState {
+  /// The channel for messages to the peer.
+  sender: Sender<Msg>,
+
+  /// The header with the most work the peer has sent us.
+  best_header: Option<[u8; SHA256_HASH_SIZE]>,
+
+  /// The number of blocks requested from the peer and not yet received.
+  blocks_in_flight: usize,
+}
+
+/// Headers-first chain synchronization.
+///
+/// On connecting to a peer we ask for the headers after our best header, and
+/// keep asking while it sends full batches. Headers are validated and stored
+/// in the block index as they arrive, so we learn the chain with the most
+/// work before downloading any of it. The blocks along each peer's best
+/// chain are then requested from it a few at a time, in order, up to
+/// `BLOCK_DOWNLOAD_WINDOW` blocks ahead of the active chain, and the active
+/// chain moves forward as they arrive.
+///
+/// Peers serve the same requests from us, and announce new blocks by sending
+/// their headers.
+///
+/// Transactions spending outputs we do not know of are held as orphans, and
+/// their missing parents requested from the peer that sent them. Orphans are
+/// added to the mempool once their parents are. Transactions rejected since
+/// the tip last moved are remembered, and neither they nor transactions
+/// spending them or already spent outputs of confirmed transactions are held.
+pub struct SyncManager {
+  chain: Arc<Mutex<ActiveChain>>,
+  mempool: Arc<Mutex<Mempool>>,
+  time: Arc<Mutex<TimeSource>>,
+  validation_events: ValidationEvents,
+  peers: HashMap<Ipv4Addr, PeerState>,
+  in_flight: HashMap<[u8; SHA256_HASH_SIZE], (Ipv4Addr, u32)>,
+  low_work_headers: HashMap<Ipv4Addr, usize>,
+  orphan_txns: OrphanTxnPool,
+  recent_rejects: HashSet<[u8; SHA256_HASH_SIZE]>,
+  recent_rejects_tip: [u8; SHA256_HASH_SIZE],
+}
+
+impl SyncManager {
+  /// Initialize a sync manager for the given chain and mempool, with no
+  /// peers. `validation_events` is the handle the chain publishes changes
+  /// through.
+  pub fn new(
+    chain: Arc<Mutex<ActiveChain>>,
+    mempool: Arc<Mutex<Mempool>>,
+    time: Arc<Mutex<TimeSource>>,
+    validation_events: ValidationEvents,
+  ) -> Self {
+    Self {
+      chain,
+      mempool,
+      time,
+      validation_events,
+      peers: HashMap::new(),
+      in_flight: HashMap::new(),
+      low_work_headers: HashMap::new(),
+      orphan_txns: OrphanTxnPool::new(),
+      recent_rejects: HashSet::new(),
+      recent_rejects_tip: [0; SHA256_HASH_SIZE],
+    }
+  }
+
+  /// Handle events until the channel closes, or until shutdown is requested,
+  /// which is noticed on the next event, e.g. the periodic `Tick`.
+  /// Before each, wait for the validation events of earlier changes to the
+  /// chain to be delivered, so that slow subscribers hold up syncing rather
+  /// than fall ever further behind.
+  pub async fn run(mut self, events: Receiver<Event>, shutdown: Shutdown) {
+    while let Ok(event) = events.recv().await {
+      if shutdown.is_requested() {
+        break;
+      }
+      self.validation_events.sync(MAX_PENDING_EVENTS).await;
+      self.handle_event(event);
+    }
+  }
+
+  /// Handle one event.
+  pub fn handle_event(&mut self, event: Event) {
+    match event {
+      Event::Connected(peer, sender) => self.on_connected(peer, sender),
+      Event::Message(peer, msg) => self.on_msg(peer, msg),
+      Event::Disconnected(peer) => self.forget_peer(&peer),
+      Event::MinedBlock(block) => {
+        self.announce(block.header(), None);
+      },
+      Event::BlockRequest(peer, hash) => self.on_block_request(peer, hash),
+      Event::Tick => self.on_tick(),
+    }
+  }
+
+  /// Start syncing with a newly connected peer.
+  fn on_connected(&mut self, peer: Ipv4Addr, sender: Sender<Msg>) {
+    let state = PeerState { sender, best_header: None, blocks_in_flight: 0 };
+    self.peers.insert(peer, state);
+    let version = version_msg(&self.time.lock().expect("Poisoned time lock"));
+    self.send(&peer, version);
+    self.request_headers(&peer, None, [0u8; SHA256_HASH_SIZE]);
+  }
+
+  /// Handle a message from a peer.
+  fn on_msg(&mut self, peer: Ipv4Addr, msg: Msg) {
+    match msg {
+      Msg::Version { .. } => handle_handshake_msg(
+        &mut self.time.lock().expect("Poisoned time lock"),
+        peer,
+        &msg,
+      ),
+      Msg::Ping => self.send(&peer, Msg::Pong),
+      Msg::GetHeaders { locator, stop } => {
+        let headers =
+          self
+            .lock_chain()
+            .headers_after(&locator, &stop, MAX_HEADERS_RESULTS);
+        self.send(&peer, Msg::Headers(headers));
+      },
+      Msg::Headers(headers) => self.on_headers(peer, headers),
+      Msg::GetBlocks(hashes) => self.on_get_blocks(peer, hashes),
+      Msg::Block(block) => self.on_block(peer, block),
+      Msg::Txn(txn) => self.on_txn(peer, txn),
+      Msg::GetTxns(hashes) => self.on_get_txns(peer, hashes),
+      Msg::Pong | Msg::Addr(_) => {},
+    }
+  }
+
+  /// Validate and store headers from a peer, ask for more if it may have
+  /// them, and request the blocks they lead to.
+  fn on_headers(&mut self, peer: Ipv4Addr, headers: Vec<Header>) {
+    let last = match headers.last() {
+      Some(last) => last.hash(),
+      None => return,
+    };
+    if headers.len() > MAX_HEADERS_RESULTS {
+      self.disconnect(&peer, "sent too many headers");
+      return;
+    }
+    if !self.peers.contains_key(&peer) {
+      return;
+    }
+    let adjusted_time = self.adjusted_time();
+    let chain = Arc::clone(&self.chain);
+    let mut chain = chain.lock().expect("Poisoned chain lock");
+
+    // Headers that do not connect to ours announce a chain we are missing
+    // part of; ask for the headers leading up to them.
+    if !chain.index().contains(&headers[0].prev_block_hash()) {
+      drop(chain);
+      self.request_headers(&peer, None, [0u8; SHA256_HASH_SIZE]);
+      return;
+    }
+
+    // Headers cost work to make, but headers forking from far back in our
+    // chain can be made cheaply, so bound how many a peer can make us store
+    // before it shows us a chain with more work than ours. The count is kept
+    // by address, so that reconnecting does not reset it.
+    let low_work_headers = self
+      .low_work_headers
+      .get(&peer)
+      .copied()
+      .unwrap_or_default();
+    if low_work_headers > MAX_LOW_WORK_HEADERS {
+      drop(chain);
+      self.disconnect(&peer, "sent too many low-work headers");
+      return;
+    }
+    let new: Vec<_> = headers
+      .iter()
+      .map(|header| header.hash())
+      .filter(|hash| !chain.index().contains(hash))
+      .collect();
+    let result = chain.accept_headers(&headers, adjusted_time);
+
+    // Only headers that passed validation count towards the work the peer
+    // has shown us, as the rest may claim any work.
+    let index = chain.index();
+    let accepted = new.iter().filter(|hash| index.contains(hash)).count();
+    let last_valid = headers
+      .iter()
+      .rev()
+      .find_map(|header| index.get(&header.hash()));
+    let low_work = last_valid
+      .is_some_and(|entry| entry.chain_work() < chain.tip().chain_work());
+    if low_work {
+      let count = low_work_headers + accepted;
+      self.low_work_headers.insert(peer, count);
+      if count > MAX_LOW_WORK_HEADERS {
+        drop(chain);
+        self.disconnect(&peer, "sent too many low-work headers");
+        return;
+      }
+    } else if last_valid.is_some() {
+      self.low_work_headers.remove(&peer);
+    }
+
+    let state = match self.peers.get_mut(&peer) {
+      Some(state) => state,
+      None => return,
+    };
+    if let Some(entry) = last_valid {
+      let best_work = state
+        .best_header
+        .and_then(|hash| index.get(&hash))
+        .map_or(0.0, |best| best.chain_work());
+      if entry.chain_work() > best_work {
+        state.best_header = Some(entry.hash());
+      }
+    }
+    drop(chain);
+
+    match result {
+      Err(err) => {
+        self.disconnect(&peer, &format!("sent invalid headers: {}", err))
+      },
+      Ok(()) => {
+        if headers.len() == MAX_HEADERS_RESULTS {
+          self.request_headers(&peer, Some(last), [0u8; SHA256_HASH_SIZE]);
+        }
+        self.request_blocks(&peer);
+      },
+    }
+  }
+
+  /// Send a peer the blocks it asked for that we have.
+  fn on_get_blocks(
+    &mut self,
+    peer: Ipv4Addr,
+    hashes: Vec<[u8; SHA256_HASH_SIZE]>,
+  ) {
+    for hash in hashes.iter().take(MAX_GET_BLOCKS) {
+      let result = self.lock_chain().block(hash);
+      match result {
+        Ok(Some(block)) => self.send(&peer, Msg::Block(block)),
+        Ok(None) => {},
+        Err(err) => {
+          logln!("Cannot send block {} to {}: {}", hex(hash), peer, err)
+        },
+      }
+    }
+  }
+
+  /// Validate and store a block from a peer, and request more.
+  fn on_block(&mut self, peer: Ipv4Addr, block: Block) {
+    let hash = block.hash();
+    if let Some((from, _)) = self.in_flight.remove(&hash) {
+      if let Some(state) = self.peers.get_mut(&from) {
+        state.blocks_in_flight -= 1;
+      }
+    }
+
+    let adjusted_time = self.adjusted_time();
+    let result =
+      self
+        .lock_chain()
+        .validate_and_push(block, Some(peer), adjusted_time);
+    match result {
+      Ok(PushOutcome::Connected(_)) => {
+        // Once caught up with the best chain we know, pass new blocks on.
+        let chain = Arc::clone(&self.chain);
+        let chain = chain.lock().expect("Poisoned chain lock");
+        if chain.tip().hash() == chain.best_header().hash() {
+          let tip = chain.tip().header().clone();
+          drop(chain);
+          self.announce(&tip, Some(peer));
+        }
+      },
+      Ok(PushOutcome::Stored | PushOutcome::Orphaned)
+      | Err(chain::Error::DuplicateBlock) => {},
+      Err(err) => {
+        logln!("Rejected block {} from {}: {}", hex(&hash), peer, err)
+      },
+    }
+    self.request_blocks(&peer);
+  }
+
+  /// Validate a transaction from a peer and add it to the mempool, relaying
+  /// it to the other peers if accepted, along with any orphans it was the
+  /// last missing parent of. If it spends outputs we do not know of, hold it
+  /// as an orphan and ask the peer for the transactions creating them.
+  fn on_txn(&mut self, peer: Ipv4Addr, txn: Txn) {
+    let hash = txn.hash();
+    if self.orphan_txns.contains(&hash) || self.is_recently_rejected(&hash) {
+      return;
+    }
+    let mut accepted = Vec::new();
+    self.try_accept_txn(peer, txn, &mut accepted);
+    while let Some((hash, txo_count)) = accepted.pop() {
+      for orphan in self.orphan_txns.take_spenders(&hash, txo_count) {
+        self.try_accept_txn(orphan.peer, orphan.txn, &mut accepted);
+      }
+    }
+  }
+
+  /// Validate a transaction from a peer and add it to the mempool. If
+  /// accepted, relay it and push its hash and number of outputs to
+  /// `accepted`. If it spends outputs we do not know of, hold it as an
+  /// orphan.
+  fn try_accept_txn(
+    &mut self,
+    peer: Ipv4Addr,
+    txn: Txn,
+    accepted: &mut Vec<([u8; SHA256_HASH_SIZE], usize)>,
+  ) {
+    let hash = txn.hash();
+    let adjusted_time = self.adjusted_time();
+    let result = {
+      let mut chain = self.lock_chain();
+      self.mempool.lock().expect("Poisoned mempool lock").accept(
+        txn.clone(),
+        &mut chain,
+        adjusted_time,
+      )
+    };
+    match result {
+      Ok(_) => {
+        self.relay(&txn, Some(peer));
+        accepted.push((hash, txn.txo_list().len()));
+      },
+      Err(mempool::error::Error::AlreadyInMempool(_)) => {},
+      Err(mempool::error::Error::MissingInput(_)) => {
+        self.on_orphan_txn(peer, txn)
+      },
+      Err(
+        err @ (mempool::error::Error::ChainError(_)
+        | mempool::error::Error::StorageError(_)),
+      ) => {
+        logln!("Failed to validate transaction {}: {}", hex(&hash), err)
+      },
+      Err(err) => {
+        logln!("Rejected transaction {} from {}: {}", hex(&hash), peer, err);
+        self.reject_txn(hash);
+      },
+    }
+  }
+
+  /// Hold a transaction from a peer that spends outputs we do not know of as
+  /// an orphan, and ask the peer for the transactions creating them.
+  ///
+  /// The transaction is rejected instead if one of them was rejected too, or
+  /// is known from the transaction index to be confirmed, in which case the
+  /// outputs it is missing were already spent.
+  fn on_orphan_txn(&mut self, peer: Ipv4Addr, txn: Txn) {
+    let hash = txn.hash();
+    let result = {
+      let mut chain = self.lock_chain();
+      self
+        .mempool
+        .lock()
+        .expect("Poisoned mempool lock")
+        .missing_inputs(&txn, &mut chain)
+    };
+    let missing = match result {
+      Ok(missing) if !missing.is_empty() => missing,
+      Ok(_) => return,
+      Err(err) => {
+        logln!("Cannot hold orphan transaction from {}: {}", peer, err);
+        return;
+      },
+    };
+    let parents: HashSet<_> =
+      missing.iter().map(|outpoint| outpoint.txn_hash).collect();
+    let has_rejected_parent = parents
+      .iter()
+      .any(|parent| self.is_recently_rejected(parent));
+    let has_confirmed_parent = {
+      let chain = self.lock_chain();
+      parents
+        .iter()
+        .any(|parent| matches!(chain.is_confirmed(parent), Ok(true)))
+    };
+    if has_rejected_parent || has_confirmed_parent {
+      logln!(
+        "Rejected transaction {} from {}: spends a rejected transaction or \
+         spent outputs",
+        hex(&hash),
+        peer
+      );
+      self.reject_txn(hash);
+      return;
+    }
+    let now = self.time.lock().expect("Poisoned time lock").now();
+    self.orphan_txns.insert(txn, peer, missing, now);
+    self.send(&peer, Msg::GetTxns(parents.into_iter().collect()));
+  }
+
+  /// Send a peer the mempool transactions it asked for that we have.
+  fn on_get_txns(
+    &mut self,
+    peer: Ipv4Addr,
+    hashes: Vec<[u8; SHA256_HASH_SIZE]>,
+  ) {
+    let txns: Vec<_> = {
+      let mempool = self.mempool.lock().expect("Poisoned mempool lock");
+      hashes
+        .iter()
+        .take(MAX_GET_TXNS)
+        .filter_map(|hash| mempool.get(hash))
+        .map(|entry| (**entry.txn()).clone())
+        .collect()
+    };
+    for txn in txns {
+      self.send(&peer, Msg::Txn(txn));
+    }
+  }
+
+  /// Ask for the headers leading to a missing ancestor of an orphan block,
+  /// from the peer that sent the orphan or, if unknown, from every peer.
+  fn on_block_request(
+    &mut self,
+    peer: Option<Ipv4Addr>,
+    hash: [u8; SHA256_HASH_SIZE],
+  ) {
+    let peers: Vec<_> = match peer {
+      Some(peer) => vec![peer],
+      None => self.peers.keys().copied().collect(),
+    };
+    for peer in peers {
+      self.request_headers(&peer, None, hash);
+    }
+  }
+
+  /// Give up on block requests that have timed out, and request more blocks
+  /// from every peer.
+  fn on_tick(&mut self) {
+    let now = self.time.lock().expect("Poisoned time lock").now();
+    let peers = &mut self.peers;
+    self.in_flight.retain(|hash, (peer, requested)| {
+      if now.saturating_sub(*requested) < BLOCK_DOWNLOAD_TIMEOUT {
+        return true;
+      }
+      logln!("Block {} from {} timed out", hex(hash), peer);
+      if let Some(state) = peers.get_mut(peer) {
+        state.blocks_in_flight -= 1;
+      }
+      false
+    });
+
+    let peers: Vec<_> = self.peers.keys().copied().collect();
+    for peer in peers {
+      self.request_blocks(&peer);
+    }
+  }
+
+  /// Ask a peer for the headers after the indexed header `from`, or our best
+  /// header if `None`, up to `stop`.
+  fn request_headers(
+    &mut self,
+    peer: &Ipv4Addr,
+    from: Option<[u8; SHA256_HASH_SIZE]>,
+    stop: [u8; SHA256_HASH_SIZE],
+  ) {
+    let locator = {
+      let chain = self.lock_chain();
+      chain.locator(&from.unwrap_or_else(|| chain.best_header().hash()))
+    };
+    self.send(peer, Msg::GetHeaders { locator, stop });
+  }
+
+  /// Request the next blocks on a peer's best chain, if it has room for more
+  /// requests.
+  fn request_blocks(&mut self, peer: &Ipv4Addr) {
+    let (best_header, room) = match self.peers.get(peer) {
+      Some(PeerState { best_header: Some(best_header), .. }) => (
+        *best_header,
+        MAX_BLOCKS_IN_FLIGHT_PER_PEER - self.peers[peer].blocks_in_flight,
+      ),
+      _ => return,
+    };
+    if room == 0 {
+      return;
+    }
+
+    let hashes = self.lock_chain().blocks_to_download(
+      &best_header,
+      room,
+      BLOCK_DOWNLOAD_WINDOW,
+      &|hash| self.in_flight.contains_key(hash),
+    );
+    if hashes.is_empty() {
+      return;
+    }
+    let now = self.time.lock().expect("Poisoned time lock").now();
+    for hash in &hashes {
+      self.in_flight.insert(*hash, (*peer, now));
+    }
+    if let Some(state) = self.peers.get_mut(peer) {
+      state.blocks_in_flight += hashes.len();
+    }
+    self.send(peer, Msg::GetBlocks(hashes));
+  }
+
+  /// Announce a new block to every peer except `except`.
+  fn announce(&self, header: &Header, except: Option<Ipv4Addr>) {
+    for peer in self.peers.keys() {
+      if Some(*peer) != except {
+        self.send(peer, Msg::Headers(vec![header.clone()]));
+      }
+    }
+  }
+
+  /// Relay a transaction to every peer except `except`.
+  fn relay(&self, txn: &Txn, except: Option<Ipv4Addr>) {
+    for peer in self.peers.keys() {
+      if Some(*peer) != except {
+        self.send(peer, Msg::Txn(txn.clone()));
+      }
+    }
+  }
+
+  /// Send a message to a peer, if connected.
+  fn send(&self, peer: &Ipv4Addr, msg: Msg) {
+    if let Some(state) = self.peers.get(peer) {
+      // The channel is unbounded, so this fails only once it is closed.
+      let _ = state.sender.try_send(msg);
+    }
+  }
+
+  /// Close the connection to a misbehaving peer.
+  fn disconnect(&mut self, peer: &Ipv4Addr, reason: &str) {
+    logln!("Disconnecting {}: {}", peer, reason);
+    self.forget_peer(peer);
+  }
+
+  /// Forget a peer, closing its connection if still open, and release its
+  /// block requests to other peers.
+  fn forget_peer(&mut self, peer: &Ipv4Addr) {
+    self.peers.remove(peer);
+    self.in_flight.retain(|_, (from, _)| from != peer);
+    self.orphan_txns.remove_for_peer(peer);
+  }
+
+  /// Return whether the transaction with the given hash was rejected since
+  /// the chain's tip last moved. Rejections are forgotten once it moves, as
+  /// the transactions may be valid on the new tip.
+  fn is_recently_rejected(&mut self, hash: &[u8; SHA256_HASH_SIZE]) -> bool {
+    let tip = self.lock_chain().last_block_hash();
+    if tip != self.recent_rejects_tip {
+      self.recent_rejects.clear();
+      self.recent_rejects_tip = tip;
+    }
+    self.recent_rejects.contains(hash)
+  }
+
+  /// Remember that the transaction with the given hash was rejected,
+  /// forgetting every earlier rejection once `MAX_RECENT_REJECTS` are
+  /// remembered.
+  fn reject_txn(&mut self, hash: [u8; SHA256_HASH_SIZE]) {
+    if self.recent_rejects.len() >= MAX_RECENT_REJECTS {
+      self.recent_rejects.clear();
+    }
+    self.recent_rejects.insert(hash);
+  }
+
+  /// Lock the chain.
+  fn lock_chain(&self) -> std::sync::MutexGuard<'_, ActiveChain> {
+    self.chain.lock().expect("Poisoned chain lock")
+  }
+
+  /// Return the current network-adjusted time.
+  fn adjusted_time(&self) -> u32 {
+    self
+      .time
+      .lock()
+      .expect("Poisoned time lock")
+      .adjusted_time()
+  }
+}
@@ -1,93 +1,105 @@
-use std::{thread, time::Duration};
+use std::{
+  collections::HashSet,
+  net::Ipv4Addr,
+  sync::{Arc, Mutex},
+};
 
 use async_std::{
-  channel::{Receiver, Sender},
+  channel::{self, Receiver, Sender},
   task,
 };
 
-use super::error::Error;
-use crate::util::types::{block::Block, txn::Txn};
+use super::{
+  constants::SYNC_TICK_INTERVAL,
+  error::Error,
+  inbound::start_inbound,
+  outbound::start_outbound,
+  sync::{Event, SyncManager},
+};
+use crate::{
+  events::validation::ValidationEvents,
+  mempool::pool::Mempool,
+  util::{
+    constants::SHA256_HASH_SIZE,
+    shutdown::Shutdown,
+    time::TimeSource,
+    types::{block::Block, chain::ActiveChain},
+  },
+};
 
 /// # Networking thread
 /// Asynchronously handles the following tasks:
-/// - Forward incoming transactions and blocks from network to miner
-/// - Broadcast outgoing transactions and blocks from miner to network
+/// - Keep connections open to other nodes and sync the shared chain with them
+/// - Accept transactions from the network into the shared mempool, and relay
+///   them
+/// - Announce blocks from miner to network
+/// - Request blocks the chain is missing from the network
+///
+/// Returns once shutdown is requested, leaving peer connections to close
+/// with the process.
+#[allow(clippy::too_many_arguments)]
 pub async fn start_networking(
+  local_ip_addr: Ipv4Addr,
+  chain: Arc<Mutex<ActiveChain>>,
+  mempool: Arc<Mutex<Mempool>>,
+  time: Arc<Mutex<TimeSource>>,
+  validation_events: ValidationEvents,
   blks_from_miner: Receiver<Block>,
-  txns_from_miner: Receiver<Txn>,
-  blks_to_miner: Sender<Block>,
-  txns_to_miner: Sender<Txn>,
+  blk_requests: Receiver<(Option<Ipv4Addr>, [u8; SHA256_HASH_SIZE])>,
+  shutdown: Shutdown,
 ) -> Result<(), Error> {
+  // Every task reports to the sync manager through one channel.
+  let (events_to_sync, events) = channel::unbounded();
+  let peers = Arc::new(Mutex::new(HashSet::new()));
+  start_outbound(local_ip_addr, peers.clone(), events_to_sync.clone());
+  let inbound_handle =
+    task::spawn(start_inbound(local_ip_addr, peers, events_to_sync.clone()));
+
   // Spawn and await async tasks.
-  let broadcast_blks_handle = task::spawn(broadcast_blks(blks_from_miner));
-  let broadcast_txns_handle = task::spawn(broadcast_txns(txns_from_miner));
-  let forward_blks_handle = task::spawn(forward_blks(blks_to_miner));
-  let forward_txns_handle = task::spawn(forward_txns(txns_to_miner));
-  broadcast_blks_handle.await;
-  broadcast_txns_handle.await;
-  forward_blks_handle.await;
-  forward_txns_handle.await?;
+  task::spawn(broadcast_blks(blks_from_miner, events_to_sync.clone()));
+  task::spawn(request_blks(blk_requests, events_to_sync.clone()));
+  task::spawn(tick(events_to_sync));
+  SyncManager::new(chain, mempool, time, validation_events)
+    .run(events, shutdown)
+    .await;
+  if let Some(result) = inbound_handle.cancel().await {
+    result?;
+  }
   Ok(())
 }
 
-/// Receive blocks from the mining thread and broadcast to the network.
-async fn broadcast_blks(blks_from_miner: Receiver<Block>) {
-  loop {
-    // Broadcast outgoing blocks from miner to network.
-    match blks_from_miner.recv().await {
-      Ok(_) => println!("Broadcasting outgoing block from miner"),
-      Err(err) => {
-        println!("Failed to receive outgoing block from miner: {}", err)
-      },
+/// Receive blocks from the mining thread and announce them to the network.
+async fn broadcast_blks(
+  blks_from_miner: Receiver<Block>,
+  events: Sender<Event>,
+) {
+  while let Ok(blk) = blks_from_miner.recv().await {
+    if events.send(Event::MinedBlock(blk)).await.is_err() {
+      break;
     }
   }
 }
 
-/// Receive transactions from the mining thread and broadcast to the network.
-async fn broadcast_txns(txns_from_miner: Receiver<Txn>) {
-  loop {
-    // Broadcast outgoing transactions to network.
-    match txns_from_miner.recv().await {
-      Ok(_) => println!("Broadcasting outgoing transaction from miner"),
-      Err(err) => {
-        println!("Failed to receive outgoing transaction from miner: {}", err)
-      },
+/// Receive requests for the missing parents of orphan blocks and pass them to
+/// the sync manager, which asks the peer that sent the orphan, or every peer
+/// if unknown.
+async fn request_blks(
+  blk_requests: Receiver<(Option<Ipv4Addr>, [u8; SHA256_HASH_SIZE])>,
+  events: Sender<Event>,
+) {
+  while let Ok((peer, hash)) = blk_requests.recv().await {
+    if events.send(Event::BlockRequest(peer, hash)).await.is_err() {
+      break;
     }
   }
 }
 
-const DELAY_MEDIUM: Duration = Duration::from_secs(4);
-
-/// Receive blocks from the network and forward to the mining thread.
-async fn forward_blks(blks_to_miner: Sender<Block>) {
+/// Send the sync manager a `Tick` event every `SYNC_TICK_INTERVAL`.
+async fn tick(events: Sender<Event>) {
   loop {
-    // TODO: Await incoming block from network.
-    thread::sleep(DELAY_MEDIUM);
-    let blk = Block::default();
-
-    // Forward incoming block to miner.
-    match blks_to_miner.send(blk).await {
-      Ok(_) => {},
-      Err(err) => println!("Failed to send incoming block to miner: {}", err),
+    task::sleep(SYNC_TICK_INTERVAL).await;
+    if events.send(Event::Tick).await.is_err() {
+      break;
     }
   }
 }
-
-const DELAY_SHORT: Duration = Duration::from_millis(900);
-
-/// Receive transactions from the network and forward to the mining thread.
-async fn forward_txns(txns_to_miner: Sender<Txn>) -> Result<(), Error> {
-  loop {
-    // Await transaction from network.
-    thread::sleep(DELAY_SHORT);
-    let txn = Txn::new(0, 0, Vec::new(), 0, Vec::new());
-
-    // Forward transaction to miner.
-    match txns_to_miner.send(txn).await {
-      Ok(_) => {},
-      Err(err) => {
-        println!("Failed to forward incoming transaction to miner: {}", err)
-      },
-    };
-  }
-}
@@ -0,0 +1,354 @@
+use std::{
+  collections::{BTreeMap, HashSet},
+  fs::{self, File, OpenOptions},
+  io::{self, Read, Seek, SeekFrom, Write},
+  path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+  constants::{
+    BLOCKS_DIR, BLOCK_FILE_MAGIC, BLOCK_FILE_PREFIX, BLOCK_INDEX_DIR,
+    MAX_BLOCK_FILE_SIZE, UNDO_FILE_PREFIX,
+  },
+  error::Error,
+  kv::{KvStore, WriteBatch},
+};
+use crate::util::{
+  constants::SHA256_HASH_SIZE,
+  types::{block::Block, block_index::BlockIndexEntry, coin::BlockUndo},
+};
+
+/// Key prefix of block index entries, followed by the block hash.
+const KEY_BLOCK_INDEX: u8 = b'b';
+
+/// Key of the hash of the last block of the active chain.
+const KEY_BEST_BLOCK: u8 = b'B';
+
+/// Key prefix of block file information, followed by the big-endian file
+/// number.
+const KEY_FILE_INFO: u8 = b'f';
+
+/// Key present once any block file has been pruned.
+const KEY_PRUNED: u8 = b'P';
+
+/// The position of a record in the block files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BlockPos {
+  /// The number of the file holding the record.
+  pub file: u32,
+
+  /// The offset of the record's magic bytes in the file.
+  pub offset: u64,
+}
+
+/// What a block file and its undo file hold.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct BlockFileInfo {
+  /// The number of blocks in the block file.
+  pub blocks: u32,
+
+  /// The size of the block file, in bytes.
+  pub size: u64,
+
+  /// The size of the undo file, in bytes.
+  pub undo_size: u64,
+
+  /// The lowest height of any block in the file.
+  pub height_first: u32,
+
+  /// The highest height of any block in the file.
+  pub height_last: u32,
+}
+
+/// Block storage. Serialized blocks are appended to numbered flat files
+/// (`blk00000.dat`, `blk00001.dat`, ...) under the data directory's blocks
+/// directory, moving on to a new file once the current one reaches
+/// `MAX_BLOCK_FILE_SIZE`. Each block's undo data goes in the undo file
+/// (`rev00000.dat`, ...) numbered like the block's file. A key-value database
+/// alongside them indexes every known block by hash, recording its header,
+/// height, validation status and position in the files, plus the last block of
+/// the active chain and what each file holds.
+///
+/// Whole files of old blocks may be pruned to save space, after which their
+/// blocks' index entries no longer record a position.
+pub struct BlockStore {
+  dir: PathBuf,
+  index: KvStore,
+  files: BTreeMap<u32, BlockFileInfo>,
+  dirty_files: HashSet<u32>,
+  last_file: u32,
+  pruned: bool,
+}
+
+impl BlockStore {
+  /// Open the block store under the given data directory, creating it if
+  /// needed.
+  pub fn open(data_dir: &Path) -> Result<Self, Error> {
+    let dir = data_dir.join(BLOCKS_DIR);
+    fs::create_dir_all(&dir)?;
+    let index = KvStore::open(&dir.join(BLOCK_INDEX_DIR))?;
+
+    // Resume appending to the highest-numbered block file.
+    let mut last_file = 0;
+    for entry in fs::read_dir(&dir)? {
+      let name = entry?.file_name();
+      if let Some(number) =
+        parse_block_file_name(&name.to_string_lossy(), BLOCK_FILE_PREFIX)
+      {
+        last_file = last_file.max(number);
+      }
+    }
+
+    let mut files = BTreeMap::new();
+    for (key, value) in index.scan_prefix(&[KEY_FILE_INFO])? {
+      let number = match key[1..].try_into() {
+        Ok(bytes) => u32::from_be_bytes(bytes),
+        Err(_) => {
+          return Err(Error::Corrupt("malformed block file info key".into()))
+        },
+      };
+      files.insert(number, bincode::deserialize(&value)?);
+    }
+    let pruned = index.contains(&[KEY_PRUNED]);
+
+    Ok(Self {
+      dir,
+      index,
+      files,
+      dirty_files: HashSet::new(),
+      last_file,
+      pruned,
+    })
+  }
+
+  /// Append a block at the given height to the block files and return its
+  /// position. The block is on stable storage when this returns, so an index
+  /// entry written afterwards never points at missing data.
+  pub fn write_block(
+    &mut self,
+    block: &Block,
+    height: u32,
+  ) -> Result<BlockPos, Error> {
+    let bytes = bincode::serialize(block)?;
+    let record_size = record_size(&bytes);
+    let last_file_size = self.files.get(&self.last_file).map_or(0, |f| f.size);
+    if last_file_size > 0 && last_file_size + record_size > MAX_BLOCK_FILE_SIZE
+    {
+      self.last_file += 1;
+    }
+
+    let path = block_file_path(&self.dir, BLOCK_FILE_PREFIX, self.last_file);
+    let offset = append_record(&path, &bytes)?;
+    let info = self.files.entry(self.last_file).or_default();
+    if info.blocks == 0 || height < info.height_first {
+      info.height_first = height;
+    }
+    info.height_last = info.height_last.max(height);
+    info.blocks += 1;
+    info.size = offset + record_size;
+    self.dirty_files.insert(self.last_file);
+    Ok(BlockPos { file: self.last_file, offset })
+  }
+
+  /// Read the block at the given position.
+  pub fn read_block(&self, pos: BlockPos) -> Result<Block, Error> {
+    let path = block_file_path(&self.dir, BLOCK_FILE_PREFIX, pos.file);
+    Ok(bincode::deserialize(&read_record(&path, pos.offset)?)?)
+  }
+
+  /// Append a block's undo data to the undo file paired with block file
+  /// number `file`, i.e. the file holding the block, and return its
+  /// position.
+  pub fn write_undo(
+    &mut self,
+    file: u32,
+    undo: &BlockUndo,
+  ) -> Result<BlockPos, Error> {
+    let bytes = bincode::serialize(undo)?;
+    let path = block_file_path(&self.dir, UNDO_FILE_PREFIX, file);
+    let offset = append_record(&path, &bytes)?;
+    self.files.entry(file).or_default().undo_size =
+      offset + record_size(&bytes);
+    self.dirty_files.insert(file);
+    Ok(BlockPos { file, offset })
+  }
+
+  /// Read the undo data at the given position.
+  pub fn read_undo(&self, pos: BlockPos) -> Result<BlockUndo, Error> {
+    let path = block_file_path(&self.dir, UNDO_FILE_PREFIX, pos.file);
+    Ok(bincode::deserialize(&read_record(&path, pos.offset)?)?)
+  }
+
+  /// Write block index entries, and optionally a new last block of the active
+  /// chain, in one atomic batch along with any changed file information.
+  pub fn write_index(
+    &mut self,
+    entries: &[&BlockIndexEntry],
+    best_block: Option<[u8; SHA256_HASH_SIZE]>,
+  ) -> Result<(), Error> {
+    let mut batch = WriteBatch::new();
+    for entry in entries {
+      batch.put(&index_key(&entry.hash()), &bincode::serialize(entry)?);
+    }
+    if let Some(hash) = best_block {
+      batch.put(&[KEY_BEST_BLOCK], &hash);
+    }
+    for file in self.dirty_files.drain() {
+      batch.put(
+        &file_info_key(file),
+        &bincode::serialize(&self.files[&file])?,
+      );
+    }
+    self.index.write(batch, true)
+  }
+
+  /// Return the disk space used by block and undo files, in bytes.
+  pub fn disk_usage(&self) -> u64 {
+    self
+      .files
+      .values()
+      .map(|info| info.size + info.undo_size)
+      .sum()
+  }
+
+  /// Return the files, oldest first, that may be pruned because every block
+  /// in them is at or below `max_height`. The file being appended to is never
+  /// included.
+  pub fn prunable_files(&self, max_height: u32) -> Vec<(u32, BlockFileInfo)> {
+    self
+      .files
+      .iter()
+      .filter(|(&file, info)| {
+        file != self.last_file
+          && info.blocks > 0
+          && info.height_last <= max_height
+      })
+      .map(|(&file, info)| (file, *info))
+      .collect()
+  }
+
+  /// Delete the given block files and their undo files. The index entries of
+  /// the blocks they held, which must no longer record positions in them, are
+  /// written first, so an entry never points at deleted data.
+  pub fn prune_files(
+    &mut self,
+    files: &[u32],
+    entries: &[&BlockIndexEntry],
+  ) -> Result<(), Error> {
+    let mut batch = WriteBatch::new();
+    for entry in entries {
+      batch.put(&index_key(&entry.hash()), &bincode::serialize(entry)?);
+    }
+    for file in files {
+      batch.delete(&file_info_key(*file));
+    }
+    batch.put(&[KEY_PRUNED], &[]);
+    self.index.write(batch, true)?;
+    self.pruned = true;
+
+    for file in files {
+      self.files.remove(file);
+      self.dirty_files.remove(file);
+      for prefix in [BLOCK_FILE_PREFIX, UNDO_FILE_PREFIX] {
+        match fs::remove_file(block_file_path(&self.dir, prefix, *file)) {
+          Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            return Err(err.into())
+          },
+          _ => {},
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Return whether any block file has ever been pruned.
+  pub fn have_pruned(&self) -> bool {
+    self.pruned
+  }
+
+  /// Read every block index entry.
+  pub fn load_index(&self) -> Result<Vec<BlockIndexEntry>, Error> {
+    self
+      .index
+      .scan_prefix(&[KEY_BLOCK_INDEX])?
+      .into_iter()
+      .map(|(_, value)| Ok(bincode::deserialize(&value)?))
+      .collect()
+  }
+
+  /// Get the hash of the last block of the active chain, or `None` if the store
+  /// is new.
+  pub fn best_block(&self) -> Result<Option<[u8; SHA256_HASH_SIZE]>, Error> {
+    match self.index.get(&[KEY_BEST_BLOCK])? {
+      Some(value) => match value.try_into() {
+        Ok(hash) => Ok(Some(hash)),
+        Err(_) => Err(Error::Corrupt("malformed best block hash".into())),
+      },
+      None => Ok(None),
+    }
+  }
+}
+
+/// Return the block index key of the block with the given hash.
+fn index_key(hash: &[u8; SHA256_HASH_SIZE]) -> Vec<u8> {
+  [&[KEY_BLOCK_INDEX][..], hash].concat()
+}
+
+/// Return the block file information key of the given file.
+fn file_info_key(file: u32) -> Vec<u8> {
+  [&[KEY_FILE_INFO][..], &file.to_be_bytes()].concat()
+}
+
+/// Return the size of a record holding `bytes`.
+fn record_size(bytes: &[u8]) -> u64 {
+  (BLOCK_FILE_MAGIC.len() + 4 + bytes.len()) as u64
+}
+
+/// Append a record holding `bytes` to the file at `path`, and return the
+/// record's offset. The record is on stable storage when this returns.
+fn append_record(path: &Path, bytes: &[u8]) -> Result<u64, Error> {
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  let offset = file.metadata()?.len();
+  file.write_all(&BLOCK_FILE_MAGIC)?;
+  file.write_all(&(bytes.len() as u32).to_le_bytes())?;
+  file.write_all(bytes)?;
+  file.sync_data()?;
+  Ok(offset)
+}
+
+/// Read the bytes of the record at `offset` in the file at `path`.
+fn read_record(path: &Path, offset: u64) -> Result<Vec<u8>, Error> {
+  let mut file = File::open(path)?;
+  file.seek(SeekFrom::Start(offset))?;
+
+  let mut header = [0u8; 8];
+  file.read_exact(&mut header)?;
+  if header[..4] != BLOCK_FILE_MAGIC {
+    return Err(Error::Corrupt(format!(
+      "no record at offset {} of {}",
+      offset,
+      path.display()
+    )));
+  }
+  let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+  let mut bytes = vec![0u8; len as usize];
+  file.read_exact(&mut bytes)?;
+  Ok(bytes)
+}
+
+/// Return the path of the file with the given prefix and number.
+fn block_file_path(dir: &Path, prefix: &str, number: u32) -> PathBuf {
+  dir.join(format!("{}{:05}.dat", prefix, number))
+}
+
+/// Return the number of the file with the given name and prefix, if it is
+/// one.
+fn parse_block_file_name(name: &str, prefix: &str) -> Option<u32> {
+  name
+    .strip_prefix(prefix)?
+    .strip_suffix(".dat")?
+    .parse()
+    .ok()
+}
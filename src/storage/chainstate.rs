@@ -0,0 +1,226 @@
+use std::{collections::HashMap, mem, path::Path};
+
+use super::{
+  constants::{CHAINSTATE_DIR, MAX_FLUSH_BATCH_SIZE},
+  error::Error,
+  kv::{KvStore, WriteBatch},
+};
+use crate::util::{
+  constants::SHA256_HASH_SIZE,
+  types::coin::{Coin, OutPoint},
+};
+
+/// Key prefix of coins, followed by the outpoint's transaction hash and
+/// big-endian output index.
+const KEY_COIN: u8 = b'c';
+
+/// Key of the hash of the block the database's coins are consistent with.
+const KEY_BEST_BLOCK: u8 = b'B';
+
+/// Key present only while a flush is in progress, holding the hash of the
+/// block being flushed followed by the previous best block's hash, if any.
+const KEY_HEAD_BLOCKS: u8 = b'H';
+
+/// The approximate memory used by each cached coin.
+const CACHE_ENTRY_SIZE: usize =
+  mem::size_of::<(OutPoint, CacheEntry)>() + 2 * mem::size_of::<usize>();
+
+/// The blocks an interrupted flush was moving the database to and from.
+pub type HeadBlocks = ([u8; SHA256_HASH_SIZE], Option<[u8; SHA256_HASH_SIZE]>);
+
+/// A cached view of one outpoint.
+#[derive(Clone, Debug)]
+struct CacheEntry {
+  /// The coin, or `None` if spent or never created.
+  coin: Option<Coin>,
+
+  /// Whether the entry differs from the database.
+  dirty: bool,
+
+  /// Whether the database is known not to hold the outpoint, so that a coin
+  /// spent before the next flush never needs to be written at all.
+  fresh: bool,
+}
+
+/// The UTXO set: a key-value database of unspent coins under the data
+/// directory, fronted by a write-back cache.
+///
+/// Changes collect in the cache until it outgrows its configured size and is
+/// flushed. A flush marks the database with the blocks it is moving between
+/// before writing coins, possibly in several batches, and replaces the mark
+/// with the new best block hash only once every coin is written. A mark found
+/// on open therefore means a flush was interrupted, and `head_blocks` says
+/// which blocks to replay to repair it.
+pub struct Chainstate {
+  db: KvStore,
+  cache: HashMap<OutPoint, CacheEntry>,
+  cache_size: usize,
+  best_block: Option<[u8; SHA256_HASH_SIZE]>,
+}
+
+impl Chainstate {
+  /// Open the chainstate under the given data directory, creating it if
+  /// needed, with a cache of at most `cache_size` bytes.
+  pub fn open(data_dir: &Path, cache_size: usize) -> Result<Self, Error> {
+    let db = KvStore::open(&data_dir.join(CHAINSTATE_DIR))?;
+    let best_block = match db.get(&[KEY_BEST_BLOCK])? {
+      Some(value) => Some(to_hash(&value)?),
+      None => None,
+    };
+    Ok(Self { db, cache: HashMap::new(), cache_size, best_block })
+  }
+
+  /// Get the hash of the block the UTXO set is consistent with, or `None` if
+  /// no block has been applied.
+  pub fn best_block(&self) -> Option<[u8; SHA256_HASH_SIZE]> {
+    self.best_block
+  }
+
+  /// Set the hash of the block the UTXO set is consistent with.
+  pub fn set_best_block(&mut self, hash: Option<[u8; SHA256_HASH_SIZE]>) {
+    self.best_block = hash;
+  }
+
+  /// Get the blocks an interrupted flush was moving the database from and to,
+  /// as `(to, from)`, or `None` if the last flush completed.
+  pub fn head_blocks(&self) -> Result<Option<HeadBlocks>, Error> {
+    let value = match self.db.get(&[KEY_HEAD_BLOCKS])? {
+      Some(value) => value,
+      None => return Ok(None),
+    };
+    let (to, from) = value.split_at(SHA256_HASH_SIZE.min(value.len()));
+    let from = if from.is_empty() {
+      None
+    } else {
+      Some(to_hash(from)?)
+    };
+    Ok(Some((to_hash(to)?, from)))
+  }
+
+  /// Get the coin at the given outpoint, if unspent.
+  pub fn coin(&mut self, outpoint: &OutPoint) -> Result<Option<Coin>, Error> {
+    Ok(self.fetch(outpoint)?.coin.clone())
+  }
+
+  /// Return whether the given outpoint is unspent.
+  pub fn have_coin(&mut self, outpoint: &OutPoint) -> Result<bool, Error> {
+    Ok(self.fetch(outpoint)?.coin.is_some())
+  }
+
+  /// Add a coin at the given outpoint, replacing any coin already there.
+  pub fn add_coin(
+    &mut self,
+    outpoint: OutPoint,
+    coin: Coin,
+  ) -> Result<(), Error> {
+    let entry = self.fetch(&outpoint)?;
+    entry.coin = Some(coin);
+    entry.dirty = true;
+    Ok(())
+  }
+
+  /// Spend the coin at the given outpoint and return it, if it was unspent.
+  pub fn spend_coin(
+    &mut self,
+    outpoint: &OutPoint,
+  ) -> Result<Option<Coin>, Error> {
+    let entry = self.fetch(outpoint)?;
+    let coin = entry.coin.take();
+    if entry.fresh {
+      self.cache.remove(outpoint);
+    } else {
+      entry.dirty = true;
+    }
+    Ok(coin)
+  }
+
+  /// Return the approximate memory used by the cache, in bytes.
+  pub fn cache_usage(&self) -> usize {
+    self.cache.len() * CACHE_ENTRY_SIZE
+  }
+
+  /// Return whether the cache has outgrown its configured size.
+  pub fn needs_flush(&self) -> bool {
+    self.cache_usage() > self.cache_size
+  }
+
+  /// Write every change in the cache to the database, tagged with the current
+  /// best block, and empty the cache.
+  pub fn flush(&mut self) -> Result<(), Error> {
+    let best_block = match self.best_block {
+      Some(hash) => hash,
+      None => return Ok(()),
+    };
+    let db_best_block = match self.db.get(&[KEY_BEST_BLOCK])? {
+      Some(value) => Some(to_hash(&value)?),
+      None => None,
+    };
+    let dirty = self.cache.values().any(|entry| entry.dirty);
+    if !dirty && db_best_block == Some(best_block) {
+      self.cache.clear();
+      return Ok(());
+    }
+
+    // Mark the flush as in progress. If an earlier flush was interrupted, its
+    // mark still says where the database started from.
+    let from = match self.head_blocks()? {
+      Some((_, from)) => from,
+      None => db_best_block,
+    };
+    let mut head_blocks = best_block.to_vec();
+    head_blocks.extend(from.iter().flatten());
+    let mut batch = WriteBatch::new();
+    batch.put(&[KEY_HEAD_BLOCKS], &head_blocks);
+    self.db.write(batch, true)?;
+
+    // Write coins in bounded batches.
+    let mut batch = WriteBatch::new();
+    for (outpoint, entry) in self.cache.drain().filter(|(_, e)| e.dirty) {
+      match &entry.coin {
+        Some(coin) => {
+          batch.put(&coin_key(&outpoint), &bincode::serialize(coin)?)
+        },
+        None => batch.delete(&coin_key(&outpoint)),
+      }
+      if batch.len() >= MAX_FLUSH_BATCH_SIZE {
+        self.db.write(mem::take(&mut batch), false)?;
+      }
+    }
+
+    // Commit the new best block and clear the mark.
+    batch.put(&[KEY_BEST_BLOCK], &best_block);
+    batch.delete(&[KEY_HEAD_BLOCKS]);
+    self.db.write(batch, true)
+  }
+
+  /// Get the cache entry for an outpoint, reading it from the database on a
+  /// miss.
+  fn fetch(&mut self, outpoint: &OutPoint) -> Result<&mut CacheEntry, Error> {
+    if !self.cache.contains_key(outpoint) {
+      let coin = match self.db.get(&coin_key(outpoint))? {
+        Some(value) => Some(bincode::deserialize(&value)?),
+        None => None,
+      };
+      let fresh = coin.is_none();
+      self
+        .cache
+        .insert(*outpoint, CacheEntry { coin, dirty: false, fresh });
+    }
+    Ok(self.cache.get_mut(outpoint).expect("Cache entry vanished"))
+  }
+}
+
+/// Return the database key of the coin at the given outpoint.
+fn coin_key(outpoint: &OutPoint) -> Vec<u8> {
+  let mut key = vec![KEY_COIN];
+  key.extend_from_slice(&outpoint.txn_hash);
+  key.extend_from_slice(&(outpoint.index as u32).to_be_bytes());
+  key
+}
+
+/// Convert a database value to a block hash.
+fn to_hash(value: &[u8]) -> Result<[u8; SHA256_HASH_SIZE], Error> {
+  value
+    .try_into()
+    .map_err(|_| Error::Corrupt("malformed block hash in chainstate".into()))
+}
@@ -0,0 +1,39 @@
+/// The name of the directory, under the data directory, holding block files
+/// and the block index.
+pub const BLOCKS_DIR: &str = "blocks";
+
+/// The name of the directory, under the blocks directory, holding the block
+/// index database.
+pub const BLOCK_INDEX_DIR: &str = "index";
+
+/// The name of the directory, under the data directory, holding the
+/// chainstate database.
+pub const CHAINSTATE_DIR: &str = "chainstate";
+
+/// The maximum number of coins written per batch when flushing the UTXO cache.
+pub const MAX_FLUSH_BATCH_SIZE: usize = 10_000;
+
+/// The name of a key-value store's log file.
+pub const KV_LOG_FILE: &str = "data.log";
+
+/// The size, in bytes, beyond which a key-value store's log is compacted if
+/// most of it is overwritten or deleted data.
+pub const KV_COMPACT_THRESHOLD: u64 = 1 << 20;
+
+/// The payload size, in bytes, past which compacting a key-value store's log
+/// starts a new record, keeping each well below the largest a record's `u32`
+/// length can describe.
+pub const KV_COMPACT_RECORD_SIZE: usize = 64 << 20;
+
+/// The magic bytes preceding every record in a block file.
+pub const BLOCK_FILE_MAGIC: [u8; 4] = *b"rbtc";
+
+/// The file name prefix of block files.
+pub const BLOCK_FILE_PREFIX: &str = "blk";
+
+/// The file name prefix of undo files.
+pub const UNDO_FILE_PREFIX: &str = "rev";
+
+/// The maximum size of a block file, in bytes. Blocks are appended to a new
+/// file once the current one would exceed it.
+pub const MAX_BLOCK_FILE_SIZE: u64 = 16 << 20;
@@ -0,0 +1,56 @@
+use std::fmt::Display;
+
+/// Wrapper error types for the `storage` module.
+#[derive(Debug)]
+pub enum Error {
+  /// Wrapper type for `io::Error`.
+  IOError(std::io::Error),
+
+  /// Wrapper type for `bincode::Error`.
+  BincodeError(bincode::Error),
+
+  /// Indicates that data read back from disk is not what was written.
+  Corrupt(String),
+
+  /// Indicates that a batch is too large for a key-value store's log to hold
+  /// in one record.
+  BatchTooLarge(usize),
+
+  /// Indicates that a key-value store failed to remove a partly written batch
+  /// from its log, and refuses further writes until it is reopened.
+  Poisoned,
+}
+
+impl From<std::io::Error> for Error {
+  fn from(err: std::io::Error) -> Self {
+    Self::IOError(err)
+  }
+}
+
+impl From<bincode::Error> for Error {
+  fn from(err: bincode::Error) -> Self {
+    Self::BincodeError(err)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::IOError(err) => write!(f, "{}", err),
+      Error::BincodeError(err) => write!(f, "{}", err),
+      Error::Corrupt(what) => write!(f, "Corrupt storage: {}", what),
+      Error::BatchTooLarge(size) => {
+        write!(
+          f,
+          "Attempted to write a batch of {} bytes in one record",
+          size
+        )
+      },
+      Error::Poisoned => {
+        write!(f, "Attempted to write to a store torn by a failed write")
+      },
+    }
+  }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,331 @@
+use std::{
+  collections::BTreeMap,
+  fs::{self, File, OpenOptions},
+  io::{Read, Seek, SeekFrom, Write},
+  ops::Bound,
+  path::{Path, PathBuf},
+};
+
+use super::{
+  constants::{KV_COMPACT_RECORD_SIZE, KV_COMPACT_THRESHOLD, KV_LOG_FILE},
+  error::Error,
+};
+use crate::{logln, util::hashes::sha256};
+
+/// The size of a batch record's header: payload length and checksum.
+const RECORD_HEADER_SIZE: u64 = 8;
+
+/// A key and its value.
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
+/// A set of writes applied to a `KvStore` all at once, or not at all.
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+  ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+  /// Initialize an empty batch.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Set `key` to `value`.
+  pub fn put(&mut self, key: &[u8], value: &[u8]) {
+    self.ops.push((key.to_vec(), Some(value.to_vec())));
+  }
+
+  /// Delete `key`.
+  pub fn delete(&mut self, key: &[u8]) {
+    self.ops.push((key.to_vec(), None));
+  }
+
+  /// Return the number of writes in this batch.
+  pub fn len(&self) -> usize {
+    self.ops.len()
+  }
+
+  /// Return whether this batch is empty.
+  pub fn is_empty(&self) -> bool {
+    self.ops.is_empty()
+  }
+}
+
+/// Where a value lives in the log.
+#[derive(Clone, Copy, Debug)]
+struct ValuePos {
+  offset: u64,
+  len: u32,
+}
+
+/// A minimal embedded key-value store.
+///
+/// Writes are appended to a single log file as checksummed batch records, and
+/// an in-memory map from each live key to its latest value's position in the
+/// log is rebuilt by scanning the log on open. Values stay on disk. A batch
+/// torn by a crash fails its checksum and is discarded on the next open, so
+/// every batch is atomic. A batch that fails to write is cut off the log
+/// straight away, and the store refuses further writes if that fails too. The
+/// log is rewritten with only live values once most of it is dead.
+pub struct KvStore {
+  path: PathBuf,
+  file: File,
+  keys: BTreeMap<Vec<u8>, ValuePos>,
+  log_size: u64,
+  live_size: u64,
+  poisoned: bool,
+}
+
+impl KvStore {
+  /// Open the store in the given directory, creating it if needed.
+  pub fn open(dir: &Path) -> Result<Self, Error> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(KV_LOG_FILE);
+    let file = open_log(&path)?;
+    Self::load(path, file)
+  }
+
+  /// Initialize a store from the log at `path`, open as `file`.
+  fn load(path: PathBuf, mut file: File) -> Result<Self, Error> {
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut bytes)?;
+    let mut store = Self {
+      path,
+      file,
+      keys: BTreeMap::new(),
+      log_size: 0,
+      live_size: 0,
+      poisoned: false,
+    };
+    let valid_size = store.replay(&bytes);
+    if valid_size < bytes.len() as u64 {
+      // Drop the torn tail left by a crash mid-write.
+      store.file.set_len(valid_size)?;
+    }
+    store.log_size = valid_size;
+    Ok(store)
+  }
+
+  /// Get the value of `key`.
+  pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    match self.keys.get(key) {
+      Some(pos) => Ok(Some(self.read_value(*pos)?)),
+      None => Ok(None),
+    }
+  }
+
+  /// Return whether `key` has a value.
+  pub fn contains(&self, key: &[u8]) -> bool {
+    self.keys.contains_key(key)
+  }
+
+  /// Return every key starting with `prefix`, in order.
+  pub fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+    self
+      .keys
+      .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+      .take_while(|(key, _)| key.starts_with(prefix))
+      .map(|(key, _)| key.clone())
+      .collect()
+  }
+
+  /// Return every key-value pair whose key starts with `prefix`, in order.
+  pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<KeyValue>, Error> {
+    self
+      .keys_with_prefix(prefix)
+      .into_iter()
+      .map(|key| {
+        let value = self.read_value(self.keys[&key])?;
+        Ok((key, value))
+      })
+      .collect()
+  }
+
+  /// Apply a batch of writes atomically. If `sync` is set, the batch is on
+  /// stable storage when this returns.
+  ///
+  /// Returns `BatchTooLarge` if the batch does not fit in one record, and
+  /// `Poisoned` if an earlier failed write could not be undone.
+  pub fn write(&mut self, batch: WriteBatch, sync: bool) -> Result<(), Error> {
+    if self.poisoned {
+      return Err(Error::Poisoned);
+    }
+    if batch.is_empty() {
+      return Ok(());
+    }
+    let record = encode_record(&batch.ops)?;
+    if let Err(err) = self.append(&record, sync) {
+      // Cut off whatever part of the record was written, so that later
+      // batches are not appended after it and lost with it on the next open.
+      if self.file.set_len(self.log_size).is_err() {
+        self.poisoned = true;
+      }
+      return Err(err.into());
+    }
+    let start = self.log_size;
+    self.replay_payload(start, &record[RECORD_HEADER_SIZE as usize..]);
+    self.log_size += record.len() as u64;
+
+    // The batch is written either way, so a failed compaction is only
+    // retried on a later write.
+    if self.log_size > KV_COMPACT_THRESHOLD
+      && self.log_size > 2 * self.live_size
+    {
+      if let Err(err) = self.compact() {
+        logln!("Failed to compact {}: {}", self.path.display(), err);
+      }
+    }
+    Ok(())
+  }
+
+  /// Rewrite the log with only live values, replacing the old log atomically.
+  /// The values are written in records of about `KV_COMPACT_RECORD_SIZE`
+  /// bytes each. The store keeps using the old log unless the new one is
+  /// written and read back.
+  pub fn compact(&mut self) -> Result<(), Error> {
+    if self.poisoned {
+      return Err(Error::Poisoned);
+    }
+    let tmp_path = self.path.with_extension("tmp");
+    if tmp_path.exists() {
+      fs::remove_file(&tmp_path)?;
+    }
+    let mut tmp = open_log(&tmp_path)?;
+    let mut ops = Vec::new();
+    let mut ops_size = 0;
+    for (key, pos) in &self.keys {
+      ops.push((key.clone(), Some(self.read_value(*pos)?)));
+      ops_size += key.len() + pos.len as usize;
+      if ops_size >= KV_COMPACT_RECORD_SIZE {
+        tmp.write_all(&encode_record(&ops)?)?;
+        ops.clear();
+        ops_size = 0;
+      }
+    }
+    if !ops.is_empty() {
+      tmp.write_all(&encode_record(&ops)?)?;
+    }
+    tmp.sync_all()?;
+    let compacted = Self::load(self.path.clone(), tmp)?;
+    fs::rename(&tmp_path, &self.path)?;
+    *self = compacted;
+
+    // Make the rename itself durable.
+    let dir = self.path.parent().expect("Log file has no directory");
+    File::open(dir)?.sync_all()?;
+    Ok(())
+  }
+
+  /// Append a record to the log, syncing it if `sync` is set.
+  fn append(&mut self, record: &[u8], sync: bool) -> std::io::Result<()> {
+    self.file.write_all(record)?;
+    if sync {
+      self.file.sync_data()?;
+    }
+    Ok(())
+  }
+
+  /// Apply every intact record in `bytes` to the key map and return the
+  /// length of the intact prefix.
+  fn replay(&mut self, bytes: &[u8]) -> u64 {
+    let mut offset = 0u64;
+    loop {
+      let rest = &bytes[offset as usize..];
+      if rest.len() < RECORD_HEADER_SIZE as usize {
+        return offset;
+      }
+      let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+      let end = RECORD_HEADER_SIZE as usize + len;
+      if rest.len() < end {
+        return offset;
+      }
+      let payload = &rest[RECORD_HEADER_SIZE as usize..end];
+      if sha256(payload)[..4] != rest[4..8] {
+        return offset;
+      }
+      self.replay_payload(offset, payload);
+      offset += end as u64;
+    }
+  }
+
+  /// Apply the writes in a record's payload, where the record starts at
+  /// `record_offset` in the log.
+  fn replay_payload(&mut self, record_offset: u64, payload: &[u8]) {
+    let base = record_offset + RECORD_HEADER_SIZE;
+    let mut cursor = 0usize;
+    let read_u32 = |cursor: &mut usize| {
+      let value =
+        u32::from_le_bytes(payload[*cursor..*cursor + 4].try_into().unwrap());
+      *cursor += 4;
+      value as usize
+    };
+    while cursor < payload.len() {
+      let key_len = read_u32(&mut cursor);
+      let key = payload[cursor..cursor + key_len].to_vec();
+      cursor += key_len;
+      let has_value = payload[cursor] == 1;
+      cursor += 1;
+
+      if let Some(old) = self.keys.remove(&key) {
+        self.live_size -= old.len as u64 + key.len() as u64;
+      }
+      if has_value {
+        let value_len = read_u32(&mut cursor);
+        let pos =
+          ValuePos { offset: base + cursor as u64, len: value_len as u32 };
+        cursor += value_len;
+        self.live_size += value_len as u64 + key.len() as u64;
+        self.keys.insert(key, pos);
+      }
+    }
+  }
+
+  /// Read a value from the log.
+  fn read_value(&self, pos: ValuePos) -> Result<Vec<u8>, Error> {
+    let mut file = &self.file;
+    let mut value = vec![0u8; pos.len as usize];
+    file.seek(SeekFrom::Start(pos.offset))?;
+    file.read_exact(&mut value)?;
+    Ok(value)
+  }
+}
+
+/// Open the log file at `path` for reading and appending, creating it if
+/// needed.
+fn open_log(path: &Path) -> std::io::Result<File> {
+  OpenOptions::new()
+    .read(true)
+    .append(true)
+    .create(true)
+    .open(path)
+}
+
+/// Encode a batch as a log record: payload length, the first four bytes of the
+/// payload's SHA-256 hash, then the payload. Each write in the payload is the
+/// key's length and bytes, a byte that is one for a put and zero for a
+/// delete, and for a put the value's length and bytes.
+///
+/// Returns `BatchTooLarge` if the payload's length does not fit in a `u32`.
+fn encode_record(ops: &[(Vec<u8>, Option<Vec<u8>>)]) -> Result<Vec<u8>, Error> {
+  let mut payload = Vec::new();
+  for (key, value) in ops {
+    payload.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    payload.extend_from_slice(key);
+    match value {
+      Some(value) => {
+        payload.push(1);
+        payload.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        payload.extend_from_slice(value);
+      },
+      None => payload.push(0),
+    }
+  }
+  let len = u32::try_from(payload.len())
+    .map_err(|_| Error::BatchTooLarge(payload.len()))?;
+  let mut record = Vec::with_capacity(payload.len() + 8);
+  record.extend_from_slice(&len.to_le_bytes());
+  record.extend_from_slice(&sha256(&payload)[..4]);
+  record.extend_from_slice(&payload);
+  Ok(record)
+}
@@ -0,0 +1,5 @@
+pub mod blockstore;
+pub mod chainstate;
+pub mod constants;
+pub mod error;
+pub mod kv;
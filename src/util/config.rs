@@ -0,0 +1,65 @@
+use std::{net::Ipv4Addr, path::PathBuf};
+
+use super::constants::{
+  DEFAULT_COINS_CACHE_SIZE, DEFAULT_DATA_DIR, DEFAULT_MEMPOOL_EXPIRY,
+  DEFAULT_MEMPOOL_MAX_SIZE,
+};
+
+/// Settings local to this node, as opposed to the consensus rules in
+/// `ChainParams` that every node on a network shares.
+#[derive(Clone, Debug)]
+pub struct Config {
+  /// The directory to store node data in.
+  pub data_dir: PathBuf,
+
+  /// The memory, in bytes, the UTXO cache may use before it is flushed to the
+  /// chainstate database.
+  pub coins_cache_size: usize,
+
+  /// The disk space, in bytes, that block and undo files may use before old
+  /// ones are pruned, or `None` to keep every block.
+  pub prune_target: Option<u64>,
+
+  /// Whether to keep an index of the blocks confirming each transaction.
+  pub txindex: bool,
+
+  /// Whether to keep an index of the outputs paying each address and their
+  /// spends.
+  pub addrindex: bool,
+
+  /// Whether to keep an index of compact block filters and their headers.
+  pub blockfilterindex: bool,
+
+  /// The total size, in bytes, of the transactions the mempool may hold
+  /// before evicting those paying the least.
+  pub mempool_max_size: usize,
+
+  /// How long, in seconds, a transaction may stay in the mempool before it
+  /// is dropped.
+  pub mempool_expiry: u32,
+
+  /// Whether mempool transactions may be replaced by conflicting ones paying
+  /// higher fees even if they do not opt in to replacement.
+  pub mempool_full_rbf: bool,
+
+  /// The address to listen for connections from other nodes on, and to make
+  /// connections to them from.
+  pub listen_addr: Ipv4Addr,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+      coins_cache_size: DEFAULT_COINS_CACHE_SIZE,
+      prune_target: None,
+      txindex: false,
+      addrindex: false,
+      blockfilterindex: false,
+      mempool_max_size: DEFAULT_MEMPOOL_MAX_SIZE,
+      mempool_expiry: DEFAULT_MEMPOOL_EXPIRY,
+      mempool_full_rbf: false,
+      listen_addr: Ipv4Addr::LOCALHOST,
+    }
+  }
+}
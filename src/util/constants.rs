@@ -11,6 +11,46 @@ pub const NANO_FROM_KILO: f64 = 1e12;
 pub const NANO_FROM_MEGA: f64 = 1e15;
 pub const NANO_FROM_GIGA: f64 = 1e18;
 
+/// The directory to store node data in when none is given.
+pub const DEFAULT_DATA_DIR: &str = "data";
+
+/// The memory, in bytes, the UTXO cache may use when not configured.
+pub const DEFAULT_COINS_CACHE_SIZE: usize = 32 << 20;
+
+/// The total size, in bytes, of the transactions the mempool may hold when
+/// not configured.
+pub const DEFAULT_MEMPOOL_MAX_SIZE: usize = 300 << 20;
+
+/// How long, in seconds, a transaction may stay in the mempool when not
+/// configured.
+pub const DEFAULT_MEMPOOL_EXPIRY: u32 = 14 * 24 * 60 * 60;
+
+/// The smallest disk space, in bytes, block and undo files may be pruned to.
+pub const MIN_PRUNE_TARGET: u64 = 64 << 20;
+
+/// The number of most recent blocks whose data is never pruned, so that
+/// reorganizations within this depth can still be undone.
+pub const MIN_BLOCKS_TO_KEEP: u32 = 288;
+
+/// The number of most recent blocks whose hashes begin a block locator.
+pub const LOCATOR_RECENT_BLOCKS: usize = 10;
+
+/// The Golomb-Rice parameter of block filters: the number of low bits of each
+/// difference between sorted hashed items written verbatim.
+pub const BLOCK_FILTER_P: u8 = 19;
+
+/// The inverse false positive rate of block filters: items are hashed into a
+/// range of this many values per item in the filter.
+pub const BLOCK_FILTER_M: u64 = 784_931;
+
+/// The sequence number of a transaction input that does not opt in to
+/// replacement.
+pub const SEQUENCE_FINAL: u32 = u32::MAX;
+
+/// The highest sequence number of a transaction input opting the transaction
+/// in to replacement by one paying a higher fee, as in BIP 125.
+pub const MAX_RBF_SEQUENCE: u32 = SEQUENCE_FINAL - 2;
+
 /// The designated inbound TCP port to be used by the Rusty Bitcoin network.
 pub const RBTC_PORT: u16 = 42069;
 
@@ -59,10 +99,64 @@ pub const RIPEMD160_HASH_SIZE: usize = 20;
 /// The size of a SHA-256 hash, in bytes.
 pub const SHA256_HASH_SIZE: usize = 32;
 
-/// The initial mining target, in compressed `bits` form.
-pub const INTIIAL_TARGET_BITS: u32 = 0x1d00ffff;
+/// The initial mining target, in compressed `bits` form. This is also the
+/// proof-of-work limit: no difficulty adjustment may raise the target above it.
+pub const INITIAL_TARGET_BITS: u32 = 0x207fffff;
+
+/// The timestamp of the mainnet genesis block.
+pub const MAINNET_GENESIS_TIMESTAMP: u32 = 1_651_363_200;
+
+/// The hash of the mainnet genesis block.
+pub const MAINNET_GENESIS_HASH: [u8; SHA256_HASH_SIZE] = [
+  0xfc, 0x21, 0xba, 0x22, 0xfc, 0x1c, 0xff, 0xec, 0xfc, 0xf0, 0xea, 0x4f, 0xad,
+  0x1a, 0x22, 0xf1, 0x8c, 0x1d, 0x87, 0x21, 0x78, 0x5e, 0x22, 0x74, 0x69, 0x7a,
+  0x2d, 0xf2, 0x8f, 0x82, 0x70, 0xeb,
+];
+
+/// The timestamp of the testnet genesis block.
+pub const TESTNET_GENESIS_TIMESTAMP: u32 = 1_651_449_600;
+
+/// The hash of the testnet genesis block.
+pub const TESTNET_GENESIS_HASH: [u8; SHA256_HASH_SIZE] = [
+  0x61, 0x99, 0x19, 0x23, 0x78, 0xc8, 0xff, 0x74, 0x35, 0x82, 0x54, 0x96, 0xc4,
+  0xbd, 0x7a, 0x52, 0xbb, 0xa1, 0xdd, 0x9a, 0xc0, 0x9a, 0x5c, 0x76, 0x15, 0xbf,
+  0x1f, 0xb2, 0x78, 0xd4, 0x0f, 0xbb,
+];
+
+/// The number of most recent blocks whose median timestamp a new block's
+/// timestamp must exceed.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// The maximum number of seconds a block's timestamp may be ahead of the
+/// network-adjusted time.
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// The maximum number of seconds by which peers' clocks may adjust the local
+/// clock.
+pub const MAX_TIME_OFFSET: i64 = 70 * 60;
+
+/// The number of seconds beyond which every peer's clock disagreeing with the
+/// local clock triggers a warning.
+pub const TIME_WARNING_OFFSET: i64 = 5 * 60;
+
+/// The minimum number of peer time samples before the local clock is adjusted.
+pub const MIN_TIME_SAMPLES: usize = 5;
+
+/// The maximum number of peer time samples kept.
+pub const MAX_TIME_SAMPLES: usize = 200;
+
+/// The maximum number of orphan blocks held while waiting for their parents.
+pub const MAX_ORPHAN_BLOCKS: usize = 100;
+
+/// The maximum total size, in bytes, of the orphan blocks held while waiting
+/// for their parents.
+pub const MAX_ORPHAN_BLOCKS_SIZE: usize = 32 << 20;
+
+/// The number of seconds after which an orphan block is evicted.
+pub const ORPHAN_BLOCK_EXPIRY: u32 = 20 * 60;
 
 /// A network ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NetworkID {
   /// The mainnet network ID (byte: 0x00).
   Mainnet,
@@ -19,3 +19,49 @@ pub fn sha256(input: &[u8]) -> [u8; SHA256_HASH_SIZE] {
   hasher.result(&mut output);
   output
 }
+
+/// Return the lowercase hexadecimal encoding of the input bytes.
+pub fn hex(input: &[u8]) -> String {
+  input.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Return the SipHash-2-4 hash of the input bytes under the key `(k0, k1)`.
+pub fn siphash(k0: u64, k1: u64, input: &[u8]) -> u64 {
+  let mut v = [
+    k0 ^ 0x736f6d6570736575,
+    k1 ^ 0x646f72616e646f6d,
+    k0 ^ 0x6c7967656e657261,
+    k1 ^ 0x7465646279746573,
+  ];
+  let rounds = |v: &mut [u64; 4], count: usize| {
+    for _ in 0..count {
+      v[0] = v[0].wrapping_add(v[1]);
+      v[1] = v[1].rotate_left(13) ^ v[0];
+      v[0] = v[0].rotate_left(32);
+      v[2] = v[2].wrapping_add(v[3]);
+      v[3] = v[3].rotate_left(16) ^ v[2];
+      v[0] = v[0].wrapping_add(v[3]);
+      v[3] = v[3].rotate_left(21) ^ v[0];
+      v[2] = v[2].wrapping_add(v[1]);
+      v[1] = v[1].rotate_left(17) ^ v[2];
+      v[2] = v[2].rotate_left(32);
+    }
+  };
+
+  let mut chunks = input.chunks_exact(8);
+  for chunk in &mut chunks {
+    let m = u64::from_le_bytes(chunk.try_into().unwrap());
+    v[3] ^= m;
+    rounds(&mut v, 2);
+    v[0] ^= m;
+  }
+  let mut last = [0u8; 8];
+  last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+  let m = u64::from_le_bytes(last) | (input.len() as u64) << 56;
+  v[3] ^= m;
+  rounds(&mut v, 2);
+  v[0] ^= m;
+  v[2] ^= 0xff;
+  rounds(&mut v, 4);
+  v[0] ^ v[1] ^ v[2] ^ v[3]
+}
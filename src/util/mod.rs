@@ -1,4 +1,8 @@
+pub mod config;
 pub mod constants;
 pub mod hashes;
 pub mod macros;
+pub mod params;
+pub mod shutdown;
+pub mod time;
 pub mod types;
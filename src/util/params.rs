@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use super::constants::{
+  NetworkID, MAINNET_GENESIS_HASH, MAINNET_GENESIS_TIMESTAMP, SHA256_HASH_SIZE,
+  TESTNET_GENESIS_HASH, TESTNET_GENESIS_TIMESTAMP,
+};
+use crate::mining::difficulty::{Asert, DifficultyAlgorithm, Epoch};
+
+/// Consensus parameters of a Rusty Bitcoin network.
+#[derive(Clone)]
+pub struct ChainParams {
+  /// The network these parameters belong to.
+  pub network_id: NetworkID,
+
+  /// The difficulty adjustment algorithm used to validate and mine blocks.
+  pub difficulty: Arc<dyn DifficultyAlgorithm>,
+
+  /// The timestamp of the genesis block, which every node on the network
+  /// must agree on to share a chain.
+  pub genesis_timestamp: u32,
+
+  /// Known-good blocks, by height and hash. Headers at a checkpoint's height
+  /// must match its hash, and once a checkpoint is in the block index no
+  /// header may fork from the chain below it.
+  pub checkpoints: Vec<(u32, [u8; SHA256_HASH_SIZE])>,
+
+  /// A block whose ancestors are assumed to have valid scripts and
+  /// signatures, which are then not checked while they are connected. Every
+  /// other consensus rule still is, UTXO accounting included. `None` checks
+  /// every block.
+  pub assume_valid: Option<[u8; SHA256_HASH_SIZE]>,
+
+  /// The chain work the best header must have before the assume-valid block
+  /// is trusted, so that a chain of headers too weak to be the network's
+  /// cannot vouch for it.
+  pub min_chain_work: f64,
+}
+
+impl ChainParams {
+  /// Return the mainnet parameters, which retarget once per epoch.
+  pub fn mainnet() -> Self {
+    Self {
+      network_id: NetworkID::Mainnet,
+      difficulty: Arc::new(Epoch::default()),
+      genesis_timestamp: MAINNET_GENESIS_TIMESTAMP,
+      checkpoints: vec![(0, MAINNET_GENESIS_HASH)],
+      assume_valid: None,
+      min_chain_work: 0.0,
+    }
+  }
+
+  /// Return the testnet parameters, which retarget on every block so that
+  /// difficulty follows the few miners of a test network closely. The
+  /// schedule is anchored at the first block mined rather than the genesis
+  /// block, which predates it.
+  pub fn testnet() -> Self {
+    Self {
+      network_id: NetworkID::Testnet,
+      difficulty: Arc::new(Asert { anchor_height: 1, ..Asert::default() }),
+      genesis_timestamp: TESTNET_GENESIS_TIMESTAMP,
+      checkpoints: vec![(0, TESTNET_GENESIS_HASH)],
+      assume_valid: None,
+      min_chain_work: 0.0,
+    }
+  }
+
+  /// Return the checkpoint at the given height, if any.
+  pub fn checkpoint(&self, height: u32) -> Option<[u8; SHA256_HASH_SIZE]> {
+    self
+      .checkpoints
+      .iter()
+      .find(|(checkpoint_height, _)| *checkpoint_height == height)
+      .map(|(_, hash)| *hash)
+  }
+
+  /// Return the parameters of the network with the given ID.
+  pub fn from_network_id(network_id: NetworkID) -> Self {
+    match network_id {
+      NetworkID::Mainnet => Self::mainnet(),
+      NetworkID::Testnet => Self::testnet(),
+    }
+  }
+}
+
+impl Default for ChainParams {
+  fn default() -> Self {
+    Self::mainnet()
+  }
+}
@@ -0,0 +1,49 @@
+use std::{
+  io,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+};
+
+use signal_hook::{consts::TERM_SIGNALS, flag};
+
+/// Whether the node has been asked to shut down, shared between threads.
+///
+/// Each long-running thread checks it between units of work and returns once
+/// it is set, so that the main thread can then write out what is kept in
+/// memory without any thread changing it.
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown {
+  requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+  /// Initialize a flag that is not yet set.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Ask every thread holding this flag to return.
+  pub fn request(&self) {
+    self.requested.store(true, Ordering::SeqCst);
+  }
+
+  /// Return whether shutdown has been requested.
+  pub fn is_requested(&self) -> bool {
+    self.requested.load(Ordering::SeqCst)
+  }
+
+  /// Request shutdown once the process receives a termination signal, e.g.
+  /// SIGINT on ctrl-c, instead of being killed outright. A second signal
+  /// kills the process without waiting.
+  pub fn request_on_signal(&self) -> io::Result<()> {
+    for &signal in TERM_SIGNALS {
+      // Registered first, so that it only exits on a signal arriving once the
+      // flag is already set by an earlier one.
+      flag::register_conditional_shutdown(signal, 1, self.requested.clone())?;
+      flag::register(signal, self.requested.clone())?;
+    }
+    Ok(())
+  }
+}
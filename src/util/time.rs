@@ -0,0 +1,150 @@
+use std::{
+  collections::HashMap,
+  net::Ipv4Addr,
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  },
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  logln,
+  util::constants::{
+    MAX_TIME_OFFSET, MAX_TIME_SAMPLES, MIN_TIME_SAMPLES, TIME_WARNING_OFFSET,
+  },
+};
+
+/// A source of the local time.
+pub trait Clock: Send + Sync {
+  /// Return the current time, in seconds since the UNIX epoch.
+  fn now(&self) -> u32;
+}
+
+/// The local system clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> u32 {
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .expect("Failed to get duration since UNIX epoch")
+      .as_secs() as u32
+  }
+}
+
+/// A clock that only moves when told to. For tests and simulations.
+#[derive(Debug, Default)]
+pub struct MockClock {
+  time: AtomicU32,
+}
+
+impl MockClock {
+  /// Initialize a mock clock reading the given time.
+  pub fn new(time: u32) -> Self {
+    Self { time: AtomicU32::new(time) }
+  }
+
+  /// Set the time read by this clock.
+  pub fn set(&self, time: u32) {
+    self.time.store(time, Ordering::SeqCst);
+  }
+
+  /// Move this clock forward by the given number of seconds.
+  pub fn advance(&self, secs: u32) {
+    self.time.fetch_add(secs, Ordering::SeqCst);
+  }
+}
+
+impl Clock for MockClock {
+  fn now(&self) -> u32 {
+    self.time.load(Ordering::SeqCst)
+  }
+}
+
+/// The network-adjusted time: the local clock, corrected by the median offset
+/// of peers' clocks from it.
+///
+/// Peers report their time during the handshake. One sample is kept per peer
+/// address, so a single peer cannot reconnect repeatedly to skew the median.
+pub struct TimeSource {
+  clock: Arc<dyn Clock>,
+  samples: HashMap<Ipv4Addr, i64>,
+  offset: i64,
+  warned: bool,
+}
+
+impl TimeSource {
+  /// Initialize a time source reading the given clock, with no peer samples.
+  pub fn new(clock: Arc<dyn Clock>) -> Self {
+    Self { clock, samples: HashMap::new(), offset: 0, warned: false }
+  }
+
+  /// Return the clock this time source corrects.
+  pub fn clock(&self) -> Arc<dyn Clock> {
+    self.clock.clone()
+  }
+
+  /// Return the uncorrected local time.
+  pub fn now(&self) -> u32 {
+    self.clock.now()
+  }
+
+  /// Return the current network-adjusted time.
+  pub fn adjusted_time(&self) -> u32 {
+    (self.now() as i64 + self.offset).clamp(0, u32::MAX as i64) as u32
+  }
+
+  /// Return the offset, in seconds, applied to the local clock.
+  pub fn offset(&self) -> i64 {
+    self.offset
+  }
+
+  /// Record the time reported by `peer` during its handshake and recompute
+  /// the offset.
+  ///
+  /// The offset is the median of all samples once there are at least
+  /// `MIN_TIME_SAMPLES`, recomputed only on an odd count so the median is a
+  /// real sample. A median further than `MAX_TIME_OFFSET` from the local clock
+  /// is not trusted, and the offset falls back to zero.
+  pub fn add_sample(&mut self, peer: Ipv4Addr, peer_time: u32) {
+    if self.samples.len() >= MAX_TIME_SAMPLES
+      || self.samples.contains_key(&peer)
+    {
+      return;
+    }
+    self
+      .samples
+      .insert(peer, peer_time as i64 - self.now() as i64);
+
+    let count = self.samples.len();
+    if count < MIN_TIME_SAMPLES || count.is_multiple_of(2) {
+      return;
+    }
+    let mut offsets: Vec<i64> = self.samples.values().copied().collect();
+    offsets.sort_unstable();
+    let median = offsets[count / 2];
+
+    if median.abs() <= MAX_TIME_OFFSET {
+      self.offset = median;
+    } else {
+      self.offset = 0;
+
+      // Warn once if no peer's clock is even close to ours, since then ours is
+      // the likely culprit.
+      if !self.warned
+        && offsets
+          .iter()
+          .all(|offset| offset.abs() > TIME_WARNING_OFFSET)
+      {
+        self.warned = true;
+        logln!(
+          "WARNING: Local clock differs from the network's by {}s; check that \
+           your computer's date and time are correct",
+          median
+        );
+      }
+    }
+  }
+}
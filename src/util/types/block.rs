@@ -1,13 +1,12 @@
 use std::fmt::Display;
 
-use ethnum::u256;
-use serde::{ser::SerializeSeq, Serialize};
+use serde::{Deserialize, Serialize};
 
 use super::{header::Header, txn::Txn};
 use crate::util::{constants::SHA256_HASH_SIZE, hashes::sha256};
 
 /// A block.
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Block {
   header: Header,
   txn_count: u32,
@@ -20,9 +19,9 @@ impl Block {
     Self { header, txn_count, txns }
   }
 
-  /// Return the genesis block.
-  pub fn genesis() -> Self {
-    Self { header: Header::genesis(), txn_count: 0, txns: Vec::new() }
+  /// Return the genesis block with the given timestamp.
+  pub fn genesis(timestamp: u32) -> Self {
+    Self { header: Header::genesis(timestamp), txn_count: 0, txns: Vec::new() }
   }
 
   /// Return the double-SHA-256 hash of this block's `header`.
@@ -35,42 +34,102 @@ impl Block {
     self.header.prev_block_hash()
   }
 
+  /// Return this block's `header`.
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+
+  /// Return this block's `txn_count`.
+  pub fn txn_count(&self) -> u32 {
+    self.txn_count
+  }
+
+  /// Return this block's `txns`.
+  pub fn txns(&self) -> &[Txn] {
+    &self.txns
+  }
+
+  /// Set the `nonce` in this block's header.
+  pub fn set_nonce(&mut self, nonce: u32) {
+    self.header.set_nonce(nonce);
+  }
+
+  /// Return the size of this block's serialized data, in bytes.
+  pub fn size(&self) -> usize {
+    bincode::serialized_size(self).expect("Failed to serialize block") as usize
+  }
+
   /// Return the relative work done to mine this block.
   pub fn relative_work(&self) -> f64 {
-    u256::MAX.as_f64() / self.header.target().as_f64()
+    self.header.relative_work()
   }
 
-  /// Verify that this block's nonce is valid. Called in the networking thread
-  /// to validate incoming blocks.
+  /// Verify that this block's nonce is valid, i.e. that its header's hash
+  /// meets the target. Since the header commits to the transactions through
+  /// its `merkle_root`, this can be checked before the transactions are known.
   pub fn verify_nonce(&self) -> Result<(), Error> {
-    let txns_and_nonce = [
-      bincode::serialize(&self.txns)
-        .expect("Failed to serialize block transactions"),
-      self.header.nonce().to_le_bytes().to_vec(),
-    ]
-    .concat();
-    if u256::from_be_bytes(sha256(&sha256(&txns_and_nonce)))
-      > self.header.target()
-    {
-      Err(Error::NonceFailedVerification)
-    } else {
+    if self.header.check_pow() {
       Ok(())
+    } else {
+      Err(Error::NonceFailedVerification)
     }
   }
-}
 
-impl Serialize for Block {
-  /// Serialize this block.
-  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-  where
-    S: serde::Serializer,
-  {
-    let mut seq = serializer.serialize_seq(Some(self.txns.len()))?;
-    for e in &self.txns {
-      seq.serialize_element(&e)?;
+  /// Verify that this block's header commits to its transactions, and that
+  /// no other list of transactions has the same merkle root.
+  ///
+  /// Since an odd level of the merkle tree pairs its last node with itself,
+  /// repeating the transactions under that node gives the same root. Such a
+  /// mutated block has the hash of the real one, but not its contents, so it
+  /// is rejected outright: any two equal nodes paired at some level, which a
+  /// block without duplicate transactions never has, are taken for one.
+  pub fn verify_merkle_root(&self) -> Result<(), Error> {
+    if self.txn_count as usize != self.txns.len() {
+      return Err(Error::IncorrectTxnCount);
+    }
+    let (root, mutated) = merkle_tree(&self.txns);
+    if self.header.merkle_root() != root {
+      return Err(Error::MerkleRootMismatch);
+    }
+    if mutated {
+      return Err(Error::MutatedMerkleTree);
     }
-    seq.end()
+    Ok(())
+  }
+}
+
+/// Return the merkle root of the given transactions: the root of a binary
+/// tree of double-SHA-256 hashes whose leaves are the transactions' hashes,
+/// where a level with an odd number of nodes pairs its last node with itself.
+/// The merkle root of no transactions is all zeros.
+pub fn merkle_root(txns: &[Txn]) -> [u8; SHA256_HASH_SIZE] {
+  merkle_tree(txns).0
+}
+
+/// Return the merkle root of the given transactions, and whether any level of
+/// the tree pairs two equal nodes.
+fn merkle_tree(txns: &[Txn]) -> ([u8; SHA256_HASH_SIZE], bool) {
+  let mut level: Vec<_> = txns.iter().map(|txn| txn.hash()).collect();
+  if level.is_empty() {
+    return ([0u8; SHA256_HASH_SIZE], false);
+  }
+  let mut mutated = false;
+  while level.len() > 1 {
+    level = level
+      .chunks(2)
+      .map(|pair| {
+        let right = match pair.get(1) {
+          Some(right) => {
+            mutated |= *right == pair[0];
+            right
+          },
+          None => &pair[0],
+        };
+        sha256(&sha256(&[pair[0], *right].concat()))
+      })
+      .collect();
   }
+  (level[0], mutated)
 }
 
 impl Default for Block {
@@ -84,12 +143,18 @@ impl Default for Block {
 #[derive(Debug)]
 pub enum Error {
   NonceFailedVerification,
+  IncorrectTxnCount,
+  MerkleRootMismatch,
+  MutatedMerkleTree,
 }
 
 impl Display for Error {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
       Error::NonceFailedVerification => write!(f, "NonceFailedVerification"),
+      Error::IncorrectTxnCount => write!(f, "IncorrectTxnCount"),
+      Error::MerkleRootMismatch => write!(f, "MerkleRootMismatch"),
+      Error::MutatedMerkleTree => write!(f, "MutatedMerkleTree"),
     }
   }
 }
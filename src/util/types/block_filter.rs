@@ -0,0 +1,279 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use super::{block::Block, coin::BlockUndo};
+use crate::util::{
+  constants::{
+    BLOCK_FILTER_M, BLOCK_FILTER_P, RIPEMD160_HASH_SIZE, SHA256_HASH_SIZE,
+  },
+  hashes::{sha256, siphash},
+};
+
+/// A compact block filter: a Golomb-coded set of the pubkey hashes a block's
+/// outputs pay and the outputs its inputs spend, as in BIP 158 with pubkey
+/// hashes in place of scripts.
+///
+/// Each item is hashed with SipHash, keyed by the first 16 bytes of the block
+/// hash, into the range `[0, n * BLOCK_FILTER_M)`. The sorted hashes'
+/// differences are then Golomb-Rice coded with parameter `BLOCK_FILTER_P`. A
+/// filter matches every item it was built from, and any other item with
+/// probability about `1 / BLOCK_FILTER_M`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockFilter {
+  block_hash: [u8; SHA256_HASH_SIZE],
+  n: u64,
+  data: Vec<u8>,
+}
+
+impl BlockFilter {
+  /// Build the filter of a block from the block and the coins it spends.
+  pub fn new(block: &Block, undo: &BlockUndo) -> Self {
+    let mut items = BTreeSet::<[u8; RIPEMD160_HASH_SIZE]>::new();
+    for txn in block.txns() {
+      items.extend(txn.txo_list().iter().map(|txo| txo.pubkey_hash()));
+    }
+    items.extend(undo.spent.iter().map(|coin| coin.txo.pubkey_hash()));
+
+    let block_hash = block.hash();
+    let n = items.len() as u64;
+    let mut values: Vec<u64> = items
+      .iter()
+      .map(|item| hash_to_range(&block_hash, n, item))
+      .collect();
+    values.sort_unstable();
+
+    let mut writer = BitWriter::new();
+    let mut last = 0;
+    for value in values {
+      let delta = value - last;
+      for _ in 0..delta >> BLOCK_FILTER_P {
+        writer.write_bit(true);
+      }
+      writer.write_bit(false);
+      writer.write_bits(delta, BLOCK_FILTER_P);
+      last = value;
+    }
+    Self { block_hash, n, data: writer.finish() }
+  }
+
+  /// Decode a filter of the block with the given hash from its serialized
+  /// form: the number of items as a BIP 158 compact size, followed by the
+  /// Golomb-Rice coded bits.
+  pub fn decode(
+    block_hash: [u8; SHA256_HASH_SIZE],
+    bytes: &[u8],
+  ) -> Result<Self, Error> {
+    let (n, len) = read_compact_size(bytes).ok_or(Error::MalformedFilter)?;
+    Ok(Self { block_hash, n, data: bytes[len..].to_vec() })
+  }
+
+  /// Return the serialized form of this filter.
+  pub fn encode(&self) -> Vec<u8> {
+    let mut bytes = compact_size(self.n);
+    bytes.extend_from_slice(&self.data);
+    bytes
+  }
+
+  /// Return the hash of the block this filter was built from.
+  pub fn block_hash(&self) -> [u8; SHA256_HASH_SIZE] {
+    self.block_hash
+  }
+
+  /// Return the number of items in this filter.
+  pub fn len(&self) -> u64 {
+    self.n
+  }
+
+  /// Return whether this filter has no items.
+  pub fn is_empty(&self) -> bool {
+    self.n == 0
+  }
+
+  /// Return the double-SHA-256 hash of this filter's serialized form.
+  pub fn hash(&self) -> [u8; SHA256_HASH_SIZE] {
+    sha256(&sha256(&self.encode()))
+  }
+
+  /// Return the header of this filter: the double-SHA-256 hash of this
+  /// filter's hash followed by the header of the previous block's filter, all
+  /// zeros for the genesis block.
+  pub fn header(
+    &self,
+    prev_header: &[u8; SHA256_HASH_SIZE],
+  ) -> [u8; SHA256_HASH_SIZE] {
+    sha256(&sha256(&[&self.hash()[..], prev_header].concat()))
+  }
+
+  /// Return whether this filter matches the given pubkey hash.
+  pub fn matches(
+    &self,
+    pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  ) -> Result<bool, Error> {
+    self.matches_any(&[*pubkey_hash])
+  }
+
+  /// Return whether this filter matches any of the given pubkey hashes.
+  ///
+  /// False positives occur with probability about `1 / BLOCK_FILTER_M` per
+  /// pubkey hash; there are no false negatives.
+  pub fn matches_any(
+    &self,
+    pubkey_hashes: &[[u8; RIPEMD160_HASH_SIZE]],
+  ) -> Result<bool, Error> {
+    if self.n == 0 || pubkey_hashes.is_empty() {
+      return Ok(false);
+    }
+    let mut queries: Vec<u64> = pubkey_hashes
+      .iter()
+      .map(|item| hash_to_range(&self.block_hash, self.n, item))
+      .collect();
+    queries.sort_unstable();
+
+    // Walk the filter's sorted values and the sorted queries together.
+    let mut reader = BitReader::new(&self.data);
+    let mut queries = queries.into_iter().peekable();
+    let mut value = 0u64;
+    for _ in 0..self.n {
+      let mut quotient = 0u64;
+      while reader.read_bit().ok_or(Error::MalformedFilter)? {
+        quotient += 1;
+      }
+      let remainder = reader
+        .read_bits(BLOCK_FILTER_P)
+        .ok_or(Error::MalformedFilter)?;
+      value = (quotient << BLOCK_FILTER_P)
+        .checked_add(remainder)
+        .and_then(|delta| value.checked_add(delta))
+        .ok_or(Error::MalformedFilter)?;
+      while let Some(&query) = queries.peek() {
+        if query == value {
+          return Ok(true);
+        }
+        if query > value {
+          break;
+        }
+        queries.next();
+      }
+      if queries.peek().is_none() {
+        return Ok(false);
+      }
+    }
+    Ok(false)
+  }
+}
+
+/// Return the hash of an item in a filter of `n` items of the block with the
+/// given hash, mapped uniformly onto `[0, n * BLOCK_FILTER_M)`.
+fn hash_to_range(
+  block_hash: &[u8; SHA256_HASH_SIZE],
+  n: u64,
+  item: &[u8],
+) -> u64 {
+  let k0 = u64::from_le_bytes(block_hash[..8].try_into().unwrap());
+  let k1 = u64::from_le_bytes(block_hash[8..16].try_into().unwrap());
+  let range = n * BLOCK_FILTER_M;
+  ((siphash(k0, k1, item) as u128 * range as u128) >> 64) as u64
+}
+
+/// Return the BIP 158 compact size encoding of a number.
+fn compact_size(n: u64) -> Vec<u8> {
+  match n {
+    0..=0xfc => vec![n as u8],
+    0xfd..=0xffff => [&[0xfd][..], &(n as u16).to_le_bytes()].concat(),
+    0x10000..=0xffff_ffff => [&[0xfe][..], &(n as u32).to_le_bytes()].concat(),
+    _ => [&[0xff][..], &n.to_le_bytes()].concat(),
+  }
+}
+
+/// Read a compact size from the start of the given bytes, returning it and the
+/// number of bytes it took up.
+fn read_compact_size(bytes: &[u8]) -> Option<(u64, usize)> {
+  let len = match *bytes.first()? {
+    0xfd => 2,
+    0xfe => 4,
+    0xff => 8,
+    n => return Some((n as u64, 1)),
+  };
+  let mut buf = [0u8; 8];
+  buf[..len].copy_from_slice(bytes.get(1..1 + len)?);
+  Some((u64::from_le_bytes(buf), 1 + len))
+}
+
+/// Writes bits most significant first into bytes.
+struct BitWriter {
+  bytes: Vec<u8>,
+  used: u8,
+}
+
+impl BitWriter {
+  /// Initialize an empty bit writer.
+  fn new() -> Self {
+    Self { bytes: Vec::new(), used: 8 }
+  }
+
+  /// Write a single bit.
+  fn write_bit(&mut self, bit: bool) {
+    if self.used == 8 {
+      self.bytes.push(0);
+      self.used = 0;
+    }
+    if bit {
+      *self.bytes.last_mut().unwrap() |= 0x80 >> self.used;
+    }
+    self.used += 1;
+  }
+
+  /// Write the low `count` bits of a value, most significant first.
+  fn write_bits(&mut self, value: u64, count: u8) {
+    for i in (0..count).rev() {
+      self.write_bit(value >> i & 1 == 1);
+    }
+  }
+
+  /// Return the bytes written, the last padded with zero bits.
+  fn finish(self) -> Vec<u8> {
+    self.bytes
+  }
+}
+
+/// Reads bits most significant first from bytes.
+struct BitReader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+  /// Initialize a bit reader at the start of the given bytes.
+  fn new(bytes: &'a [u8]) -> Self {
+    Self { bytes, pos: 0 }
+  }
+
+  /// Read a single bit, or `None` past the end of the bytes.
+  fn read_bit(&mut self) -> Option<bool> {
+    let byte = self.bytes.get(self.pos / 8)?;
+    let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+    self.pos += 1;
+    Some(bit)
+  }
+
+  /// Read `count` bits as a value, most significant first.
+  fn read_bits(&mut self, count: u8) -> Option<u64> {
+    let mut value = 0;
+    for _ in 0..count {
+      value = value << 1 | self.read_bit()? as u64;
+    }
+    Some(value)
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  MalformedFilter,
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::MalformedFilter => write!(f, "MalformedFilter"),
+    }
+  }
+}
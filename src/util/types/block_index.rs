@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::header::Header;
+use crate::{
+  storage::blockstore::BlockPos,
+  util::constants::{
+    LOCATOR_RECENT_BLOCKS, MEDIAN_TIME_SPAN, SHA256_HASH_SIZE,
+  },
+};
+
+/// How far a known block has been validated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BlockStatus {
+  /// The header is valid, but the block's data is missing or unchecked.
+  ValidHeader,
+
+  /// The whole block is valid.
+  ValidBlock,
+
+  /// The block failed validation, or was invalidated by hand.
+  Invalid,
+
+  /// An ancestor of the block failed validation or was invalidated.
+  InvalidChild,
+}
+
+impl BlockStatus {
+  /// Return whether the block, or one of its ancestors, is invalid.
+  pub fn is_invalid(&self) -> bool {
+    matches!(self, BlockStatus::Invalid | BlockStatus::InvalidChild)
+  }
+}
+
+/// An entry in the block index, describing a known block header and where it
+/// sits in the block tree.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockIndexEntry {
+  header: Header,
+  hash: [u8; SHA256_HASH_SIZE],
+  height: u32,
+  chain_work: f64,
+  status: BlockStatus,
+  pos: Option<BlockPos>,
+  undo_pos: Option<BlockPos>,
+  skip: Option<[u8; SHA256_HASH_SIZE]>,
+}
+
+impl BlockIndexEntry {
+  /// Initialize an entry for a header at the given height, with the given
+  /// total work of its chain, whose data is not stored.
+  pub fn new(
+    header: Header,
+    height: u32,
+    chain_work: f64,
+    status: BlockStatus,
+  ) -> Self {
+    Self {
+      hash: header.hash(),
+      header,
+      height,
+      chain_work,
+      status,
+      pos: None,
+      undo_pos: None,
+      skip: None,
+    }
+  }
+
+  /// Return the header of the indexed block.
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+
+  /// Return the hash of the indexed block.
+  pub fn hash(&self) -> [u8; SHA256_HASH_SIZE] {
+    self.hash
+  }
+
+  /// Return the height of the indexed block, where the genesis block has
+  /// height zero.
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  /// Return the total relative work of the indexed block and all of its
+  /// ancestors.
+  pub fn chain_work(&self) -> f64 {
+    self.chain_work
+  }
+
+  /// Return how far the indexed block has been validated.
+  pub fn status(&self) -> BlockStatus {
+    self.status
+  }
+
+  /// Return the position of the indexed block's data in the block files, if
+  /// stored.
+  pub fn pos(&self) -> Option<BlockPos> {
+    self.pos
+  }
+
+  /// Return the position of the indexed block's undo data in the undo files,
+  /// if stored.
+  pub fn undo_pos(&self) -> Option<BlockPos> {
+    self.undo_pos
+  }
+
+  /// Return the hash of the indexed block's ancestor at `skip_height` of its
+  /// height, used to jump back along its chain, or `None` for the genesis
+  /// block.
+  pub fn skip(&self) -> Option<[u8; SHA256_HASH_SIZE]> {
+    self.skip
+  }
+}
+
+/// Return the height to which an entry at `height` keeps a skip pointer.
+///
+/// Any height is reached from any higher one through these in a logarithmic
+/// number of steps, while entries close together mostly point to different
+/// heights, so consecutive walks do not all take the same path.
+fn skip_height(height: u32) -> u32 {
+  if height < 2 {
+    return 0;
+  }
+  if height & 1 == 1 {
+    invert_lowest_one(invert_lowest_one(height - 1)) + 1
+  } else {
+    invert_lowest_one(height)
+  }
+}
+
+/// Return `n` with its lowest set bit cleared.
+fn invert_lowest_one(n: u32) -> u32 {
+  n & n.wrapping_sub(1)
+}
+
+/// An index of every known block header, keyed by block hash.
+#[derive(Default)]
+pub struct BlockIndex {
+  entries: HashMap<[u8; SHA256_HASH_SIZE], BlockIndexEntry>,
+}
+
+impl BlockIndex {
+  /// Initialize and return an empty index.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a header to the index and return its entry. The header's parent must
+  /// already be indexed.
+  pub fn insert(&mut self, header: Header) -> Option<&BlockIndexEntry> {
+    let hash = header.hash();
+    if self.entries.contains_key(&hash) {
+      return self.entries.get(&hash);
+    }
+    let parent = self.get(&header.prev_block_hash())?;
+    let height = parent.height + 1;
+    let chain_work = parent.chain_work + header.relative_work();
+    let skip = self.ancestor(&parent.hash, skip_height(height))?.hash;
+    let mut entry = BlockIndexEntry::new(
+      header,
+      height,
+      chain_work,
+      BlockStatus::ValidHeader,
+    );
+    entry.skip = Some(skip);
+    Some(self.entries.entry(hash).or_insert(entry))
+  }
+
+  /// Add a complete entry to the index, e.g. one read back from disk,
+  /// replacing any entry for the same block.
+  pub fn insert_entry(&mut self, entry: BlockIndexEntry) {
+    self.entries.insert(entry.hash, entry);
+  }
+
+  /// Get the entry for the block with the given hash.
+  pub fn get(&self, hash: &[u8; SHA256_HASH_SIZE]) -> Option<&BlockIndexEntry> {
+    self.entries.get(hash)
+  }
+
+  /// Return an iterator over every entry, in no particular order.
+  pub fn entries(&self) -> impl Iterator<Item = &BlockIndexEntry> {
+    self.entries.values()
+  }
+
+  /// Return whether the block with the given hash is indexed.
+  pub fn contains(&self, hash: &[u8; SHA256_HASH_SIZE]) -> bool {
+    self.entries.contains_key(hash)
+  }
+
+  /// Set the validation status of the block with the given hash, and return
+  /// its updated entry.
+  pub fn set_status(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    status: BlockStatus,
+  ) -> Option<&BlockIndexEntry> {
+    let entry = self.entries.get_mut(hash)?;
+    entry.status = status;
+    Some(entry)
+  }
+
+  /// Set the data position of the block with the given hash, and return its
+  /// updated entry.
+  pub fn set_pos(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    pos: Option<BlockPos>,
+  ) -> Option<&BlockIndexEntry> {
+    let entry = self.entries.get_mut(hash)?;
+    entry.pos = pos;
+    Some(entry)
+  }
+
+  /// Set the undo data position of the block with the given hash, and return
+  /// its updated entry.
+  pub fn set_undo_pos(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    undo_pos: Option<BlockPos>,
+  ) -> Option<&BlockIndexEntry> {
+    let entry = self.entries.get_mut(hash)?;
+    entry.undo_pos = undo_pos;
+    Some(entry)
+  }
+
+  /// Get the entry of the ancestor at `height` of the block with the given
+  /// hash, which may be the block itself.
+  ///
+  /// Follows skip pointers where they do not overshoot, so this takes a
+  /// number of steps logarithmic in the distance.
+  pub fn ancestor(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    height: u32,
+  ) -> Option<&BlockIndexEntry> {
+    let mut entry = self.get(hash)?;
+    if height > entry.height {
+      return None;
+    }
+    while entry.height > height {
+      // Take the skip pointer unless it overshoots, or the parent's skip
+      // pointer lands closer to `height` without overshooting, in which
+      // case stepping to the parent first gets there sooner.
+      let skip = skip_height(entry.height);
+      let parent_skip = skip_height(entry.height - 1);
+      let take_skip = skip == height
+        || (skip > height
+          && !(parent_skip + 2 < skip && parent_skip >= height));
+      entry = match entry.skip {
+        Some(skip) if take_skip => self.get(&skip)?,
+        _ => self.get(&entry.header.prev_block_hash())?,
+      };
+    }
+    Some(entry)
+  }
+
+  /// Get the entry of the last common ancestor of the blocks with the given
+  /// hashes, which may be either block itself. Takes a number of steps
+  /// logarithmic in the blocks' heights plus linear in how far back they
+  /// fork.
+  pub fn find_fork(
+    &self,
+    a: &[u8; SHA256_HASH_SIZE],
+    b: &[u8; SHA256_HASH_SIZE],
+  ) -> Option<&BlockIndexEntry> {
+    let height = self.get(a)?.height.min(self.get(b)?.height);
+    let mut a = self.ancestor(a, height)?;
+    let mut b = self.ancestor(b, height)?;
+    while a.hash != b.hash {
+      a = self.get(&a.header.prev_block_hash())?;
+      b = self.get(&b.header.prev_block_hash())?;
+    }
+    Some(a)
+  }
+
+  /// Return the hashes of every indexed block descending from the block with
+  /// the given hash, in no particular order.
+  pub fn descendants(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Vec<[u8; SHA256_HASH_SIZE]> {
+    let height = match self.get(hash) {
+      Some(entry) => entry.height,
+      None => return Vec::new(),
+    };
+    self
+      .entries
+      .values()
+      .filter(|entry| {
+        entry.height > height
+          && self
+            .ancestor(&entry.hash, height)
+            .is_some_and(|ancestor| ancestor.hash == *hash)
+      })
+      .map(|entry| entry.hash)
+      .collect()
+  }
+
+  /// Return a block locator for the block with the given hash: the hashes of
+  /// the block and its `LOCATOR_RECENT_BLOCKS - 1` closest ancestors, then of
+  /// ancestors at exponentially growing distances, ending with the genesis
+  /// block. A peer finds where its chain forks from ours with a locator in
+  /// a number of hashes logarithmic in our chain's length.
+  pub fn locator(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Option<Vec<[u8; SHA256_HASH_SIZE]>> {
+    let mut locator = Vec::new();
+    let mut entry = self.get(hash)?;
+    let mut step = 1;
+    loop {
+      locator.push(entry.hash);
+      if entry.height == 0 {
+        break;
+      }
+      if locator.len() >= LOCATOR_RECENT_BLOCKS {
+        step *= 2;
+      }
+      entry = self.ancestor(&entry.hash, entry.height.saturating_sub(step))?;
+    }
+    Some(locator)
+  }
+
+  /// Get the median timestamp of the block with the given hash and its
+  /// `MEDIAN_TIME_SPAN - 1` closest ancestors (fewer near genesis).
+  ///
+  /// Unlike a single timestamp, this cannot be moved far by any one miner, and
+  /// never decreases along a chain.
+  pub fn median_time_past(&self, hash: &[u8; SHA256_HASH_SIZE]) -> Option<u32> {
+    let mut timestamps = Vec::with_capacity(MEDIAN_TIME_SPAN);
+    let mut entry = self.get(hash)?;
+    loop {
+      timestamps.push(entry.header.timestamp());
+      if timestamps.len() == MEDIAN_TIME_SPAN || entry.height == 0 {
+        break;
+      }
+      entry = self.get(&entry.header.prev_block_hash())?;
+    }
+    timestamps.sort_unstable();
+    Some(timestamps[timestamps.len() / 2])
+  }
+}
@@ -1,59 +1,1767 @@
-use std::fmt::Display;
+use std::{
+  cmp::Ordering,
+  collections::{BTreeSet, HashMap, HashSet},
+  fmt::Display,
+  net::Ipv4Addr,
+  sync::Arc,
+};
 
-use super::block::{self, Block};
-use crate::util::constants::SHA256_HASH_SIZE;
+use super::{
+  block::{self, Block},
+  block_filter::BlockFilter,
+  block_index::{BlockIndex, BlockIndexEntry, BlockStatus},
+  coin::{BlockUndo, Coin, OutPoint},
+  header::Header,
+  orphan_pool::OrphanPool,
+  txn::Txn,
+};
+use crate::{
+  events::validation::{ValidationEvent, ValidationEvents},
+  index::{
+    addrindex::{AddrIndex, AddrTxn, AddrUtxo},
+    chain_index::ChainIndex,
+    filterindex::FilterIndex,
+    txindex::TxIndex,
+  },
+  logln,
+  storage::{
+    self,
+    blockstore::{BlockPos, BlockStore},
+    chainstate::Chainstate,
+  },
+  util::{
+    config::Config,
+    constants::{
+      MAX_FUTURE_BLOCK_TIME, MIN_BLOCKS_TO_KEEP, MIN_PRUNE_TARGET,
+      RIPEMD160_HASH_SIZE, SHA256_HASH_SIZE,
+    },
+    hashes::hex,
+    params::ChainParams,
+  },
+};
 
-/// The active local chain.
+/// A hook called when an orphan block is stored, with the peer that sent it (if
+/// any) and the hash of the block needed to connect it.
+pub type MissingParentHook =
+  Box<dyn FnMut(Option<Ipv4Addr>, [u8; SHA256_HASH_SIZE]) + Send>;
+
+/// What became of a block accepted by `ActiveChain::validate_and_push`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushOutcome {
+  /// The active chain moved to include the block. Holds the total number of
+  /// blocks connected, including any stored blocks and orphans that were
+  /// waiting on it.
+  Connected(usize),
+
+  /// The block was stored, but is not on a chain with more work than the
+  /// active chain, or is waiting on the data of an ancestor.
+  Stored,
+
+  /// The block's parent is unknown, so it was stored as an orphan.
+  Orphaned,
+}
+
+/// A block in the block index with no known children, or the active chain's
+/// last block, as reported by `ActiveChain::chain_tips`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainTip {
+  /// The height of the tip.
+  pub height: u32,
+
+  /// The hash of the tip.
+  pub hash: [u8; SHA256_HASH_SIZE],
+
+  /// The number of blocks from where the tip's branch forks from the active
+  /// chain up to the tip; zero for the active chain's tip.
+  pub branch_len: u32,
+
+  /// How far the tip's branch has been validated.
+  pub status: ChainTipStatus,
+}
+
+/// How far a chain tip's branch has been validated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainTipStatus {
+  /// The tip is the active chain's last block.
+  Active,
+
+  /// Every block on the branch has been connected, but the branch has since
+  /// been disconnected in favor of one with more work.
+  ValidFork,
+
+  /// Every block on the branch is stored, but not all have been connected.
+  ValidHeaders,
+
+  /// Some blocks on the branch have not been downloaded, or have since been
+  /// pruned, so only their headers are known to be valid.
+  HeadersOnly,
+
+  /// The tip or one of its ancestors is invalid.
+  Invalid,
+}
+
+impl Display for ChainTipStatus {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ChainTipStatus::Active => write!(f, "active"),
+      ChainTipStatus::ValidFork => write!(f, "valid-fork"),
+      ChainTipStatus::ValidHeaders => write!(f, "valid-headers"),
+      ChainTipStatus::HeadersOnly => write!(f, "headers-only"),
+      ChainTipStatus::Invalid => write!(f, "invalid"),
+    }
+  }
+}
+
+/// A block's chain work and hash, ordered by work and then by hash.
+#[derive(Clone, Copy, Debug)]
+struct WorkKey(f64, [u8; SHA256_HASH_SIZE]);
+
+impl WorkKey {
+  /// Return the key of the given block index entry.
+  fn of(entry: &BlockIndexEntry) -> Self {
+    Self(entry.chain_work(), entry.hash())
+  }
+}
+
+impl PartialEq for WorkKey {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for WorkKey {}
+
+impl PartialOrd for WorkKey {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for WorkKey {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+  }
+}
+
+/// The active local chain. Blocks are kept in a block store on disk; only the
+/// block index and the hashes of the active chain's blocks are kept in memory.
+/// The UTXO set of the active chain's last block is kept in the chainstate.
+///
+/// Headers are accepted into the block index ahead of their blocks, so the
+/// index may hold a chain of headers with more work than the active chain.
+/// As blocks arrive, the active chain moves to the chain with the most work
+/// whose blocks are all stored. To find it without searching the block index,
+/// blocks off the active chain that could be connected, being stored along
+/// with every ancestor back to the active chain and not known to be invalid,
+/// are kept ordered by work as candidates. Stored blocks still waiting on an
+/// ancestor's data are kept by parent until it arrives.
+///
+/// With a prune target configured, the data of blocks buried deeper than
+/// `MIN_BLOCKS_TO_KEEP`, and already indexed by every enabled index, is
+/// deleted once the block store outgrows the target.
 pub struct ActiveChain {
-  blocks: Vec<Block>,
+  params: ChainParams,
+  prune_target: Option<u64>,
+  store: BlockStore,
+  index: BlockIndex,
+  chainstate: Chainstate,
+  active: Vec<[u8; SHA256_HASH_SIZE]>,
+  best_header: [u8; SHA256_HASH_SIZE],
+  candidates: BTreeSet<WorkKey>,
+  unlinked: HashMap<[u8; SHA256_HASH_SIZE], Vec<[u8; SHA256_HASH_SIZE]>>,
+  orphans: OrphanPool,
+  missing_parent_hook: Option<MissingParentHook>,
+  events: Option<ValidationEvents>,
+  txindex: Option<TxIndex>,
+  addrindex: Option<AddrIndex>,
+  filterindex: Option<FilterIndex>,
 }
 
 impl ActiveChain {
-  /// Initialize and return the active chain with the genesis block.
-  pub fn new() -> Self {
-    Self { blocks: vec![Block::genesis()] }
+  /// Open the active chain stored under the configured data directory,
+  /// validated under the given chain parameters.
+  ///
+  /// The block index is read back from the block store and the active chain
+  /// rebuilt from its last block. A new data directory starts a chain with
+  /// the network's genesis block. The chainstate is then
+  /// repaired if its last flush was interrupted, and brought up to date with
+  /// the active chain.
+  pub fn open(params: ChainParams, config: &Config) -> Result<Self, Error> {
+    if let Some(target) = config.prune_target {
+      if target < MIN_PRUNE_TARGET {
+        return Err(Error::PruneTargetTooSmall {
+          target,
+          minimum: MIN_PRUNE_TARGET,
+        });
+      }
+      if config.txindex {
+        return Err(Error::TxIndexWithPrune);
+      }
+    }
+
+    let mut store = BlockStore::open(&config.data_dir)?;
+    let mut index = BlockIndex::new();
+    let best_block = match store.best_block()? {
+      Some(hash) => {
+        for entry in store.load_index()? {
+          index.insert_entry(entry);
+        }
+        hash
+      },
+      None => {
+        let genesis = Block::genesis(params.genesis_timestamp);
+        let hash = genesis.hash();
+        index.insert_entry(BlockIndexEntry::new(
+          genesis.header().clone(),
+          0,
+          genesis.relative_work(),
+          BlockStatus::ValidBlock,
+        ));
+        let pos = store.write_block(&genesis, 0)?;
+        let entry = index
+          .set_pos(&hash, Some(pos))
+          .expect("Genesis block missing from block index");
+        store.write_index(&[entry], Some(hash))?;
+        hash
+      },
+    };
+
+    // Walk back from the last block to genesis to rebuild the active chain.
+    let mut active = Vec::new();
+    let mut hash = best_block;
+    loop {
+      let entry = index.get(&hash).ok_or_else(|| {
+        storage::error::Error::Corrupt(format!(
+          "active chain block {} missing from block index",
+          hex(&hash)
+        ))
+      })?;
+      active.push(hash);
+      if entry.height() == 0 {
+        break;
+      }
+      hash = entry.header().prev_block_hash();
+    }
+    active.reverse();
+
+    let chainstate =
+      Chainstate::open(&config.data_dir, config.coins_cache_size)?;
+    let txindex = match config.txindex {
+      true => Some(TxIndex::open(&config.data_dir)?),
+      false => None,
+    };
+    let addrindex = match config.addrindex {
+      true => Some(AddrIndex::open(&config.data_dir)?),
+      false => None,
+    };
+    let filterindex = match config.blockfilterindex {
+      true => Some(FilterIndex::open(&config.data_dir)?),
+      false => None,
+    };
+    let mut chain = Self {
+      params,
+      prune_target: config.prune_target,
+      store,
+      index,
+      chainstate,
+      active,
+      best_header: best_block,
+      candidates: BTreeSet::new(),
+      unlinked: HashMap::new(),
+      orphans: OrphanPool::new(),
+      missing_parent_hook: None,
+      events: None,
+      txindex,
+      addrindex,
+      filterindex,
+    };
+    chain.best_header = chain.find_best_header();
+    chain.rebuild_candidates();
+    chain.recover_chainstate()?;
+    Ok(chain)
+  }
+
+  /// Bring the chainstate in line with the active chain's last block.
+  ///
+  /// An interrupted flush leaves coins from both the block it was flushing
+  /// from and the block it was flushing to. Since applying a block's changes
+  /// twice is harmless, replaying every block between the two repairs it.
+  /// The chainstate also lags the active chain by whatever was not flushed
+  /// before shutdown, so the remaining blocks are then replayed as well.
+  fn recover_chainstate(&mut self) -> Result<(), Error> {
+    if let Some((to, from)) = self.chainstate.head_blocks()? {
+      logln!("Repairing interrupted chainstate flush to {}", hex(&to));
+      self.replay_blocks(from, to)?;
+    }
+    let tip = self.last_block_hash();
+    if self.chainstate.best_block() != Some(tip) {
+      self.replay_blocks(self.chainstate.best_block(), tip)?;
+    }
+    self.chainstate.flush()?;
+    Ok(())
+  }
+
+  /// Move the chainstate from block `from` (or no block at all) to block
+  /// `to`, disconnecting blocks back to their last common ancestor and then
+  /// connecting blocks up to `to`, without validating them.
+  fn replay_blocks(
+    &mut self,
+    from: Option<[u8; SHA256_HASH_SIZE]>,
+    to: [u8; SHA256_HASH_SIZE],
+  ) -> Result<(), Error> {
+    let fork = match from {
+      Some(from) => Some(self.find_fork(from, to)?),
+      None => None,
+    };
+
+    let mut hash = from;
+    while hash.is_some() && hash != fork {
+      let entry = self.stored_entry(&hash.unwrap())?;
+      let block = self
+        .store
+        .read_block(self.stored_pos(&entry, entry.pos())?)?;
+      let undo = self
+        .store
+        .read_undo(self.stored_pos(&entry, entry.undo_pos())?)?;
+      self.disconnect_txns(&block, undo)?;
+      hash = (entry.height() > 0).then_some(block.prev_block_hash());
+      self.chainstate.set_best_block(hash);
+      self.flush_if_needed()?;
+    }
+
+    let mut path = Vec::new();
+    let mut hash = Some(to);
+    while hash.is_some() && hash != fork {
+      let entry = self.stored_entry(&hash.unwrap())?;
+      path.push(entry.hash());
+      hash = (entry.height() > 0).then_some(entry.header().prev_block_hash());
+    }
+    for hash in path.into_iter().rev() {
+      let entry = self.stored_entry(&hash)?;
+      let block = self
+        .store
+        .read_block(self.stored_pos(&entry, entry.pos())?)?;
+      self.connect_txns(&block, entry.height())?;
+      self.chainstate.set_best_block(Some(hash));
+      self.flush_if_needed()?;
+    }
+    Ok(())
+  }
+
+  /// Return the last common ancestor of the two blocks with the given hashes.
+  fn find_fork(
+    &self,
+    a: [u8; SHA256_HASH_SIZE],
+    b: [u8; SHA256_HASH_SIZE],
+  ) -> Result<[u8; SHA256_HASH_SIZE], Error> {
+    match self.index.find_fork(&a, &b) {
+      Some(fork) => Ok(fork.hash()),
+      None => Err(Error::StorageError(storage::error::Error::Corrupt(
+        "blocks share no common ancestor".into(),
+      ))),
+    }
+  }
+
+  /// Get a copy of the block index entry of a block the stores refer to.
+  fn stored_entry(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<BlockIndexEntry, Error> {
+    match self.index.get(hash) {
+      Some(entry) => Ok(entry.clone()),
+      None => Err(Error::StorageError(storage::error::Error::Corrupt(
+        format!("block {} missing from block index", hex(hash)),
+      ))),
+    }
+  }
+
+  /// Return the given position of a stored block's data, or an error if it is
+  /// missing: `BlockPruned` if it has been pruned.
+  fn stored_pos(
+    &self,
+    entry: &BlockIndexEntry,
+    pos: Option<BlockPos>,
+  ) -> Result<BlockPos, Error> {
+    match pos {
+      Some(pos) => Ok(pos),
+      None if self.store.have_pruned() => Err(Error::BlockPruned(entry.hash())),
+      None => Err(Error::StorageError(storage::error::Error::Corrupt(
+        format!(
+          "data of block {} missing from block store",
+          hex(&entry.hash())
+        ),
+      ))),
+    }
+  }
+
+  /// Write the chainstate's cache to disk, e.g. before shutting down.
+  pub fn flush(&mut self) -> Result<(), Error> {
+    Ok(self.chainstate.flush()?)
+  }
+
+  /// Bring indexes behind the active chain up to date, in the order they are
+  /// enabled, indexing or unindexing at most `max_blocks` blocks in all.
+  /// Return whether every index has caught up.
+  ///
+  /// An index whose last block is no longer on the active chain, e.g. after a
+  /// reorg while it was behind, first has blocks disconnected back to where
+  /// it rejoins the active chain.
+  pub fn sync_indexes(&mut self, max_blocks: usize) -> Result<bool, Error> {
+    let tip = self.last_block_hash();
+    let mut steps = 0;
+    for i in 0..self.indexes().count() {
+      let mut synced = true;
+      loop {
+        let best_block = self.indexes().nth(i).unwrap().best_block();
+        if best_block == Some(tip) {
+          break;
+        }
+        if steps == max_blocks {
+          return Ok(false);
+        }
+        synced = false;
+        steps += 1;
+
+        // Step towards the tip along the active chain, or back towards it.
+        let (hash, connect) = match best_block {
+          None => (self.active[0], true),
+          Some(hash) => match self.index.get(&hash) {
+            Some(entry) if self.is_active(entry) => {
+              (self.active[entry.height() as usize + 1], true)
+            },
+            _ => (hash, false),
+          },
+        };
+        let entry = self.stored_entry(&hash)?;
+        let block = match self.block(&hash)? {
+          Some(block) => block,
+          None => return Err(Error::BlockPruned(hash)),
+        };
+        let undo = match entry.undo_pos() {
+          Some(pos) => self.store.read_undo(pos)?,
+          None => BlockUndo::default(),
+        };
+        let index = self.indexes().nth(i).unwrap();
+        match connect {
+          true => index.connect_block(&block, entry.height(), &undo)?,
+          false => index.disconnect_block(&block, entry.height(), &undo)?,
+        }
+      }
+      if !synced {
+        let index = self.indexes().nth(i).unwrap();
+        logln!("{} synced to height {}", index.name(), self.height());
+      }
+    }
+    Ok(true)
+  }
+
+  /// Return the enabled indexes.
+  fn indexes(&mut self) -> impl Iterator<Item = &mut dyn ChainIndex> {
+    let txindex = self
+      .txindex
+      .iter_mut()
+      .map(|index| index as &mut dyn ChainIndex);
+    let addrindex = self
+      .addrindex
+      .iter_mut()
+      .map(|index| index as &mut dyn ChainIndex);
+    let filterindex = self
+      .filterindex
+      .iter_mut()
+      .map(|index| index as &mut dyn ChainIndex);
+    txindex.chain(addrindex).chain(filterindex)
+  }
+
+  /// Write the chainstate's cache to disk if it has outgrown its configured
+  /// size.
+  fn flush_if_needed(&mut self) -> Result<(), Error> {
+    if self.chainstate.needs_flush() {
+      self.chainstate.flush()?;
+    }
+    Ok(())
+  }
+
+  /// Set the hook used to request the missing parent of each new orphan.
+  pub fn set_missing_parent_hook(&mut self, hook: MissingParentHook) {
+    self.missing_parent_hook = Some(hook);
+  }
+
+  /// Set the handle through which changes to the active chain are published.
+  pub fn set_validation_events(&mut self, events: ValidationEvents) {
+    self.events = Some(events);
+  }
+
+  /// Publish the event made by `event`, if a handle to publish through is
+  /// set.
+  fn notify(&self, event: impl FnOnce() -> ValidationEvent) {
+    if let Some(events) = &self.events {
+      events.publish(event());
+    }
+  }
+
+  /// Return the pool of blocks waiting on an unknown parent.
+  pub fn orphans(&self) -> &OrphanPool {
+    &self.orphans
+  }
+
+  /// Return the chain parameters this chain is validated under.
+  pub fn params(&self) -> &ChainParams {
+    &self.params
+  }
+
+  /// Return every known chain tip: each block in the block index with no
+  /// known children, and the active chain's last block, highest first.
+  pub fn chain_tips(&self) -> Vec<ChainTip> {
+    let parents: HashSet<_> = self
+      .index
+      .entries()
+      .map(|entry| entry.header().prev_block_hash())
+      .collect();
+    let active_tip = self.last_block_hash();
+    let mut tips: Vec<_> = self
+      .index
+      .entries()
+      .filter(|entry| {
+        entry.hash() == active_tip || !parents.contains(&entry.hash())
+      })
+      .map(|entry| {
+        let fork_height = self
+          .index
+          .find_fork(&entry.hash(), &active_tip)
+          .map_or(0, |fork| fork.height());
+        ChainTip {
+          height: entry.height(),
+          hash: entry.hash(),
+          branch_len: entry.height() - fork_height,
+          status: self.chain_tip_status(entry, fork_height),
+        }
+      })
+      .collect();
+    tips.sort_by(|a, b| b.height.cmp(&a.height).then(a.hash.cmp(&b.hash)));
+    tips
+  }
+
+  /// Return the status of a chain tip whose branch forks from the active
+  /// chain at `fork_height`.
+  fn chain_tip_status(
+    &self,
+    tip: &BlockIndexEntry,
+    fork_height: u32,
+  ) -> ChainTipStatus {
+    if self.is_active(tip) {
+      return ChainTipStatus::Active;
+    }
+    if tip.status().is_invalid() {
+      return ChainTipStatus::Invalid;
+    }
+    let mut entry = Some(tip);
+    while let Some(current) = entry.filter(|e| e.height() > fork_height) {
+      if current.pos().is_none() {
+        return ChainTipStatus::HeadersOnly;
+      }
+      entry = self.index.get(&current.header().prev_block_hash());
+    }
+    match tip.status() {
+      BlockStatus::ValidBlock => ChainTipStatus::ValidFork,
+      _ => ChainTipStatus::ValidHeaders,
+    }
+  }
+
+  /// Return the index of every header known to this chain.
+  pub fn index(&self) -> &BlockIndex {
+    &self.index
+  }
+
+  /// Validate and push a block received from `peer`. `adjusted_time` is the
+  /// current network-adjusted time, against which the block's timestamp is
+  /// checked.
+  ///
+  /// A block whose parent is unknown is stored as an orphan, and the missing
+  /// parent requested through the missing-parent hook. Otherwise the block's
+  /// header is accepted and its data stored, along with any orphans waiting
+  /// on it, and the chain moves to the most-work chain whose blocks are all
+  /// stored.
+  pub fn validate_and_push(
+    &mut self,
+    block: Block,
+    peer: Option<Ipv4Addr>,
+    adjusted_time: u32,
+  ) -> Result<PushOutcome, Error> {
+    let hash = block.hash();
+    let have_data = self.index.get(&hash).is_some_and(|e| e.pos().is_some());
+    if have_data || self.orphans.contains(&hash) {
+      return Err(Error::DuplicateBlock);
+    }
+
+    if !self.index.contains(&block.prev_block_hash()) {
+      // Only hold on to blocks that cost real work to make, and whose data is
+      // what their header commits to, so that a mutated copy cannot take the
+      // real block's place.
+      block.verify_nonce().map_err(Error::BlockError)?;
+      block.verify_merkle_root().map_err(Error::BlockError)?;
+      self.orphans.insert(block, peer, adjusted_time);
+      if let (Some(hook), Some(missing)) = (
+        self.missing_parent_hook.as_mut(),
+        self.orphans.missing_ancestor(&hash),
+      ) {
+        hook(peer, missing);
+      }
+      return Ok(PushOutcome::Orphaned);
+    }
+
+    self.accept_block(block, adjusted_time)?;
+    let mut parents = vec![hash];
+    while let Some(parent) = parents.pop() {
+      for orphan in self.orphans.take_children(&parent) {
+        let orphan_hash = orphan.block.hash();
+        match self.accept_block(orphan.block, adjusted_time) {
+          Ok(_) => parents.push(orphan_hash),
+          Err(err) => {
+            logln!("Dropped orphan block {}: {}", hex(&orphan_hash), err)
+          },
+        }
+      }
+    }
+
+    match self.activate_best_chain()? {
+      0 => Ok(PushOutcome::Stored),
+      connected => Ok(PushOutcome::Connected(connected)),
+    }
+  }
+
+  /// Validate a run of headers, each the child of the one before, and add them
+  /// to the block index. Headers already indexed are skipped.
+  ///
+  /// Headers accepted before an invalid one are kept. See `accept_header` for
+  /// the checks made.
+  pub fn accept_headers(
+    &mut self,
+    headers: &[Header],
+    adjusted_time: u32,
+  ) -> Result<(), Error> {
+    let mut accepted = Vec::new();
+    let mut result = Ok(());
+    for (i, header) in headers.iter().enumerate() {
+      if i > 0 && header.prev_block_hash() != headers[i - 1].hash() {
+        result = Err(Error::NonContinuousHeaders);
+        break;
+      }
+      match self.accept_header(header, adjusted_time) {
+        Ok(Some(hash)) => accepted.push(hash),
+        Ok(None) => {},
+        Err(err) => {
+          result = Err(err);
+          break;
+        },
+      }
+    }
+
+    let entries: Vec<_> = accepted
+      .iter()
+      .filter_map(|hash| self.index.get(hash))
+      .collect();
+    self.store.write_index(&entries, None)?;
+    result
+  }
+
+  /// Validate a header against its parent and add it to the block index, in
+  /// memory only, returning its hash if it was not already indexed.
+  ///
+  /// Returns `InvalidBlock` if the header is known to be invalid,
+  /// `MissingParent` if its parent is not indexed, `InvalidPrevBlock` if its
+  /// parent is invalid, `CheckpointMismatch` if it differs from the
+  /// checkpoint at its height, `ForkBelowCheckpoint` if it forks from the
+  /// chain below the last checkpoint in the block index, `TimestampTooOld` or
+  /// `TimestampTooNew` if its timestamp is out of range, `IncorrectBits` if
+  /// its `bits` differ from those required by the difficulty adjustment
+  /// rules, and a `BlockError` if its proof-of-work is invalid.
+  fn accept_header(
+    &mut self,
+    header: &Header,
+    adjusted_time: u32,
+  ) -> Result<Option<[u8; SHA256_HASH_SIZE]>, Error> {
+    let hash = header.hash();
+    if let Some(entry) = self.index.get(&hash) {
+      return match entry.status() {
+        status if status.is_invalid() => Err(Error::InvalidBlock(hash)),
+        _ => Ok(None),
+      };
+    }
+    let parent = match self.index.get(&header.prev_block_hash()) {
+      Some(parent) => parent,
+      None => return Err(Error::MissingParent),
+    };
+    if parent.status().is_invalid() {
+      return Err(Error::InvalidPrevBlock);
+    }
+    let height = parent.height() + 1;
+    if let Some(checkpoint) = self.params.checkpoint(height) {
+      if hash != checkpoint {
+        return Err(Error::CheckpointMismatch(height));
+      }
+    }
+    if let Some(checkpoint) = self.last_checkpoint() {
+      if height < checkpoint {
+        return Err(Error::ForkBelowCheckpoint { height, checkpoint });
+      }
+    }
+    self.check_timestamp(header, adjusted_time)?;
+
+    // Check the claimed difficulty before the proof-of-work, which is only
+    // meaningful against the required target.
+    let expected = self.next_bits_after(&header.prev_block_hash());
+    let found = header.bits();
+    if found != expected {
+      return Err(Error::IncorrectBits { expected, found });
+    }
+    if !header.check_pow() {
+      return Err(Error::BlockError(block::Error::NonceFailedVerification));
+    }
+
+    let chain_work = self
+      .index
+      .insert(header.clone())
+      .expect("Parent of accepted header missing from block index")
+      .chain_work();
+    if chain_work > self.best_header().chain_work() {
+      self.best_header = hash;
+    }
+    Ok(Some(hash))
+  }
+
+  /// Accept a block's header, check that it commits to the block's
+  /// transactions and that they are not a mutated copy of others (see
+  /// `Block::verify_merkle_root`), and store the block's data, without
+  /// connecting it.
+  fn accept_block(
+    &mut self,
+    block: Block,
+    adjusted_time: u32,
+  ) -> Result<(), Error> {
+    let hash = block.hash();
+    self.accept_headers(std::slice::from_ref(block.header()), adjusted_time)?;
+    block.verify_merkle_root().map_err(Error::BlockError)?;
+
+    let height = self.index.get(&hash).map(|entry| entry.height());
+    let pos = self.store.write_block(&block, height.unwrap_or_default())?;
+    let entry = self
+      .index
+      .set_pos(&hash, Some(pos))
+      .expect("Accepted block missing from block index");
+    self.store.write_index(&[entry], None)?;
+    self.add_candidate(&hash);
+    Ok(())
+  }
+
+  /// Move the active chain to the chain with the most work among those whose
+  /// blocks are all stored, disconnecting and connecting blocks as needed,
+  /// and return the number of blocks connected.
+  ///
+  /// A block that fails to connect is marked invalid, and the next best chain
+  /// tried instead. A block whose stored data turns out not to be what its
+  /// header commits to is not, since that says nothing about the block; its
+  /// data is discarded instead, to be stored again. Once the tip has moved,
+  /// `TipUpdated` is published after the events of the blocks disconnected
+  /// and connected.
+  pub fn activate_best_chain(&mut self) -> Result<usize, Error> {
+    let start = self.last_block_hash();
+    let result = self.activate_best_chain_steps();
+    self.notify_tip_moved(&start);
+    result
+  }
+
+  /// Publish `TipUpdated` if the tip is no longer the block with the given
+  /// hash.
+  fn notify_tip_moved(&self, start: &[u8; SHA256_HASH_SIZE]) {
+    let tip = self.tip();
+    if tip.hash() != *start {
+      let (hash, height) = (tip.hash(), tip.height());
+      self.notify(|| ValidationEvent::TipUpdated { hash, height });
+    }
+  }
+
+  /// Mark a block and its descendants invalid by hand, disconnecting it from
+  /// the active chain if need be, and move to the best chain without it. The
+  /// mark is kept until `reconsider_block` is called on the block or one of
+  /// its ancestors or descendants.
+  ///
+  /// Returns `UnknownBlock` if the block is not indexed, and
+  /// `InvalidateGenesis` for the genesis block.
+  pub fn invalidate_block(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<(), Error> {
+    let entry = self.index.get(hash).ok_or(Error::UnknownBlock(*hash))?;
+    if entry.height() == 0 {
+      return Err(Error::InvalidateGenesis);
+    }
+    let start = self.last_block_hash();
+    let result = self.invalidate_block_steps(hash);
+    self.notify_tip_moved(&start);
+    result
+  }
+
+  /// Make the steps of `invalidate_block`.
+  fn invalidate_block_steps(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<(), Error> {
+    let entry = self.stored_entry(hash)?;
+    if self.is_active(&entry) {
+      while self.height() >= entry.height() {
+        self.disconnect_tip()?;
+      }
+    }
+    logln!("Invalidating block {}", hex(hash));
+    self.mark_invalid(hash)?;
+    self.activate_best_chain_steps()?;
+    Ok(())
+  }
+
+  /// Clear the invalid marks of a block, its ancestors and its descendants,
+  /// whether set by hand or by a failure to validate, and move to the best
+  /// chain among them and the rest. Blocks that really are invalid are marked
+  /// again once they fail to connect.
+  ///
+  /// Returns `UnknownBlock` if the block is not indexed.
+  pub fn reconsider_block(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<(), Error> {
+    let entry = self.index.get(hash).ok_or(Error::UnknownBlock(*hash))?;
+    let mut reset = self.index.descendants(hash);
+    let mut current = Some(entry);
+    while let Some(entry) = current {
+      reset.push(entry.hash());
+      current = self.index.get(&entry.header().prev_block_hash());
+    }
+
+    let mut entries = Vec::new();
+    for hash in reset {
+      let invalid = self
+        .index
+        .get(&hash)
+        .is_some_and(|entry| entry.status().is_invalid());
+      if invalid {
+        let entry = self
+          .index
+          .set_status(&hash, BlockStatus::ValidHeader)
+          .expect("Reconsidered block missing from block index");
+        entries.push(entry.clone());
+      }
+    }
+    self
+      .store
+      .write_index(&entries.iter().collect::<Vec<_>>(), None)?;
+    logln!("Reconsidering block {}", hex(hash));
+    self.best_header = self.find_best_header();
+    self.rebuild_candidates();
+    self.activate_best_chain()?;
+    Ok(())
+  }
+
+  /// Make the steps of `activate_best_chain`.
+  fn activate_best_chain_steps(&mut self) -> Result<usize, Error> {
+    let mut connected = 0;
+    while let Some(target) = self.best_connectable() {
+      let fork = self.find_fork(self.last_block_hash(), target)?;
+      while self.last_block_hash() != fork {
+        self.disconnect_tip()?;
+      }
+
+      for hash in self.branch(&fork, &target)? {
+        let entry = self.stored_entry(&hash)?;
+        let block = self
+          .store
+          .read_block(self.stored_pos(&entry, entry.pos())?)?;
+
+        // Data that is not what the block's header commits to says nothing
+        // about the block, so it is discarded to be downloaded again rather
+        // than the block marked invalid.
+        if block.hash() != hash || block.verify_merkle_root().is_err() {
+          logln!("Discarding mutated data of block {}", hex(&hash));
+          self.discard_block_data(&hash)?;
+          break;
+        }
+        match self.connect(&block) {
+          Ok(()) => connected += 1,
+          Err(Error::StorageError(err)) => return Err(err.into()),
+          Err(err) => {
+            logln!("Marking block {} invalid: {}", hex(&hash), err);
+            self.mark_invalid(&hash)?;
+            break;
+          },
+        }
+      }
+    }
+    Ok(connected)
+  }
+
+  /// Return the candidate with the most work, if it has more work than the
+  /// active chain.
+  fn best_connectable(&self) -> Option<[u8; SHA256_HASH_SIZE]> {
+    let tip_work = self.tip().chain_work();
+    self
+      .candidates
+      .last()
+      .filter(|key| key.0 > tip_work)
+      .map(|key| key.1)
+  }
+
+  /// Make the stored block with the given hash a candidate, along with the
+  /// stored blocks that were waiting on it, if its parent is on the active
+  /// chain or a candidate. Otherwise hold it until its parent becomes one.
+  fn add_candidate(&mut self, hash: &[u8; SHA256_HASH_SIZE]) {
+    let parent = match self.index.get(hash) {
+      Some(entry) => entry.header().prev_block_hash(),
+      None => return,
+    };
+    let linked = self.index.get(&parent).is_some_and(|parent| {
+      self.is_active(parent) || self.candidates.contains(&WorkKey::of(parent))
+    });
+    if !linked {
+      self.unlinked.entry(parent).or_default().push(*hash);
+      return;
+    }
+
+    let mut hashes = vec![*hash];
+    while let Some(hash) = hashes.pop() {
+      let entry = match self.index.get(&hash) {
+        Some(entry) if !entry.status().is_invalid() => entry,
+        _ => continue,
+      };
+      if !self.is_active(entry) {
+        self.candidates.insert(WorkKey::of(entry));
+      }
+      hashes.extend(self.unlinked.remove(&hash).unwrap_or_default());
+    }
+  }
+
+  /// Forget the stored data of the block with the given hash, so that it
+  /// must be stored again before it is connected. Its stored descendants
+  /// wait on it until then.
+  fn discard_block_data(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<(), Error> {
+    self.index.set_undo_pos(hash, None);
+    let entry = self
+      .index
+      .set_pos(hash, None)
+      .expect("Discarded block missing from block index");
+    self.candidates.remove(&WorkKey::of(entry));
+    self.store.write_index(&[entry], None)?;
+    for descendant in self.index.descendants(hash) {
+      let entry = self
+        .index
+        .get(&descendant)
+        .expect("Descendant missing from block index");
+      if self.candidates.remove(&WorkKey::of(entry)) {
+        let parent = entry.header().prev_block_hash();
+        self.unlinked.entry(parent).or_default().push(descendant);
+      }
+    }
+    Ok(())
+  }
+
+  /// Rebuild the candidates and the blocks waiting on an ancestor's data from
+  /// the block index.
+  fn rebuild_candidates(&mut self) {
+    self.candidates.clear();
+    self.unlinked.clear();
+    let mut stored: Vec<_> = self
+      .index
+      .entries()
+      .filter(|entry| {
+        entry.pos().is_some()
+          && !entry.status().is_invalid()
+          && !self.is_active(entry)
+      })
+      .map(|entry| (entry.height(), entry.hash()))
+      .collect();
+    stored.sort_unstable();
+    for (_, hash) in stored {
+      self.add_candidate(&hash);
+    }
+  }
+
+  /// Return the hashes of the blocks after `ancestor` up to and including
+  /// `hash`, in order.
+  fn branch(
+    &self,
+    ancestor: &[u8; SHA256_HASH_SIZE],
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Vec<[u8; SHA256_HASH_SIZE]>, Error> {
+    let mut branch = Vec::new();
+    let mut entry = self.stored_entry(hash)?;
+    while entry.hash() != *ancestor {
+      branch.push(entry.hash());
+      entry = self.stored_entry(&entry.header().prev_block_hash())?;
+    }
+    branch.reverse();
+    Ok(branch)
   }
 
-  /// Validate and push a block to the end of this chain.
+  /// Validate a stored block extending the end of this chain and connect it.
   ///
   /// Returns `IncorrectPrevBlockHash` if block to be pushed has value of
-  /// `prev_block_hash` which does not match this chain's `last_hash`.
-  pub fn validate_and_push(&mut self, block: Block) -> Result<(), Error> {
-    match block.verify_nonce() {
-      Ok(_) => {
-        if block.prev_block_hash() == self.last_block_hash() {
-          self.blocks.push(block);
-          Ok(())
-        } else {
-          Err(Error::IncorrectPrevBlockHash)
+  /// `prev_block_hash` which does not match this chain's `last_hash`. Its
+  /// header must already be accepted, and its transactions must be valid
+  /// against the chain's UTXO set (see `check_txns`).
+  fn connect(&mut self, block: &Block) -> Result<(), Error> {
+    if block.prev_block_hash() != self.last_block_hash() {
+      return Err(Error::IncorrectPrevBlockHash);
+    }
+    let hash = block.hash();
+    let height = self.height() + 1;
+    let undo = self.check_txns(block, height)?;
+    if !self.is_assumed_valid(&hash) {
+      self.check_scripts(block, &undo)?;
+    }
+
+    // Store the undo data, then mark the block valid and make it the last
+    // block in one batch. The chainstate follows, and is flushed once its
+    // cache grows too large; if the node stops first, the block is replayed
+    // into it on the next start.
+    let entry = self.stored_entry(&hash)?;
+    if entry.undo_pos().is_none() {
+      let pos = self.stored_pos(&entry, entry.pos())?;
+      let undo_pos = self.store.write_undo(pos.file, &undo)?;
+      self.index.set_undo_pos(&hash, Some(undo_pos));
+    }
+    let entry = self
+      .index
+      .set_status(&hash, BlockStatus::ValidBlock)
+      .expect("Connected block missing from block index");
+    self.candidates.remove(&WorkKey::of(entry));
+    self.store.write_index(&[entry], Some(hash))?;
+    self.active.push(hash);
+
+    self.connect_txns(block, height)?;
+    self.chainstate.set_best_block(Some(hash));
+    for index in self.indexes() {
+      if index.best_block() == Some(block.prev_block_hash()) {
+        if let Err(err) = index.connect_block(block, height, &undo) {
+          logln!("Failed to update {}: {}", index.name(), err);
+        }
+      }
+    }
+    self.notify(|| ValidationEvent::BlockConnected {
+      block: Arc::new(block.clone()),
+      height,
+    });
+    self.flush_if_needed()?;
+    self.prune_if_needed()
+  }
+
+  /// Disconnect the last block of this chain, restoring the coins it spent
+  /// from its undo data.
+  fn disconnect_tip(&mut self) -> Result<(), Error> {
+    let entry = self.stored_entry(&self.last_block_hash())?;
+    if entry.height() == 0 {
+      return Err(Error::IncorrectPrevBlockHash);
+    }
+    let block = self
+      .store
+      .read_block(self.stored_pos(&entry, entry.pos())?)?;
+    let undo = self
+      .store
+      .read_undo(self.stored_pos(&entry, entry.undo_pos())?)?;
+    for index in self.indexes() {
+      if index.best_block() == Some(entry.hash()) {
+        if let Err(err) = index.disconnect_block(&block, entry.height(), &undo)
+        {
+          logln!("Failed to update {}: {}", index.name(), err);
         }
+      }
+    }
+    self.disconnect_txns(&block, undo)?;
+
+    self.active.pop();
+    self.candidates.insert(WorkKey::of(&entry));
+    let parent = self.last_block_hash();
+    self.store.write_index(&[], Some(parent))?;
+    self.chainstate.set_best_block(Some(parent));
+    let height = entry.height();
+    self.notify(|| ValidationEvent::BlockDisconnected {
+      block: Arc::new(block),
+      height,
+    });
+    self.flush_if_needed()
+  }
+
+  /// Mark the block with the given hash invalid and its descendants invalid
+  /// by ancestry, and find the new best header if it was among them.
+  fn mark_invalid(
+    &mut self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<(), Error> {
+    let mut entries = Vec::new();
+    if let Some(entry) = self.index.set_status(hash, BlockStatus::Invalid) {
+      entries.push(entry.clone());
+    }
+    for descendant in self.index.descendants(hash) {
+      if let Some(entry) = self
+        .index
+        .set_status(&descendant, BlockStatus::InvalidChild)
+      {
+        entries.push(entry.clone());
+      }
+    }
+    for entry in &entries {
+      self.candidates.remove(&WorkKey::of(entry));
+      self.unlinked.remove(&entry.hash());
+    }
+    self
+      .store
+      .write_index(&entries.iter().collect::<Vec<_>>(), None)?;
+    if self.best_header().status().is_invalid() {
+      self.best_header = self.find_best_header();
+    }
+    Ok(())
+  }
+
+  /// Return the header with the most work that is not invalid. Since the
+  /// descendants of invalid blocks are marked invalid too, it has no invalid
+  /// ancestor.
+  fn find_best_header(&self) -> [u8; SHA256_HASH_SIZE] {
+    self
+      .index
+      .entries()
+      .filter(|entry| !entry.status().is_invalid())
+      .max_by_key(|entry| WorkKey::of(entry))
+      .map_or(self.last_block_hash(), |entry| entry.hash())
+  }
+
+  /// Delete the oldest block files until the block store fits in the prune
+  /// target, if one is configured. Only files whose blocks are all buried at
+  /// least `MIN_BLOCKS_TO_KEEP` deep are deleted, and the chainstate is
+  /// flushed first so that it never needs their blocks to be replayed.
+  fn prune_if_needed(&mut self) -> Result<(), Error> {
+    let target = match self.prune_target {
+      Some(target) => target,
+      None => return Ok(()),
+    };
+    let mut usage = self.store.disk_usage();
+    if usage <= target || self.height() < MIN_BLOCKS_TO_KEEP {
+      return Ok(());
+    }
+
+    // Keep the blocks indexes behind the active chain have yet to index.
+    let mut max_height = self.height() - MIN_BLOCKS_TO_KEEP;
+    let tip = self.last_block_hash();
+    let best_blocks: Vec<_> =
+      self.indexes().map(|index| index.best_block()).collect();
+    for best_block in best_blocks {
+      let indexed_height = best_block
+        .and_then(|hash| self.index.find_fork(&hash, &tip))
+        .map_or(0, |fork| fork.height());
+      max_height = max_height.min(indexed_height);
+    }
+
+    let mut files = Vec::new();
+    for (file, info) in self.store.prunable_files(max_height) {
+      if usage <= target {
+        break;
+      }
+      usage -= info.size + info.undo_size;
+      files.push(file);
+    }
+    if files.is_empty() {
+      return Ok(());
+    }
+
+    self.chainstate.flush()?;
+    let pruned: Vec<_> = self
+      .index
+      .entries()
+      .filter(|entry| entry.pos().is_some_and(|pos| files.contains(&pos.file)))
+      .map(|entry| entry.hash())
+      .collect();
+    for hash in &pruned {
+      self.index.set_undo_pos(hash, None);
+      self.index.set_pos(hash, None);
+    }
+    let entries: Vec<_> = pruned
+      .iter()
+      .filter_map(|hash| self.index.get(hash))
+      .collect();
+    self.store.prune_files(&files, &entries)?;
+    logln!(
+      "Pruned {} block files holding {} blocks",
+      files.len(),
+      pruned.len()
+    );
+    Ok(())
+  }
+
+  /// Check a block's transactions against the chain's UTXO set, and return
+  /// the coins they spend.
+  ///
+  /// Returns `MisplacedCoinbase` if any transaction but the first is a
+  /// coinbase, `MissingInput` if an input spends a coin that does not exist
+  /// or was already spent, `DuplicateOutput` if an output would replace an
+  /// unspent coin, `ValueOverflow` if values overflow, and
+  /// `OutputsExceedInputs` if a transaction creates more value than it
+  /// spends.
+  fn check_txns(
+    &mut self,
+    block: &Block,
+    height: u32,
+  ) -> Result<BlockUndo, Error> {
+    // Transactions may spend coins created earlier in the same block.
+    let mut created = HashMap::new();
+    let mut spent = HashSet::new();
+    let mut undo = BlockUndo::default();
+    for (i, txn) in block.txns().iter().enumerate() {
+      if txn.is_coinbase() && i > 0 {
+        return Err(Error::MisplacedCoinbase);
+      }
+
+      let mut input_value = 0u64;
+      for txi in txn.txi_list() {
+        let outpoint = OutPoint::spent_by(txi);
+        let coin = match created.remove(&outpoint) {
+          Some(coin) => Some(coin),
+          None if spent.contains(&outpoint) => None,
+          None => self.chainstate.coin(&outpoint)?,
+        };
+        let coin = coin.ok_or(Error::MissingInput(outpoint))?;
+        spent.insert(outpoint);
+        input_value = input_value
+          .checked_add(coin.txo.value())
+          .ok_or(Error::ValueOverflow)?;
+        undo.spent.push(coin);
+      }
+
+      let txn_hash = txn.hash();
+      let mut output_value = 0u64;
+      for (index, txo) in txn.txo_list().iter().enumerate() {
+        let outpoint = OutPoint::new(txn_hash, index);
+        if created.contains_key(&outpoint)
+          || (!spent.contains(&outpoint)
+            && self.chainstate.have_coin(&outpoint)?)
+        {
+          return Err(Error::DuplicateOutput(outpoint));
+        }
+        output_value = output_value
+          .checked_add(txo.value())
+          .ok_or(Error::ValueOverflow)?;
+        let coin =
+          Coin { txo: txo.clone(), height, is_coinbase: txn.is_coinbase() };
+        created.insert(outpoint, coin);
+      }
+
+      if !txn.is_coinbase() && output_value > input_value {
+        return Err(Error::OutputsExceedInputs { input_value, output_value });
+      }
+    }
+    Ok(undo)
+  }
+
+  /// Check that every input of a block's transactions is signed by the owner
+  /// of the output it spends, as recorded in the block's undo data.
+  ///
+  /// TODO: Inputs do not carry a public key and a real signature yet (see
+  /// `Txi::prev_txn_sig`), so every input passes for now.
+  fn check_scripts(
+    &self,
+    _block: &Block,
+    _undo: &BlockUndo,
+  ) -> Result<(), Error> {
+    Ok(())
+  }
+
+  /// Return whether the block with the given hash is assumed to have valid
+  /// scripts and signatures: it is an ancestor of (or is) the chain
+  /// parameters' assume-valid block, which is in turn an ancestor of the best
+  /// header, so that the most-work chain we know of vouches for it. The best
+  /// header must also have at least the parameters' minimum chain work.
+  pub fn is_assumed_valid(&self, hash: &[u8; SHA256_HASH_SIZE]) -> bool {
+    let assume_valid = match self.params.assume_valid {
+      Some(assume_valid) => assume_valid,
+      None => return false,
+    };
+    if self.best_header().chain_work() < self.params.min_chain_work {
+      return false;
+    }
+    let (entry, assumed) =
+      match (self.index.get(hash), self.index.get(&assume_valid)) {
+        (Some(entry), Some(assumed)) => (entry, assumed),
+        _ => return false,
+      };
+    let is_ancestor = |descendant, ancestor: &BlockIndexEntry| {
+      self
+        .index
+        .ancestor(descendant, ancestor.height())
+        .is_some_and(|entry| entry.hash() == ancestor.hash())
+    };
+    is_ancestor(&assume_valid, entry) && is_ancestor(&self.best_header, assumed)
+  }
+
+  /// Return the height of the highest checkpoint in the block index, if any.
+  fn last_checkpoint(&self) -> Option<u32> {
+    self
+      .params
+      .checkpoints
+      .iter()
+      .filter(|(height, hash)| {
+        self
+          .index
+          .get(hash)
+          .is_some_and(|entry| entry.height() == *height)
+      })
+      .map(|(height, _)| *height)
+      .max()
+  }
+
+  /// Apply a block's transactions at the given height to the UTXO set:
+  /// spend their inputs and add their outputs.
+  fn connect_txns(&mut self, block: &Block, height: u32) -> Result<(), Error> {
+    for txn in block.txns() {
+      for txi in txn.txi_list() {
+        self.chainstate.spend_coin(&OutPoint::spent_by(txi))?;
+      }
+      let txn_hash = txn.hash();
+      for (index, txo) in txn.txo_list().iter().enumerate() {
+        let coin =
+          Coin { txo: txo.clone(), height, is_coinbase: txn.is_coinbase() };
+        self
+          .chainstate
+          .add_coin(OutPoint::new(txn_hash, index), coin)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Undo a block's transactions in the UTXO set, in reverse: remove their
+  /// outputs and restore the coins their inputs spent, as recorded in the
+  /// block's undo data.
+  fn disconnect_txns(
+    &mut self,
+    block: &Block,
+    mut undo: BlockUndo,
+  ) -> Result<(), Error> {
+    for txn in block.txns().iter().rev() {
+      let txn_hash = txn.hash();
+      for index in 0..txn.txo_list().len() {
+        self
+          .chainstate
+          .spend_coin(&OutPoint::new(txn_hash, index))?;
+      }
+      for txi in txn.txi_list().iter().rev() {
+        let coin = undo.spent.pop().ok_or_else(|| {
+          storage::error::Error::Corrupt(format!(
+            "undo data of block {} too short",
+            hex(&block.hash())
+          ))
+        })?;
+        self.chainstate.add_coin(OutPoint::spent_by(txi), coin)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Read the block with the given hash from the block store, if its data is
+  /// stored.
+  ///
+  /// Returns `BlockPruned` if the block was fully validated but its data has
+  /// since been pruned.
+  pub fn block(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Option<Block>, Error> {
+    match self.index.get(hash) {
+      Some(entry) => match entry.pos() {
+        Some(pos) => Ok(Some(self.store.read_block(pos)?)),
+        None if entry.status() == BlockStatus::ValidBlock => {
+          Err(Error::BlockPruned(*hash))
+        },
+        None => Ok(None),
       },
-      Err(err) => Err(Error::BlockError(err)),
+      None => Ok(None),
+    }
+  }
+
+  /// Get a transaction on the active chain by hash from the transaction index,
+  /// along with the block index entry of the block confirming it.
+  ///
+  /// Returns `TxIndexDisabled` if the index is not enabled. Transactions in
+  /// blocks the index has yet to catch up with are not found.
+  pub fn get_transaction(
+    &self,
+    txn_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Option<(Txn, &BlockIndexEntry)>, Error> {
+    let txindex = self.txindex.as_ref().ok_or(Error::TxIndexDisabled)?;
+    let location = match txindex.get(txn_hash)? {
+      Some(location) => location,
+      None => return Ok(None),
+    };
+    let entry = match self.index.get(&location.block_hash) {
+      Some(entry) if self.is_active(entry) => entry,
+      _ => return Ok(None),
+    };
+    let block = match self.block(&location.block_hash)? {
+      Some(block) => block,
+      None => return Err(Error::BlockPruned(location.block_hash)),
+    };
+    match block.txns().get(location.index as usize) {
+      Some(txn) if txn.hash() == *txn_hash => Ok(Some((txn.clone(), entry))),
+      _ => Err(Error::StorageError(storage::error::Error::Corrupt(
+        format!(
+          "transaction index entry for {} does not match its block",
+          hex(txn_hash)
+        ),
+      ))),
+    }
+  }
+
+  /// Return whether a transaction is on the active chain, according to the
+  /// transaction index.
+  ///
+  /// Returns `TxIndexDisabled` if the index is not enabled. Transactions in
+  /// blocks the index has yet to catch up with are not found.
+  pub fn is_confirmed(
+    &self,
+    txn_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<bool, Error> {
+    let txindex = self.txindex.as_ref().ok_or(Error::TxIndexDisabled)?;
+    Ok(match txindex.get(txn_hash)? {
+      Some(location) => self
+        .index
+        .get(&location.block_hash)
+        .is_some_and(|entry| self.is_active(entry)),
+      None => false,
+    })
+  }
+
+  /// Return the transactions on the active chain that paid or spent from the
+  /// address with the given pubkey hash, oldest first, from the address index.
+  ///
+  /// Returns `AddrIndexDisabled` if the index is not enabled. Like the other
+  /// address queries, this reflects the blocks the index has caught up with.
+  pub fn address_history(
+    &self,
+    pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  ) -> Result<Vec<AddrTxn>, Error> {
+    Ok(self.addrindex()?.history(pubkey_hash)?)
+  }
+
+  /// Return the unspent outputs paying the address with the given pubkey
+  /// hash, oldest first, from the address index.
+  pub fn address_utxos(
+    &self,
+    pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  ) -> Result<Vec<AddrUtxo>, Error> {
+    Ok(self.addrindex()?.utxos(pubkey_hash)?)
+  }
+
+  /// Return the total value of the unspent outputs paying the address with
+  /// the given pubkey hash, from the address index.
+  pub fn address_balance(
+    &self,
+    pubkey_hash: &[u8; RIPEMD160_HASH_SIZE],
+  ) -> Result<u64, Error> {
+    Ok(self.addrindex()?.balance(pubkey_hash)?)
+  }
+
+  /// Return the address index, or `AddrIndexDisabled` if not enabled.
+  fn addrindex(&self) -> Result<&AddrIndex, Error> {
+    self.addrindex.as_ref().ok_or(Error::AddrIndexDisabled)
+  }
+
+  /// Get the compact filter of the block with the given hash from the block
+  /// filter index, if the block is on the active chain and indexed.
+  ///
+  /// Returns `FilterIndexDisabled` if the index is not enabled.
+  pub fn block_filter(
+    &self,
+    block_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Option<BlockFilter>, Error> {
+    if !self.is_active_hash(block_hash) {
+      return Ok(None);
+    }
+    Ok(self.filterindex()?.filter(block_hash)?)
+  }
+
+  /// Get the filter header of the block with the given hash from the block
+  /// filter index, if the block is on the active chain and indexed.
+  pub fn block_filter_header(
+    &self,
+    block_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Result<Option<[u8; SHA256_HASH_SIZE]>, Error> {
+    if !self.is_active_hash(block_hash) {
+      return Ok(None);
+    }
+    Ok(self.filterindex()?.filter_header(block_hash)?)
+  }
+
+  /// Return the hashes of the blocks on the active chain from `start_height`
+  /// up to the tip whose filters match any of the given pubkey hashes, in
+  /// height order. Matches may be false positives, so callers should fetch
+  /// each block to find the outputs and spends they are looking for.
+  ///
+  /// Stops at the first block the index has yet to catch up with.
+  pub fn match_block_filters(
+    &self,
+    start_height: u32,
+    pubkey_hashes: &[[u8; RIPEMD160_HASH_SIZE]],
+  ) -> Result<Vec<[u8; SHA256_HASH_SIZE]>, Error> {
+    let filterindex = self.filterindex()?;
+    let mut matches = Vec::new();
+    for hash in self.active.iter().skip(start_height as usize) {
+      let filter = match filterindex.filter(hash)? {
+        Some(filter) => filter,
+        None => break,
+      };
+      let matched = filter.matches_any(pubkey_hashes).map_err(|err| {
+        storage::error::Error::Corrupt(format!(
+          "{} in block filter index for block {}",
+          err,
+          hex(hash)
+        ))
+      })?;
+      if matched {
+        matches.push(*hash);
+      }
+    }
+    Ok(matches)
+  }
+
+  /// Return the block filter index, or `FilterIndexDisabled` if not enabled.
+  fn filterindex(&self) -> Result<&FilterIndex, Error> {
+    self.filterindex.as_ref().ok_or(Error::FilterIndexDisabled)
+  }
+
+  /// Return whether the block with the given hash is on the active chain.
+  fn is_active_hash(&self, block_hash: &[u8; SHA256_HASH_SIZE]) -> bool {
+    self
+      .index
+      .get(block_hash)
+      .is_some_and(|entry| self.is_active(entry))
+  }
+
+  /// Check that a header has a timestamp later than its parent's median time
+  /// past, and no more than `MAX_FUTURE_BLOCK_TIME` ahead of `adjusted_time`.
+  /// The header's parent must be indexed.
+  pub fn check_timestamp(
+    &self,
+    header: &Header,
+    adjusted_time: u32,
+  ) -> Result<(), Error> {
+    let timestamp = header.timestamp();
+    let median_time_past = self
+      .index
+      .median_time_past(&header.prev_block_hash())
+      .ok_or(Error::MissingParent)?;
+    if timestamp <= median_time_past {
+      return Err(Error::TimestampTooOld { timestamp, median_time_past });
     }
+    if timestamp > adjusted_time.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+      return Err(Error::TimestampTooNew { timestamp, adjusted_time });
+    }
+    Ok(())
+  }
+
+  /// Get the median time past of the last block in this chain. A block
+  /// extending this chain must have a later timestamp.
+  pub fn median_time_past(&self) -> u32 {
+    self
+      .index
+      .median_time_past(&self.last_block_hash())
+      .expect("Last block of chain missing from block index")
+  }
+
+  /// Get the `bits` required of the next block on this chain, according to
+  /// the chain parameters' difficulty algorithm.
+  pub fn next_bits(&self) -> u32 {
+    self.next_bits_after(&self.last_block_hash())
+  }
+
+  /// Get the `bits` required of a child of the indexed block with the given
+  /// hash, which need not be on the active chain.
+  pub fn next_bits_after(&self, parent: &[u8; SHA256_HASH_SIZE]) -> u32 {
+    let entry = self
+      .index
+      .get(parent)
+      .expect("Parent of header missing from block index");
+    let prev_height = entry.height();
+    if self.is_active(entry) {
+      return self
+        .params
+        .difficulty
+        .next_bits(prev_height, &|height| self.active_entry(height).header());
+    }
+
+    // Off the active chain, reach each ancestor the difficulty algorithm
+    // looks up through skip pointers.
+    self.params.difficulty.next_bits(prev_height, &|height| {
+      self
+        .index
+        .ancestor(parent, height)
+        .expect("Ancestor of header missing from block index")
+        .header()
+    })
+  }
+
+  /// Get the height of the last block in this chain, where the genesis block
+  /// has height zero.
+  pub fn height(&self) -> u32 {
+    (self.active.len() - 1) as u32
+  }
+
+  /// Get the unspent coin at the given outpoint, as of the last block in this
+  /// chain.
+  pub fn coin(&mut self, outpoint: &OutPoint) -> Result<Option<Coin>, Error> {
+    Ok(self.chainstate.coin(outpoint)?)
   }
 
   /// Get the hash of the last block in this chain.
   pub fn last_block_hash(&self) -> [u8; SHA256_HASH_SIZE] {
-    self
-      .blocks
+    *self
+      .active
       .last()
       .expect("Attempted to get last hash of empty chain")
-      .hash()
   }
 
-  /// Get the total relative work of all blocks in this chain.
-  fn total_relative_work(&self) -> f64 {
-    let mut total_relative_work = 0.0;
-    for block in &self.blocks {
-      total_relative_work += block.relative_work();
+  /// Get the block index entry of the last block in this chain.
+  pub fn tip(&self) -> &BlockIndexEntry {
+    self.active_entry(self.height())
+  }
+
+  /// Get the block index entry of the header with the most work known, which
+  /// may be ahead of the active chain.
+  pub fn best_header(&self) -> &BlockIndexEntry {
+    self
+      .index
+      .get(&self.best_header)
+      .expect("Best header missing from block index")
+  }
+
+  /// Return whether the given block index entry is on the active chain.
+  pub fn is_active(&self, entry: &BlockIndexEntry) -> bool {
+    self.active.get(entry.height() as usize) == Some(&entry.hash())
+  }
+
+  /// Get the block index entry of the active chain's block at `height`, if
+  /// the chain is that long.
+  pub fn entry_at_height(&self, height: u32) -> Option<&BlockIndexEntry> {
+    let hash = self.active.get(height as usize)?;
+    self.index.get(hash)
+  }
+
+  /// Read the active chain's block at `height` from the block store, if the
+  /// chain is that long.
+  ///
+  /// Returns `BlockPruned` if the block's data has been pruned.
+  pub fn block_at_height(&self, height: u32) -> Result<Option<Block>, Error> {
+    match self.active.get(height as usize) {
+      Some(hash) => self.block(hash),
+      None => Ok(None),
     }
-    total_relative_work
   }
-}
 
-impl Default for ActiveChain {
-  fn default() -> Self {
-    Self::new()
+  /// Return a block locator for the indexed block with the given hash (see
+  /// `BlockIndex::locator`), or one holding just the genesis block if the
+  /// block is not indexed. A peer replies to a locator with the headers after
+  /// the first hash in it that is on its own active chain.
+  pub fn locator(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Vec<[u8; SHA256_HASH_SIZE]> {
+    self
+      .index
+      .locator(hash)
+      .unwrap_or_else(|| vec![self.active[0]])
+  }
+
+  /// Get the block index entry of the first block in `locator` that is on the
+  /// active chain, i.e. the last block the active chain shares with the chain
+  /// the locator was made from, or the genesis block if none is.
+  pub fn find_locator_fork(
+    &self,
+    locator: &[[u8; SHA256_HASH_SIZE]],
+  ) -> &BlockIndexEntry {
+    locator
+      .iter()
+      .filter_map(|hash| self.index.get(hash))
+      .find(|entry| self.is_active(entry))
+      .unwrap_or_else(|| self.active_entry(0))
+  }
+
+  /// Return up to `max` headers of the active chain, in order, following the
+  /// first block in `locator` that is on the active chain (or the genesis
+  /// block if none is) and ending early with the block hashed `stop`.
+  pub fn headers_after(
+    &self,
+    locator: &[[u8; SHA256_HASH_SIZE]],
+    stop: &[u8; SHA256_HASH_SIZE],
+    max: usize,
+  ) -> Vec<Header> {
+    let start = self.find_locator_fork(locator).height();
+
+    let mut headers = Vec::new();
+    for height in start + 1..=self.height() {
+      let entry = self.active_entry(height);
+      headers.push(entry.header().clone());
+      if headers.len() == max || entry.hash() == *stop {
+        break;
+      }
+    }
+    headers
+  }
+
+  /// Return the hashes of up to `count` blocks to download next, in order: the
+  /// blocks on the chain ending with the indexed header `hash` that are not
+  /// stored, not on the active chain, no more than `window` blocks ahead of
+  /// it, and not `excluded`. Empty if that chain has no more work than the
+  /// active chain.
+  pub fn blocks_to_download(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+    count: usize,
+    window: u32,
+    excluded: &dyn Fn(&[u8; SHA256_HASH_SIZE]) -> bool,
+  ) -> Vec<[u8; SHA256_HASH_SIZE]> {
+    let mut entry = match self.index.get(hash) {
+      Some(entry) if entry.chain_work() > self.tip().chain_work() => entry,
+      _ => return Vec::new(),
+    };
+    let max_height = self.height().saturating_add(window);
+    if entry.height() > max_height {
+      match self.index.ancestor(hash, max_height) {
+        Some(ancestor) => entry = ancestor,
+        None => return Vec::new(),
+      }
+    }
+
+    let mut blocks = Vec::new();
+    let mut entry = Some(entry);
+    while let Some(current) = entry.filter(|e| !self.is_active(e)) {
+      if current.status().is_invalid() {
+        return Vec::new();
+      }
+      if current.pos().is_none() && !excluded(&current.hash()) {
+        blocks.push(current.hash());
+      }
+      entry = self.index.get(&current.header().prev_block_hash());
+    }
+    blocks.reverse();
+    blocks.truncate(count);
+    blocks
+  }
+
+  /// Get the block index entry of the active chain's block at `height`.
+  fn active_entry(&self, height: u32) -> &BlockIndexEntry {
+    self
+      .index
+      .get(&self.active[height as usize])
+      .expect("Active chain block missing from block index")
+  }
+
+  /// Get the total relative work of all blocks in this chain.
+  fn total_relative_work(&self) -> f64 {
+    self.tip().chain_work()
   }
 }
 
@@ -74,20 +1782,171 @@ impl PartialOrd for ActiveChain {
 /// Error type for `Chain` objects.
 #[derive(Debug)]
 pub enum Error {
+  DuplicateBlock,
+  InvalidBlock([u8; SHA256_HASH_SIZE]),
+  MissingParent,
+  InvalidPrevBlock,
+  NonContinuousHeaders,
   IncorrectPrevBlockHash,
+  TimestampTooOld {
+    timestamp: u32,
+    median_time_past: u32,
+  },
+  TimestampTooNew {
+    timestamp: u32,
+    adjusted_time: u32,
+  },
+  IncorrectBits {
+    expected: u32,
+    found: u32,
+  },
+  MisplacedCoinbase,
+  MissingInput(OutPoint),
+  DuplicateOutput(OutPoint),
+  ValueOverflow,
+  OutputsExceedInputs {
+    input_value: u64,
+    output_value: u64,
+  },
+  BlockPruned([u8; SHA256_HASH_SIZE]),
+  PruneTargetTooSmall {
+    target: u64,
+    minimum: u64,
+  },
+  TxIndexWithPrune,
+  TxIndexDisabled,
+  AddrIndexDisabled,
+  FilterIndexDisabled,
+  UnknownBlock([u8; SHA256_HASH_SIZE]),
+  InvalidateGenesis,
+  CheckpointMismatch(u32),
+  ForkBelowCheckpoint {
+    height: u32,
+    checkpoint: u32,
+  },
   BlockError(block::Error),
+  StorageError(storage::error::Error),
+}
+
+impl From<storage::error::Error> for Error {
+  fn from(err: storage::error::Error) -> Self {
+    Error::StorageError(err)
+  }
 }
 
 impl Display for Error {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
+      Error::DuplicateBlock => write!(f, "Attempted to push known block"),
+      Error::InvalidBlock(hash) => write!(
+        f,
+        "Attempted to push block {} known to be invalid",
+        hex(hash)
+      ),
+      Error::MissingParent => {
+        write!(f, "Attempted to push header with unknown parent")
+      },
+      Error::InvalidPrevBlock => {
+        write!(f, "Attempted to push header with invalid parent")
+      },
+      Error::NonContinuousHeaders => write!(
+        f,
+        "Attempted to push headers that do not each follow the one before"
+      ),
       Error::IncorrectPrevBlockHash => write!(
         f,
         "Attempted to push block with incorrect previous block hash"
       ),
+      Error::TimestampTooOld { timestamp, median_time_past } => write!(
+        f,
+        "Attempted to push block with timestamp {}, not after median time \
+         past {}",
+        timestamp, median_time_past
+      ),
+      Error::TimestampTooNew { timestamp, adjusted_time } => write!(
+        f,
+        "Attempted to push block with timestamp {}, too far ahead of adjusted \
+         time {}",
+        timestamp, adjusted_time
+      ),
+      Error::IncorrectBits { expected, found } => write!(
+        f,
+        "Attempted to push block with bits {:#010x}, expected {:#010x}",
+        found, expected
+      ),
+      Error::MisplacedCoinbase => write!(
+        f,
+        "Attempted to push block with coinbase transaction after the first"
+      ),
+      Error::MissingInput(outpoint) => write!(
+        f,
+        "Attempted to push block spending missing or spent output {}:{}",
+        hex(&outpoint.txn_hash),
+        outpoint.index
+      ),
+      Error::DuplicateOutput(outpoint) => write!(
+        f,
+        "Attempted to push block overwriting unspent output {}:{}",
+        hex(&outpoint.txn_hash),
+        outpoint.index
+      ),
+      Error::ValueOverflow => {
+        write!(f, "Attempted to push block with overflowing values")
+      },
+      Error::OutputsExceedInputs { input_value, output_value } => write!(
+        f,
+        "Attempted to push block with transaction creating {} from inputs \
+         worth {}",
+        output_value, input_value
+      ),
+      Error::BlockPruned(hash) => write!(
+        f,
+        "Data of block {} is needed but has been pruned",
+        hex(hash)
+      ),
+      Error::PruneTargetTooSmall { target, minimum } => write!(
+        f,
+        "Prune target of {} bytes is below the minimum of {} bytes",
+        target, minimum
+      ),
+      Error::TxIndexWithPrune => write!(
+        f,
+        "The transaction index cannot be enabled on a pruned block store"
+      ),
+      Error::TxIndexDisabled => {
+        write!(
+          f,
+          "Attempted to look up transaction without a transaction index"
+        )
+      },
+      Error::AddrIndexDisabled => {
+        write!(f, "Attempted to look up address without an address index")
+      },
+      Error::FilterIndexDisabled => write!(
+        f,
+        "Attempted to look up block filter without a block filter index"
+      ),
+      Error::UnknownBlock(hash) => {
+        write!(f, "Attempted to look up unknown block {}", hex(hash))
+      },
+      Error::InvalidateGenesis => {
+        write!(f, "Attempted to invalidate the genesis block")
+      },
+      Error::CheckpointMismatch(height) => write!(
+        f,
+        "Attempted to push header not matching the checkpoint at height {}",
+        height
+      ),
+      Error::ForkBelowCheckpoint { height, checkpoint } => write!(
+        f,
+        "Attempted to push header at height {}, forking below the checkpoint \
+         at height {}",
+        height, checkpoint
+      ),
       Error::BlockError(err) => {
         write!(f, "BlockError: {}", err)
       },
+      Error::StorageError(err) => write!(f, "StorageError: {}", err),
     }
   }
 }
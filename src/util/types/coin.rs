@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use super::{txi::Txi, txo::Txo};
+use crate::util::constants::SHA256_HASH_SIZE;
+
+/// A reference to a transaction output.
+#[derive(
+  Clone,
+  Copy,
+  Debug,
+  PartialEq,
+  Eq,
+  Hash,
+  PartialOrd,
+  Ord,
+  Deserialize,
+  Serialize,
+)]
+pub struct OutPoint {
+  /// The hash of the transaction that created the output.
+  pub txn_hash: [u8; SHA256_HASH_SIZE],
+
+  /// The index of the output in that transaction.
+  pub index: usize,
+}
+
+impl OutPoint {
+  /// Initialize an outpoint from the provided values.
+  pub fn new(txn_hash: [u8; SHA256_HASH_SIZE], index: usize) -> Self {
+    Self { txn_hash, index }
+  }
+
+  /// Return the outpoint spent by a transaction input.
+  pub fn spent_by(txi: &Txi) -> Self {
+    Self { txn_hash: txi.prev_txn_hash(), index: txi.prev_txo_index() }
+  }
+}
+
+/// An unspent transaction output, with the context needed to validate spends
+/// of it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Coin {
+  /// The unspent output.
+  pub txo: Txo,
+
+  /// The height of the block that created the output.
+  pub height: u32,
+
+  /// Whether the output was created by a coinbase transaction.
+  pub is_coinbase: bool,
+}
+
+/// The coins spent by a block, in the order its inputs spent them. Restoring
+/// them undoes the block's effect on the UTXO set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BlockUndo {
+  /// The coins spent by the block.
+  pub spent: Vec<Coin>,
+}
@@ -1,11 +1,13 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
 use ethnum::u256;
+use serde::{Deserialize, Serialize};
 
-use crate::util::{constants::SHA256_HASH_SIZE, hashes::sha256};
+use crate::util::{
+  constants::{INITIAL_TARGET_BITS, SHA256_HASH_SIZE},
+  hashes::sha256,
+};
 
 /// A block header.
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Header {
   version: u32,
   prev_block_hash: [u8; SHA256_HASH_SIZE],
@@ -28,17 +30,14 @@ impl Header {
     Self { version, prev_block_hash, merkle_root, timestamp, bits, nonce }
   }
 
-  /// Return the genesis block header.
-  pub fn genesis() -> Self {
+  /// Return the genesis block header with the given timestamp.
+  pub fn genesis(timestamp: u32) -> Self {
     Self {
       version: 0,
       prev_block_hash: [0u8; SHA256_HASH_SIZE],
       merkle_root: [0u8; SHA256_HASH_SIZE],
-      timestamp: SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Failed to get duration since UNIX epoch")
-        .as_secs() as u32,
-      bits: 0,
+      timestamp,
+      bits: INITIAL_TARGET_BITS,
       nonce: 0,
     }
   }
@@ -57,15 +56,38 @@ impl Header {
 
   /// Return the target from this header's `bits` field.
   pub fn target(&self) -> u256 {
-    let be_bytes = self.bits.to_be_bytes();
+    bits_to_target(self.bits)
+  }
+
+  /// Return whether this header's hash meets the target in its `bits`, i.e.
+  /// whether its proof-of-work is valid.
+  pub fn check_pow(&self) -> bool {
+    u256::from_be_bytes(self.hash()) <= self.target()
+  }
+
+  /// Return the relative work done to mine a block with this header.
+  pub fn relative_work(&self) -> f64 {
+    u256::MAX.as_f64() / self.target().as_f64()
+  }
+
+  /// Return this header's `version`.
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+
+  /// Return this header's `merkle_root`.
+  pub fn merkle_root(&self) -> [u8; SHA256_HASH_SIZE] {
+    self.merkle_root
+  }
 
-    let mut coeff_bytes = [0u8; 4];
-    coeff_bytes[1..be_bytes.len()].copy_from_slice(&be_bytes[1..]);
+  /// Return this header's `timestamp`.
+  pub fn timestamp(&self) -> u32 {
+    self.timestamp
+  }
 
-    let coeff = u256::from(u32::from_be_bytes(coeff_bytes));
-    let exp = 8 * (be_bytes[0] - 3) as u32;
-    let base = u256::from(2u8);
-    coeff * base.pow(exp)
+  /// Return this header's `bits`.
+  pub fn bits(&self) -> u32 {
+    self.bits
   }
 
   /// Return this header's `nonce`.
@@ -73,12 +95,56 @@ impl Header {
     self.nonce
   }
 
+  /// Set this header's `nonce`. Used by the miner between hash attempts.
+  pub fn set_nonce(&mut self, nonce: u32) {
+    self.nonce = nonce;
+  }
+
   /// Return this header's `prev_block_hash`.
   pub fn prev_block_hash(&self) -> [u8; SHA256_HASH_SIZE] {
     self.prev_block_hash
   }
 }
 
+/// Expand a compressed `bits` value into a full 256-bit target.
+///
+/// The top byte of `bits` is a base-256 exponent and the lower three bytes are
+/// the coefficient, so that `target = coeff * 256^(exp - 3)`. Targets too large
+/// to represent saturate to `u256::MAX`.
+pub fn bits_to_target(bits: u32) -> u256 {
+  let exp = bits >> 24;
+  let coeff = u256::from(bits & 0x007fffff);
+  if exp <= 3 {
+    coeff >> (8 * (3 - exp))
+  } else {
+    let shift = 8 * (exp - 3);
+    if coeff != 0 && (shift >= 256 || coeff.leading_zeros() < shift) {
+      u256::MAX
+    } else {
+      coeff << shift
+    }
+  }
+}
+
+/// Compress a 256-bit target into its `bits` form, truncating the target to its
+/// three most significant bytes.
+pub fn target_to_bits(target: u256) -> u32 {
+  let mut size = (256 - target.leading_zeros()).div_ceil(8);
+  let mut coeff = if size <= 3 {
+    target.as_u32() << (8 * (3 - size))
+  } else {
+    (target >> (8 * (size - 3))).as_u32()
+  };
+
+  // The coefficient is signed, so keep its top bit clear by moving a byte into
+  // the exponent.
+  if coeff & 0x00800000 != 0 {
+    coeff >>= 8;
+    size += 1;
+  }
+  coeff | (size << 24)
+}
+
 impl Default for Header {
   /// NOTE: For debugging purposes only.
   fn default() -> Self {
@@ -1,7 +1,11 @@
 pub mod addr;
 pub mod block;
+pub mod block_filter;
+pub mod block_index;
 pub mod chain;
+pub mod coin;
 pub mod header;
+pub mod orphan_pool;
 pub mod txi;
 pub mod txn;
 pub mod txo;
@@ -0,0 +1,161 @@
+use std::{collections::HashMap, net::Ipv4Addr};
+
+use super::block::Block;
+use crate::util::constants::{
+  MAX_ORPHAN_BLOCKS, MAX_ORPHAN_BLOCKS_SIZE, ORPHAN_BLOCK_EXPIRY,
+  SHA256_HASH_SIZE,
+};
+
+/// A block whose parent is not yet known, held until the parent arrives.
+#[derive(Debug)]
+pub struct OrphanBlock {
+  /// The orphaned block.
+  pub block: Block,
+
+  /// The peer the block was received from, if any.
+  pub peer: Option<Ipv4Addr>,
+
+  /// The time the block was received.
+  pub received: u32,
+
+  /// The size of the block's serialized data, in bytes.
+  pub size: usize,
+}
+
+/// A pool of orphan blocks, bounded in number and total size, indexed by the
+/// hash of their missing parent.
+#[derive(Debug, Default)]
+pub struct OrphanPool {
+  orphans: HashMap<[u8; SHA256_HASH_SIZE], OrphanBlock>,
+  by_parent: HashMap<[u8; SHA256_HASH_SIZE], Vec<[u8; SHA256_HASH_SIZE]>>,
+  size: usize,
+}
+
+impl OrphanPool {
+  /// Initialize an empty orphan pool.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Return the number of orphans in the pool.
+  pub fn len(&self) -> usize {
+    self.orphans.len()
+  }
+
+  /// Return the total size of the orphans in the pool, in bytes.
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
+  /// Return whether the pool is empty.
+  pub fn is_empty(&self) -> bool {
+    self.orphans.is_empty()
+  }
+
+  /// Return whether the block with the given hash is in the pool.
+  pub fn contains(&self, hash: &[u8; SHA256_HASH_SIZE]) -> bool {
+    self.orphans.contains_key(hash)
+  }
+
+  /// Add a block received from `peer` at time `now` to the pool.
+  ///
+  /// Orphans older than `ORPHAN_BLOCK_EXPIRY` are evicted first, then the
+  /// oldest orphans until the block fits under both `MAX_ORPHAN_BLOCKS` and
+  /// `MAX_ORPHAN_BLOCKS_SIZE`. A block larger than `MAX_ORPHAN_BLOCKS_SIZE` on
+  /// its own is not held.
+  pub fn insert(&mut self, block: Block, peer: Option<Ipv4Addr>, now: u32) {
+    let hash = block.hash();
+    let size = block.size();
+    if self.orphans.contains_key(&hash) || size > MAX_ORPHAN_BLOCKS_SIZE {
+      return;
+    }
+    self.expire(now);
+    while self.orphans.len() >= MAX_ORPHAN_BLOCKS
+      || self.size + size > MAX_ORPHAN_BLOCKS_SIZE
+    {
+      self.evict_oldest();
+    }
+
+    self
+      .by_parent
+      .entry(block.prev_block_hash())
+      .or_default()
+      .push(hash);
+    self.size += size;
+    self
+      .orphans
+      .insert(hash, OrphanBlock { block, peer, received: now, size });
+  }
+
+  /// Remove and return every orphan whose parent is the block with the given
+  /// hash.
+  pub fn take_children(
+    &mut self,
+    parent_hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Vec<OrphanBlock> {
+    self
+      .by_parent
+      .remove(parent_hash)
+      .unwrap_or_default()
+      .iter()
+      .filter_map(|hash| self.orphans.remove(hash))
+      .inspect(|orphan| self.size -= orphan.size)
+      .collect()
+  }
+
+  /// Return the hash of the block that the orphan with the given hash is
+  /// ultimately waiting on, i.e. the missing parent of its oldest orphaned
+  /// ancestor.
+  pub fn missing_ancestor(
+    &self,
+    hash: &[u8; SHA256_HASH_SIZE],
+  ) -> Option<[u8; SHA256_HASH_SIZE]> {
+    let mut orphan = self.orphans.get(hash)?;
+    while let Some(parent) = self.orphans.get(&orphan.block.prev_block_hash()) {
+      orphan = parent;
+    }
+    Some(orphan.block.prev_block_hash())
+  }
+
+  /// Evict every orphan received more than `ORPHAN_BLOCK_EXPIRY` seconds
+  /// before `now`.
+  pub fn expire(&mut self, now: u32) {
+    let expired: Vec<_> = self
+      .orphans
+      .iter()
+      .filter(|(_, orphan)| {
+        now.saturating_sub(orphan.received) > ORPHAN_BLOCK_EXPIRY
+      })
+      .map(|(hash, _)| *hash)
+      .collect();
+    for hash in expired {
+      self.remove(&hash);
+    }
+  }
+
+  /// Evict the orphan that was received first.
+  fn evict_oldest(&mut self) {
+    let oldest = self
+      .orphans
+      .iter()
+      .min_by_key(|(_, orphan)| orphan.received)
+      .map(|(hash, _)| *hash);
+    if let Some(hash) = oldest {
+      self.remove(&hash);
+    }
+  }
+
+  /// Remove the orphan with the given hash from both maps.
+  fn remove(&mut self, hash: &[u8; SHA256_HASH_SIZE]) -> Option<OrphanBlock> {
+    let orphan = self.orphans.remove(hash)?;
+    self.size -= orphan.size;
+    let parent_hash = orphan.block.prev_block_hash();
+    if let Some(siblings) = self.by_parent.get_mut(&parent_hash) {
+      siblings.retain(|sibling| sibling != hash);
+      if siblings.is_empty() {
+        self.by_parent.remove(&parent_hash);
+      }
+    }
+    Some(orphan)
+  }
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use crate::util::constants::SHA256_HASH_SIZE;
 
 /// A transaction input.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Txi {
   /// Previous transaction hash.
   prev_txn_hash: [u8; SHA256_HASH_SIZE],
@@ -14,6 +14,11 @@ pub struct Txi {
   /// Previous transaction output owner's digital signature.
   /// NOTE: Not sure if this is right.
   prev_txn_sig: [u8; SHA256_HASH_SIZE],
+
+  /// Sequence number. Any value up to `MAX_RBF_SEQUENCE` opts the spending
+  /// transaction in to replacement in the mempool; `SEQUENCE_FINAL` is used
+  /// otherwise.
+  sequence: u32,
 }
 
 impl Txi {
@@ -22,7 +27,28 @@ impl Txi {
     prev_txn_hash: [u8; SHA256_HASH_SIZE],
     prev_txo_index: usize,
     prev_txn_sig: [u8; SHA256_HASH_SIZE],
+    sequence: u32,
   ) -> Self {
-    Self { prev_txn_hash, prev_txo_index, prev_txn_sig }
+    Self { prev_txn_hash, prev_txo_index, prev_txn_sig, sequence }
+  }
+
+  /// Return the hash of the transaction whose output this input spends.
+  pub fn prev_txn_hash(&self) -> [u8; SHA256_HASH_SIZE] {
+    self.prev_txn_hash
+  }
+
+  /// Return the index of the output this input spends.
+  pub fn prev_txo_index(&self) -> usize {
+    self.prev_txo_index
+  }
+
+  /// Return the spent output owner's signature.
+  pub fn prev_txn_sig(&self) -> [u8; SHA256_HASH_SIZE] {
+    self.prev_txn_sig
+  }
+
+  /// Return this input's sequence number.
+  pub fn sequence(&self) -> u32 {
+    self.sequence
   }
 }
@@ -1,10 +1,13 @@
 use serde::{Deserialize, Serialize};
 
 use super::{txi::Txi, txo::Txo};
-use crate::util::{constants::SHA256_HASH_SIZE, hashes::sha256};
+use crate::util::{
+  constants::{MAX_RBF_SEQUENCE, SHA256_HASH_SIZE},
+  hashes::sha256,
+};
 
 /// A transaction.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Txn {
   version: u32,
   txi_count: u32,
@@ -25,6 +28,43 @@ impl Txn {
     Self { version, txi_count, txi_list, txo_count, txo_list }
   }
 
+  /// Return this transaction's `version`.
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+
+  /// Return this transaction's inputs.
+  pub fn txi_list(&self) -> &[Txi] {
+    &self.txi_list
+  }
+
+  /// Return this transaction's outputs.
+  pub fn txo_list(&self) -> &[Txo] {
+    &self.txo_list
+  }
+
+  /// Return whether this is a coinbase transaction, i.e. one that spends no
+  /// outputs and creates new coins.
+  pub fn is_coinbase(&self) -> bool {
+    self.txi_list.is_empty()
+  }
+
+  /// Return whether this transaction opts in to replacement in the mempool,
+  /// i.e. whether any of its inputs has a sequence number of at most
+  /// `MAX_RBF_SEQUENCE`.
+  pub fn signals_rbf(&self) -> bool {
+    self
+      .txi_list
+      .iter()
+      .any(|txi| txi.sequence() <= MAX_RBF_SEQUENCE)
+  }
+
+  /// Return the size of this transaction's serialized data, in bytes.
+  pub fn size(&self) -> usize {
+    bincode::serialized_size(self).expect("Failed to serialize transaction")
+      as usize
+  }
+
   /// Return the hash of this transaction's data.
   pub fn hash(&self) -> [u8; SHA256_HASH_SIZE] {
     sha256(&sha256(
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use crate::util::constants::RIPEMD160_HASH_SIZE;
 
 /// A transaction output.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Txo {
   /// The value of this transaction output.
   value: u64,
@@ -18,4 +18,14 @@ impl Txo {
   pub fn new(value: u64, pubkey_hash: [u8; RIPEMD160_HASH_SIZE]) -> Self {
     Self { value, pubkey_hash }
   }
+
+  /// Return the value of this transaction output.
+  pub fn value(&self) -> u64 {
+    self.value
+  }
+
+  /// Return the hash of the recipient's public key.
+  pub fn pubkey_hash(&self) -> [u8; RIPEMD160_HASH_SIZE] {
+    self.pubkey_hash
+  }
 }
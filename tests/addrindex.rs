@@ -0,0 +1,148 @@
+mod common;
+
+use common::{coinbase, mature_chain, mine, push, TempDir, NOW};
+use rbtc::{
+  index::addrindex::{AddrTxn, AddrUtxo},
+  util::{
+    config::Config,
+    types::{chain::ActiveChain, coin::OutPoint, txi::Txi, txn::Txn, txo::Txo},
+  },
+};
+
+const ALICE: [u8; 20] = [0xa1; 20];
+const BOB: [u8; 20] = [0xb0; 20];
+const CAROL: [u8; 20] = [0xc4; 20];
+
+/// Return a transaction spending the given outputs into outputs of the given
+/// values paying the given pubkey hashes.
+fn pay(prevs: &[(&Txn, usize)], outputs: &[(u64, [u8; 20])]) -> Txn {
+  let inputs: Vec<_> = prevs
+    .iter()
+    .map(|(prev, index)| Txi::new(prev.hash(), *index, [0u8; 32], u32::MAX))
+    .collect();
+  let outputs: Vec<_> = outputs
+    .iter()
+    .map(|(value, pubkey_hash)| Txo::new(*value, *pubkey_hash))
+    .collect();
+  Txn::new(
+    0,
+    inputs.len() as u32,
+    inputs,
+    outputs.len() as u32,
+    outputs,
+  )
+}
+
+/// Return an entry of an address's history.
+fn history_entry(txn: &Txn, height: u32, received: u64, sent: u64) -> AddrTxn {
+  AddrTxn { txn_hash: txn.hash(), height, received, sent }
+}
+
+/// Return an unspent output paying an address.
+fn utxo(txn: &Txn, index: usize, height: u32) -> AddrUtxo {
+  let value = txn.txo_list()[index].value();
+  AddrUtxo { outpoint: OutPoint::new(txn.hash(), index), height, value }
+}
+
+/// Open a chain with the address index enabled and caught up, and confirm
+/// two transactions on it: the first pays Alice and Bob, the second spends
+/// Alice's output back to her and to Bob. Return the chain and transactions.
+fn chain_with_payments(dir: &TempDir) -> (ActiveChain, Txn, Txn) {
+  let mut config = Config { addrindex: true, ..Config::default() };
+  let (mut chain, coinbases) = mature_chain(dir, &mut config, 1);
+  while !chain.sync_indexes(1_000).unwrap() {}
+
+  let first = pay(&[(&coinbases[0], 0)], &[(600_000, ALICE), (300_000, BOB)]);
+  push(&mut chain, vec![coinbase(10), first.clone()]);
+  let second = pay(&[(&first, 0)], &[(200_000, BOB), (350_000, ALICE)]);
+  push(&mut chain, vec![coinbase(11), second.clone()]);
+  (chain, first, second)
+}
+
+/// The history, unspent outputs and balance of addresses follow the
+/// transactions paying and spending from them.
+#[test]
+fn address_queries() {
+  let dir = TempDir::new("addrindex-queries");
+  let (chain, first, second) = chain_with_payments(&dir);
+  let height = chain.height() - 1;
+
+  assert_eq!(
+    chain.address_history(&ALICE).unwrap(),
+    vec![
+      history_entry(&first, height, 600_000, 0),
+      history_entry(&second, height + 1, 350_000, 600_000),
+    ]
+  );
+  assert_eq!(
+    chain.address_utxos(&ALICE).unwrap(),
+    vec![utxo(&second, 1, height + 1)]
+  );
+  assert_eq!(chain.address_balance(&ALICE).unwrap(), 350_000);
+
+  assert_eq!(
+    chain.address_history(&BOB).unwrap(),
+    vec![
+      history_entry(&first, height, 300_000, 0),
+      history_entry(&second, height + 1, 200_000, 0),
+    ]
+  );
+  assert_eq!(
+    chain.address_utxos(&BOB).unwrap(),
+    vec![utxo(&first, 1, height), utxo(&second, 0, height + 1)]
+  );
+  assert_eq!(chain.address_balance(&BOB).unwrap(), 500_000);
+
+  assert_eq!(chain.address_history(&CAROL).unwrap(), vec![]);
+  assert_eq!(chain.address_utxos(&CAROL).unwrap(), vec![]);
+  assert_eq!(chain.address_balance(&CAROL).unwrap(), 0);
+}
+
+/// Disconnecting a block in a reorganization forgets what it paid and spent,
+/// and the blocks of the new branch are indexed in its place.
+#[test]
+fn reorg_updates_addresses() {
+  let dir = TempDir::new("addrindex-reorg");
+  let (mut chain, first, _) = chain_with_payments(&dir);
+  let height = chain.height() - 1;
+  let fork = chain.block_at_height(height).unwrap().unwrap().hash();
+
+  // Replace the block confirming the second transaction with a branch that
+  // has more work, spending Alice's output to Carol instead.
+  let third = pay(&[(&first, 0)], &[(500_000, CAROL)]);
+  let replacement = mine(&chain, &fork, vec![coinbase(12), third.clone()]);
+  chain
+    .validate_and_push(replacement.clone(), None, NOW)
+    .unwrap();
+  let extension = mine(&chain, &replacement.hash(), vec![coinbase(13)]);
+  chain
+    .validate_and_push(extension.clone(), None, NOW)
+    .unwrap();
+  assert_eq!(chain.last_block_hash(), extension.hash());
+
+  assert_eq!(
+    chain.address_history(&ALICE).unwrap(),
+    vec![
+      history_entry(&first, height, 600_000, 0),
+      history_entry(&third, height + 1, 0, 600_000),
+    ]
+  );
+  assert_eq!(chain.address_utxos(&ALICE).unwrap(), vec![]);
+  assert_eq!(chain.address_balance(&ALICE).unwrap(), 0);
+
+  assert_eq!(
+    chain.address_history(&BOB).unwrap(),
+    vec![history_entry(&first, height, 300_000, 0)]
+  );
+  assert_eq!(
+    chain.address_utxos(&BOB).unwrap(),
+    vec![utxo(&first, 1, height)]
+  );
+  assert_eq!(chain.address_balance(&BOB).unwrap(), 300_000);
+
+  assert_eq!(
+    chain.address_history(&CAROL).unwrap(),
+    vec![history_entry(&third, height + 1, 500_000, 0)]
+  );
+  assert_eq!(chain.address_balance(&CAROL).unwrap(), 500_000);
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::{coinbase, mine, open_chain, push, TempDir, NOW};
+use rbtc::{
+  index::filterindex::FilterIndex,
+  util::{
+    config::Config,
+    constants::SHA256_HASH_SIZE,
+    hashes::{hex, siphash},
+    types::{
+      block::Block,
+      block_filter::BlockFilter,
+      coin::{BlockUndo, Coin},
+      header::Header,
+      txn::Txn,
+      txo::Txo,
+    },
+  },
+};
+
+/// Return a block with an all-zero header and one transaction paying each of
+/// the given pubkey hashes.
+fn block_paying(pubkey_hashes: &[[u8; 20]]) -> Block {
+  let txns: Vec<_> = pubkey_hashes
+    .iter()
+    .map(|pubkey_hash| {
+      Txn::new(0, 0, vec![], 1, vec![Txo::new(1, *pubkey_hash)])
+    })
+    .collect();
+  let header =
+    Header::new(0, [0u8; SHA256_HASH_SIZE], [0u8; SHA256_HASH_SIZE], 0, 0, 0);
+  Block::new(header, txns.len() as u32, txns)
+}
+
+/// Return undo data spending one coin paying each of the given pubkey
+/// hashes.
+fn undo_spending(pubkey_hashes: &[[u8; 20]]) -> BlockUndo {
+  let spent = pubkey_hashes
+    .iter()
+    .map(|pubkey_hash| Coin {
+      txo: Txo::new(1, *pubkey_hash),
+      height: 0,
+      is_coinbase: false,
+    })
+    .collect();
+  BlockUndo { spent }
+}
+
+/// SipHash-2-4 matches the reference implementation's test vector.
+#[test]
+fn siphash_known_answer() {
+  let input: Vec<u8> = (0..15).collect();
+  let hash = siphash(0x0706050403020100, 0x0f0e0d0c0b0a0908, &input);
+  assert_eq!(hash, 0xa129ca6149be45e5);
+}
+
+/// A filter's serialized form and hash match those computed by an
+/// independent implementation, and decoding it gives the same filter.
+/// Repeated items are only counted once.
+#[test]
+fn filter_known_answer() {
+  let block = block_paying(&[[1; 20], [2; 20], [3; 20], [1; 20]]);
+  let undo = undo_spending(&[[4; 20]]);
+  assert_eq!(
+    hex(&block.hash()),
+    "4be7570e8f70eb093640c8468274ba759745a7aa2b7d25ab1e0421b259845014"
+  );
+
+  let filter = BlockFilter::new(&block, &undo);
+  assert_eq!(filter.len(), 4);
+  assert_eq!(hex(&filter.encode()), "04a92794016ab03d414c59d0");
+  assert_eq!(
+    hex(&filter.hash()),
+    "c6fc75c820deadb21aa0730e457b1ef29646941d2267bb7a30e770c979d6ac50"
+  );
+
+  let decoded = BlockFilter::decode(block.hash(), &filter.encode()).unwrap();
+  assert_eq!(decoded, filter);
+  for pubkey_hash in [[1; 20], [2; 20], [3; 20], [4; 20]] {
+    assert!(decoded.matches(&pubkey_hash).unwrap());
+  }
+}
+
+/// An empty filter encodes to a zero count and matches nothing.
+#[test]
+fn empty_filter() {
+  let block = block_paying(&[]);
+  let filter = BlockFilter::new(&block, &BlockUndo::default());
+  assert!(filter.is_empty());
+  assert_eq!(filter.encode(), vec![0]);
+  assert!(!filter.matches(&[1; 20]).unwrap());
+}
+
+/// Every item a filter was built from matches it, alone or among other
+/// queries, and few others do.
+#[test]
+fn no_false_negatives() {
+  let paid: Vec<[u8; 20]> = (0..500u32)
+    .map(|i| {
+      let mut pubkey_hash = [0u8; 20];
+      pubkey_hash[..4].copy_from_slice(&i.to_le_bytes());
+      pubkey_hash
+    })
+    .collect();
+  let (outputs, spent) = paid.split_at(300);
+  let block = block_paying(outputs);
+  let filter = BlockFilter::new(&block, &undo_spending(spent));
+  let filter = BlockFilter::decode(block.hash(), &filter.encode()).unwrap();
+  assert_eq!(filter.len(), 500);
+
+  let others: Vec<[u8; 20]> = (0..500u32)
+    .map(|i| {
+      let mut pubkey_hash = [0xff; 20];
+      pubkey_hash[..4].copy_from_slice(&i.to_le_bytes());
+      pubkey_hash
+    })
+    .collect();
+  for (item, other) in paid.iter().zip(&others) {
+    assert!(filter.matches(item).unwrap());
+    assert!(filter.matches_any(&[*other, *item]).unwrap());
+  }
+  let false_positives = others
+    .iter()
+    .filter(|other| filter.matches(other).unwrap())
+    .count();
+  assert!(false_positives < 5);
+}
+
+/// Disconnecting a block removes its filter and header from the index, and
+/// the filter header chain continues from its parent's on the block that
+/// replaces it.
+#[test]
+fn filter_index_disconnect() {
+  let dir = TempDir::new("filter-index-disconnect");
+  let mut config = Config { blockfilterindex: true, ..Config::default() };
+  let mut chain = open_chain(&dir, &mut config);
+  while !chain.sync_indexes(100).unwrap() {}
+  let first = push(&mut chain, vec![coinbase(1)]).hash();
+  let second = push(&mut chain, vec![coinbase(2)]).hash();
+
+  let first_header = chain.block_filter_header(&first).unwrap().unwrap();
+  let second_filter = chain.block_filter(&second).unwrap().unwrap();
+  assert_eq!(
+    chain.block_filter_header(&second).unwrap(),
+    Some(second_filter.header(&first_header))
+  );
+
+  // Replace the second block with a fork that has more work.
+  let replacement = mine(&chain, &first, vec![coinbase(3)]);
+  chain
+    .validate_and_push(replacement.clone(), None, NOW)
+    .unwrap();
+  let extension = mine(&chain, &replacement.hash(), vec![coinbase(4)]);
+  chain
+    .validate_and_push(extension.clone(), None, NOW)
+    .unwrap();
+  assert_eq!(chain.last_block_hash(), extension.hash());
+  let replacement = replacement.hash();
+  let filter = chain.block_filter(&replacement).unwrap().unwrap();
+  assert_eq!(
+    chain.block_filter_header(&replacement).unwrap(),
+    Some(filter.header(&first_header))
+  );
+
+  drop(chain);
+  let index = FilterIndex::open(&dir.0).unwrap();
+  assert_eq!(index.filter(&second).unwrap(), None);
+  assert_eq!(index.filter_header(&second).unwrap(), None);
+  assert_eq!(index.filter_header(&first).unwrap(), Some(first_header));
+}
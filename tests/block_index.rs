@@ -0,0 +1,135 @@
+use rbtc::util::types::{
+  block_index::{BlockIndex, BlockIndexEntry, BlockStatus},
+  header::Header,
+};
+
+/// The number of headers in the branched index the tests search.
+const HEADERS: u32 = 5_000;
+
+/// A deterministic linear congruential generator, so that failures can be
+/// reproduced.
+struct Lcg(u64);
+
+impl Lcg {
+  /// Return a pseudo-random number below `bound`.
+  fn below(&mut self, bound: usize) -> usize {
+    self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+    ((self.0 >> 33) % bound as u64) as usize
+  }
+}
+
+/// Return an index of `HEADERS` headers forming a tree: a long main chain,
+/// and branches growing from randomly chosen blocks every few headers. The
+/// headers' hashes are returned with it.
+fn branched_index(rng: &mut Lcg) -> (BlockIndex, Vec<[u8; 32]>) {
+  let genesis = Header::genesis(0);
+  let mut index = BlockIndex::new();
+  index.insert_entry(BlockIndexEntry::new(
+    genesis.clone(),
+    0,
+    genesis.relative_work(),
+    BlockStatus::ValidBlock,
+  ));
+  let mut hashes = vec![genesis.hash()];
+  let mut tips = vec![genesis.hash()];
+  for nonce in 1..HEADERS {
+    if nonce % 50 == 0 {
+      tips.push(hashes[rng.below(hashes.len())]);
+    }
+    // Extend the main chain half the time, and a random branch otherwise.
+    let tip = match rng.below(2) {
+      0 => 0,
+      _ => rng.below(tips.len()),
+    };
+    let header = Header::new(0, tips[tip], [0; 32], nonce, 0, nonce);
+    let hash = index.insert(header).unwrap().hash();
+    hashes.push(hash);
+    tips[tip] = hash;
+  }
+  (index, hashes)
+}
+
+/// Return the ancestor at `height` of the block with the given hash, found by
+/// following parents one at a time.
+fn linear_ancestor(
+  index: &BlockIndex,
+  hash: &[u8; 32],
+  height: u32,
+) -> Option<[u8; 32]> {
+  let mut entry = index.get(hash)?;
+  if height > entry.height() {
+    return None;
+  }
+  while entry.height() > height {
+    entry = index.get(&entry.header().prev_block_hash())?;
+  }
+  Some(entry.hash())
+}
+
+/// Return the last common ancestor of the blocks with the given hashes, found
+/// by following parents one at a time.
+fn linear_fork(index: &BlockIndex, a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+  let mut a = index.get(a).unwrap();
+  let mut b = index.get(b).unwrap();
+  while a.hash() != b.hash() {
+    if a.height() >= b.height() {
+      a = index.get(&a.header().prev_block_hash()).unwrap();
+    } else {
+      b = index.get(&b.header().prev_block_hash()).unwrap();
+    }
+  }
+  a.hash()
+}
+
+/// Skip pointers find the same ancestor as walking back through parents, for
+/// many blocks of a branched index, at every height of the deepest one.
+#[test]
+fn ancestor_matches_linear_walk() {
+  let mut rng = Lcg(1);
+  let (index, hashes) = branched_index(&mut rng);
+  let deepest = index.entries().max_by_key(|entry| entry.height()).unwrap();
+  assert!(deepest.height() > HEADERS / 4);
+  let mut expected = Some(deepest.hash());
+  for target in (0..=deepest.height()).rev() {
+    let ancestor = index.ancestor(&deepest.hash(), target).map(|e| e.hash());
+    assert_eq!(ancestor, expected);
+    expected = Some(
+      index
+        .get(&ancestor.unwrap())
+        .unwrap()
+        .header()
+        .prev_block_hash(),
+    );
+  }
+
+  for _ in 0..5_000 {
+    let hash = hashes[rng.below(hashes.len())];
+    let height = index.get(&hash).unwrap().height();
+    let target = rng.below(height as usize + 2) as u32;
+    let ancestor = index.ancestor(&hash, target).map(|entry| entry.hash());
+    assert_eq!(ancestor, linear_ancestor(&index, &hash, target));
+  }
+}
+
+/// The fork point of two blocks is the same as walking both back through
+/// parents finds, for blocks on the same branch and on different ones.
+#[test]
+fn find_fork_matches_linear_walk() {
+  let mut rng = Lcg(2);
+  let (index, hashes) = branched_index(&mut rng);
+  for _ in 0..5_000 {
+    let a = hashes[rng.below(hashes.len())];
+    let b = match rng.below(4) {
+      // An ancestor of `a`, or `a` itself.
+      0 => {
+        let height = index.get(&a).unwrap().height();
+        linear_ancestor(&index, &a, rng.below(height as usize + 1) as u32)
+          .unwrap()
+      },
+      _ => hashes[rng.below(hashes.len())],
+    };
+    let fork = index.find_fork(&a, &b).unwrap().hash();
+    assert_eq!(fork, linear_fork(&index, &a, &b));
+    assert_eq!(index.find_fork(&b, &a).unwrap().hash(), fork);
+  }
+}
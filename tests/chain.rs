@@ -0,0 +1,395 @@
+mod common;
+
+use std::fs;
+
+use common::{
+  coinbase, mine, open_chain, push, spend, TempDir, COINBASE_VALUE, NOW,
+};
+use rbtc::{
+  storage::constants::BLOCKS_DIR,
+  util::{
+    config::Config,
+    constants::MAX_FUTURE_BLOCK_TIME,
+    params::ChainParams,
+    types::{
+      block::{self, merkle_root, Block},
+      chain::{ActiveChain, ChainTip, ChainTipStatus, Error, PushOutcome},
+      header::{bits_to_target, target_to_bits, Header},
+      txn::Txn,
+      txo::Txo,
+    },
+  },
+};
+
+/// Blocks stored ahead of an ancestor's data are connected, in order, once
+/// it arrives.
+#[test]
+fn connects_blocks_stored_out_of_order() {
+  let dir = TempDir::new("chain-out-of-order");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let mut blocks = Vec::new();
+  let mut prev = chain.last_block_hash();
+  for tag in 0..3 {
+    let block = mine(&chain, &prev, vec![coinbase(tag)]);
+    chain
+      .accept_headers(&[block.header().clone()], NOW)
+      .unwrap();
+    prev = block.hash();
+    blocks.push(block);
+  }
+
+  for block in blocks[1..].iter().rev() {
+    let outcome = chain.validate_and_push(block.clone(), None, NOW);
+    assert_eq!(outcome.unwrap(), PushOutcome::Stored);
+  }
+  assert_eq!(chain.height(), 0);
+  let outcome = chain.validate_and_push(blocks[0].clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(3));
+  assert_eq!(chain.last_block_hash(), blocks[2].hash());
+}
+
+/// The chain moves to a fork with more work, back once the fork's tip is
+/// invalidated, and to it again once the tip is reconsidered.
+#[test]
+fn follows_most_work_fork() {
+  let dir = TempDir::new("chain-fork");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let fork = push(&mut chain, vec![coinbase(0)]).hash();
+  let a = push(&mut chain, vec![coinbase(1)]);
+
+  let b1 = mine(&chain, &fork, vec![coinbase(2)]);
+  let outcome = chain.validate_and_push(b1.clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Stored);
+  assert_eq!(chain.last_block_hash(), a.hash());
+  let b2 = mine(&chain, &b1.hash(), vec![coinbase(3)]);
+  let outcome = chain.validate_and_push(b2.clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(2));
+  assert_eq!(chain.last_block_hash(), b2.hash());
+
+  chain.invalidate_block(&b2.hash()).unwrap();
+  assert_eq!(chain.height(), 2);
+  assert!([a.hash(), b1.hash()].contains(&chain.last_block_hash()));
+  assert_ne!(chain.best_header().hash(), b2.hash());
+
+  chain.reconsider_block(&b2.hash()).unwrap();
+  assert_eq!(chain.last_block_hash(), b2.hash());
+  assert_eq!(chain.best_header().hash(), b2.hash());
+}
+
+/// Blocks stored but not connected are still candidates after a restart.
+#[test]
+fn reopens_with_stored_fork() {
+  let dir = TempDir::new("chain-reopen");
+  let mut config = Config::default();
+  let mut chain = open_chain(&dir, &mut config);
+  let fork = push(&mut chain, vec![coinbase(0)]).hash();
+  push(&mut chain, vec![coinbase(1)]);
+  let b1 = mine(&chain, &fork, vec![coinbase(2)]);
+  chain.validate_and_push(b1.clone(), None, NOW).unwrap();
+  let b2 = mine(&chain, &b1.hash(), vec![coinbase(3)]);
+  chain.accept_headers(&[b2.header().clone()], NOW).unwrap();
+  chain.flush().unwrap();
+  drop(chain);
+
+  let mut chain = open_chain(&dir, &mut config);
+  assert_eq!(chain.best_header().hash(), b2.hash());
+  let outcome = chain.validate_and_push(b2.clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(2));
+}
+
+/// A block whose transactions are repeated to give the same merkle root is
+/// rejected before it is stored, and the real block is accepted after it,
+/// whether its parent is known or not.
+#[test]
+fn rejects_mutated_block() {
+  // Mine the blocks on one chain, and push them to another.
+  let mined_dir = TempDir::new("chain-mutated-mined");
+  let mut mined = open_chain(&mined_dir, &mut Config::default());
+  let parent = push(&mut mined, vec![coinbase(0)]);
+  let first = coinbase(1);
+  let second = spend(&first, 0, &[COINBASE_VALUE]);
+  let third = spend(&second, 0, &[COINBASE_VALUE]);
+  let txns = vec![first, second, third.clone()];
+  let block = mine(&mined, &parent.hash(), txns.clone());
+  let mut mutated_txns = txns;
+  mutated_txns.push(third);
+  assert_eq!(merkle_root(&mutated_txns), block.header().merkle_root());
+  let mutated = Block::new(block.header().clone(), 4, mutated_txns);
+  assert_eq!(mutated.hash(), block.hash());
+
+  // As an orphan, without its parent.
+  let dir = TempDir::new("chain-mutated");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let result = chain.validate_and_push(mutated.clone(), None, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::BlockError(block::Error::MutatedMerkleTree))
+  ));
+  assert!(chain.orphans().is_empty());
+
+  // As a block extending the tip.
+  chain.validate_and_push(parent, None, NOW).unwrap();
+  let result = chain.validate_and_push(mutated, None, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::BlockError(block::Error::MutatedMerkleTree))
+  ));
+  assert!(chain.index().get(&block.hash()).unwrap().pos().is_none());
+
+  let outcome = chain.validate_and_push(block.clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(1));
+  assert_eq!(chain.last_block_hash(), block.hash());
+}
+
+/// A stored block whose data no longer matches its header, e.g. after disk
+/// corruption, is not marked invalid: its data is discarded, and the block
+/// and its descendants connect once it is stored again.
+#[test]
+fn discards_mutated_data() {
+  let dir = TempDir::new("chain-discard");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let fork = push(&mut chain, vec![coinbase(0)]).hash();
+  let a = push(&mut chain, vec![coinbase(1)]);
+  let paid = Txn::new(0, 0, vec![], 1, vec![Txo::new(1, [0xab; 20])]);
+  let b1 = mine(&chain, &fork, vec![paid]);
+  let outcome = chain.validate_and_push(b1.clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Stored);
+
+  // Change the pubkey hash paid in the stored copy.
+  let mut corrupted = false;
+  for file in fs::read_dir(dir.0.join(BLOCKS_DIR)).unwrap() {
+    let path = file.unwrap().path();
+    let mut bytes = match fs::read(&path) {
+      Ok(bytes) => bytes,
+      Err(_) => continue,
+    };
+    if let Some(i) = bytes.windows(20).position(|w| w == [0xab; 20]) {
+      bytes[i] = 0xcd;
+      fs::write(&path, bytes).unwrap();
+      corrupted = true;
+    }
+  }
+  assert!(corrupted);
+
+  // The chain moves back to `a` after failing to move to `b2`.
+  let b2 = mine(&chain, &b1.hash(), vec![coinbase(2)]);
+  chain.validate_and_push(b2.clone(), None, NOW).unwrap();
+  assert_eq!(chain.last_block_hash(), a.hash());
+  let entry = chain.index().get(&b1.hash()).unwrap();
+  assert!(!entry.status().is_invalid());
+  assert!(entry.pos().is_none());
+
+  let outcome = chain.validate_and_push(b1, None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(2));
+  assert_eq!(chain.last_block_hash(), b2.hash());
+}
+
+/// Each network's parameters checkpoint its own genesis block.
+#[test]
+fn checkpoints_genesis_blocks() {
+  for params in [ChainParams::mainnet(), ChainParams::testnet()] {
+    let genesis = Block::genesis(params.genesis_timestamp);
+    assert_eq!(params.checkpoint(0), Some(genesis.hash()));
+  }
+}
+
+/// A header differing from the checkpoint at its height is rejected, and once
+/// the checkpointed block is indexed, so is any header forking below it.
+#[test]
+fn rejects_headers_against_checkpoints() {
+  // Mine the checkpointed chain on one chain, and push it to another.
+  let mined_dir = TempDir::new("chain-checkpoint-mined");
+  let mut mined = open_chain(&mined_dir, &mut Config::default());
+  let first = push(&mut mined, vec![coinbase(0)]);
+  let second = push(&mut mined, vec![coinbase(1)]);
+
+  let dir = TempDir::new("chain-checkpoint");
+  let config = Config { data_dir: dir.0.clone(), ..Config::default() };
+  let mut params = ChainParams::testnet();
+  params.checkpoints.push((2, second.hash()));
+  let mut chain = ActiveChain::open(params, &config).unwrap();
+  chain.validate_and_push(first.clone(), None, NOW).unwrap();
+
+  let other = mine(&chain, &first.hash(), vec![coinbase(2)]);
+  let result = chain.validate_and_push(other, None, NOW);
+  assert!(matches!(result, Err(Error::CheckpointMismatch(2))));
+  let outcome = chain.validate_and_push(second.clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(1));
+
+  let genesis = first.header().prev_block_hash();
+  let fork = mine(&chain, &genesis, vec![coinbase(3)]);
+  let result = chain.validate_and_push(fork, None, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::ForkBelowCheckpoint { height: 1, checkpoint: 2 })
+  ));
+  let extension = mine(&chain, &second.hash(), vec![coinbase(4)]);
+  let outcome = chain.validate_and_push(extension, None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(1));
+}
+
+/// Only blocks up to the assume-valid block are assumed valid, and only while
+/// it is on the best header chain with enough work.
+#[test]
+fn assumes_valid_below_trusted_block() {
+  let dir = TempDir::new("chain-assume-valid");
+  let mut config = Config::default();
+  let mut chain = open_chain(&dir, &mut config);
+  let blocks: Vec<_> = (0..3)
+    .map(|tag| push(&mut chain, vec![coinbase(tag)]))
+    .collect();
+  let fork = mine(&chain, &blocks[0].hash(), vec![coinbase(3)]);
+  chain.validate_and_push(fork.clone(), None, NOW).unwrap();
+  let work = chain.best_header().chain_work();
+  chain.flush().unwrap();
+  drop(chain);
+
+  let open = |assume_valid: &Block, min_chain_work| {
+    let mut params = ChainParams::testnet();
+    params.assume_valid = Some(assume_valid.hash());
+    params.min_chain_work = min_chain_work;
+    ActiveChain::open(params, &config).unwrap()
+  };
+  let chain = open(&blocks[1], work);
+  assert!(chain.is_assumed_valid(&blocks[0].hash()));
+  assert!(chain.is_assumed_valid(&blocks[1].hash()));
+  assert!(!chain.is_assumed_valid(&blocks[2].hash()));
+  assert!(!chain.is_assumed_valid(&fork.hash()));
+  drop(chain);
+
+  // Not while the best header has too little work.
+  let chain = open(&blocks[1], work * 2.0);
+  assert!(!chain.is_assumed_valid(&blocks[0].hash()));
+  drop(chain);
+
+  // Not while the assume-valid block is off the best header chain.
+  let chain = open(&fork, work);
+  assert!(!chain.is_assumed_valid(&blocks[0].hash()));
+  assert!(!chain.is_assumed_valid(&fork.hash()));
+}
+
+/// A block whose `bits` differ from the difficulty the chain requires of it is
+/// rejected, even with a valid proof-of-work for the target it claims.
+#[test]
+fn rejects_incorrect_bits() {
+  let dir = TempDir::new("chain-bits");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let parent = push(&mut chain, vec![coinbase(0)]);
+  let expected = chain.next_bits_after(&parent.hash());
+
+  // Claim a target a sixteenth of the required one.
+  let found = target_to_bits(bits_to_target(expected) >> 4);
+  let txns = vec![coinbase(1)];
+  let root = merkle_root(&txns);
+  let timestamp = parent.header().timestamp() + 1;
+  let block = (0..)
+    .map(|nonce| {
+      let header = Header::new(0, parent.hash(), root, timestamp, found, nonce);
+      Block::new(header, 1, txns.clone())
+    })
+    .find(|block| block.verify_nonce().is_ok())
+    .unwrap();
+  let result = chain.validate_and_push(block.clone(), None, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::IncorrectBits { expected: e, found: f })
+      if e == expected && f == found
+  ));
+  assert!(chain.index().get(&block.hash()).is_none());
+}
+
+/// A block's timestamp must be later than the median time past of its parent,
+/// and no more than `MAX_FUTURE_BLOCK_TIME` ahead of the adjusted time.
+#[test]
+fn checks_timestamp_bounds() {
+  let dir = TempDir::new("chain-timestamps");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  for tag in 0..12 {
+    push(&mut chain, vec![coinbase(tag)]);
+  }
+  let tip = chain.last_block_hash();
+  let header = |timestamp| Header::new(0, tip, [0; 32], timestamp, 0, 0);
+
+  let median_time_past = chain.median_time_past();
+  let result = chain.check_timestamp(&header(median_time_past), NOW);
+  assert!(matches!(
+    result,
+    Err(Error::TimestampTooOld { timestamp, median_time_past: m })
+      if timestamp == median_time_past && m == median_time_past
+  ));
+  chain
+    .check_timestamp(&header(median_time_past + 1), NOW)
+    .unwrap();
+
+  let latest = NOW + MAX_FUTURE_BLOCK_TIME;
+  chain.check_timestamp(&header(latest), NOW).unwrap();
+  let result = chain.check_timestamp(&header(latest + 1), NOW);
+  assert!(matches!(
+    result,
+    Err(Error::TimestampTooNew { timestamp, adjusted_time: NOW })
+      if timestamp == latest + 1
+  ));
+}
+
+/// Every chain tip is reported with how far its branch has been validated and
+/// how many blocks it forks off the active chain.
+#[test]
+fn reports_chain_tips() {
+  let dir = TempDir::new("chain-tips");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let fork = push(&mut chain, vec![coinbase(1)]).hash();
+
+  // Mine a branch of blocks with the given tags from the given block,
+  // offering each to the chain, and return their hashes.
+  let branch = |chain: &mut ActiveChain, prev, tags: &[u32]| {
+    let mut prev = prev;
+    let mut hashes = Vec::new();
+    for tag in tags {
+      let block = mine(chain, &prev, vec![coinbase(*tag)]);
+      chain.validate_and_push(block.clone(), None, NOW).unwrap();
+      prev = block.hash();
+      hashes.push(prev);
+    }
+    hashes
+  };
+
+  // A branch connected and then disconnected for one with more work.
+  let fork_branch = branch(&mut chain, fork, &[2, 3]);
+  let active = branch(&mut chain, fork, &[4, 5, 6]);
+  assert_eq!(chain.last_block_hash(), active[2]);
+
+  // A stored block with less work than the active chain.
+  let stored = branch(&mut chain, fork, &[7]);
+
+  // Headers whose blocks have not arrived.
+  let mut headers_only = active[0];
+  for tag in [8, 9] {
+    let block = mine(&chain, &headers_only, vec![coinbase(tag)]);
+    chain
+      .accept_headers(&[block.header().clone()], NOW)
+      .unwrap();
+    headers_only = block.hash();
+  }
+
+  // A branch that took over the active chain until it was invalidated.
+  let invalidated = branch(&mut chain, fork, &[10, 11, 12, 13]);
+  assert_eq!(chain.last_block_hash(), invalidated[3]);
+  chain.invalidate_block(&invalidated[0]).unwrap();
+  assert_eq!(chain.last_block_hash(), active[2]);
+
+  let tip = |hash, height, branch_len, status| ChainTip {
+    height,
+    hash,
+    branch_len,
+    status,
+  };
+  let mut expected = vec![
+    tip(invalidated[3], 5, 4, ChainTipStatus::Invalid),
+    tip(active[2], 4, 0, ChainTipStatus::Active),
+    tip(headers_only, 4, 2, ChainTipStatus::HeadersOnly),
+    tip(fork_branch[1], 3, 2, ChainTipStatus::ValidFork),
+    tip(stored[0], 2, 1, ChainTipStatus::ValidHeaders),
+  ];
+  expected.sort_by(|a, b| b.height.cmp(&a.height).then(a.hash.cmp(&b.hash)));
+  assert_eq!(chain.chain_tips(), expected);
+}
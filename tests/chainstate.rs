@@ -0,0 +1,84 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+
+use common::{
+  coinbase, mature_chain, open_chain, push, spend, TempDir, COINBASE_VALUE, NOW,
+};
+use rbtc::{
+  storage::constants::{CHAINSTATE_DIR, KV_LOG_FILE},
+  util::{
+    config::Config,
+    types::{chain::ActiveChain, coin::OutPoint, txn::Txn},
+  },
+};
+
+/// Return the value, height and origin of the coin at output `index` of
+/// `txn`, if unspent.
+fn coin(
+  chain: &mut ActiveChain,
+  txn: &Txn,
+  index: usize,
+) -> Option<(u64, u32, bool)> {
+  let outpoint = OutPoint::new(txn.hash(), index);
+  let coin = chain.coin(&outpoint).unwrap()?;
+  Some((coin.txo.value(), coin.height, coin.is_coinbase))
+}
+
+/// A flush interrupted after marking the chainstate with the blocks it is
+/// moving between, but before committing the new best block, is repaired on
+/// the next open, leaving the UTXO set a clean replay of the chain gives.
+#[test]
+fn repairs_interrupted_flush() {
+  let dir = TempDir::new("chainstate-interrupted");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  chain.flush().unwrap();
+
+  // Spend, and spend again, coins the flushed chainstate holds.
+  let first = spend(&coinbases[0], 0, &[COINBASE_VALUE / 2; 2]);
+  let second = spend(&first, 1, &[COINBASE_VALUE / 2]);
+  let third = spend(&coinbases[1], 0, &[COINBASE_VALUE]);
+  let blocks = [
+    push(&mut chain, vec![coinbase(10), first]),
+    push(&mut chain, vec![coinbase(11), second, third]),
+  ];
+  let log = dir.0.join(CHAINSTATE_DIR).join(KV_LOG_FILE);
+  let flushed_size = fs::metadata(&log).unwrap().len() as usize;
+  chain.flush().unwrap();
+  drop(chain);
+
+  // Cut off the last record of that flush, which commits its best block,
+  // leaving the one marking the flush as in progress.
+  let bytes = fs::read(&log).unwrap();
+  let mut offset = flushed_size;
+  let mut last_record = offset;
+  while offset < bytes.len() {
+    last_record = offset;
+    let len = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    offset += 8 + len as usize;
+  }
+  assert!(last_record > flushed_size);
+  let file = OpenOptions::new().write(true).open(&log).unwrap();
+  file.set_len(last_record as u64).unwrap();
+  drop(file);
+
+  let mut chain = open_chain(&dir, &mut config);
+  let clean_dir = TempDir::new("chainstate-clean");
+  let (mut clean, _) = mature_chain(&clean_dir, &mut Config::default(), 2);
+  for block in &blocks {
+    clean.validate_and_push(block.clone(), None, NOW).unwrap();
+  }
+  assert_eq!(chain.last_block_hash(), clean.last_block_hash());
+
+  let txns = blocks
+    .iter()
+    .flat_map(|block| block.txns())
+    .chain(&coinbases);
+  for txn in txns {
+    for index in 0..txn.txo_list().len() {
+      assert_eq!(coin(&mut chain, txn, index), coin(&mut clean, txn, index));
+    }
+  }
+  assert_eq!(coin(&mut chain, &coinbases[0], 0), None);
+}
@@ -0,0 +1,146 @@
+#![allow(dead_code)]
+
+use std::{env, fs, path::PathBuf, process};
+
+use rbtc::util::{
+  config::Config,
+  params::ChainParams,
+  types::{
+    block::{merkle_root, Block},
+    chain::ActiveChain,
+    header::Header,
+    txi::Txi,
+    txn::Txn,
+    txo::Txo,
+  },
+};
+
+/// The adjusted time every test validates against, well after any block
+/// timestamp the tests produce.
+pub const NOW: u32 = 2_000_000_000;
+
+/// The value of every coinbase output, in satoshis.
+pub const COINBASE_VALUE: u64 = 1_000_000;
+
+/// A data directory removed again when dropped.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+  /// Initialize an empty data directory unique to the given test name.
+  pub fn new(name: &str) -> Self {
+    let dir =
+      env::temp_dir().join(format!("rbtc-test-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    Self(dir)
+  }
+}
+
+impl Drop for TempDir {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.0);
+  }
+}
+
+/// Open a new testnet chain in the given data directory.
+pub fn open_chain(dir: &TempDir, config: &mut Config) -> ActiveChain {
+  config.data_dir = dir.0.clone();
+  ActiveChain::open(ChainParams::testnet(), config)
+    .expect("Failed to open chain")
+}
+
+/// Return a block of the given transactions extending the block with the
+/// given hash, with a nonce meeting the target the chain requires of it.
+pub fn mine(chain: &ActiveChain, prev: &[u8; 32], txns: Vec<Txn>) -> Block {
+  let prev_header = chain.index().get(prev).expect("Unknown parent").header();
+  let bits = chain.next_bits_after(prev);
+  let root = merkle_root(&txns);
+  let mut nonce = 0;
+  loop {
+    let header =
+      Header::new(0, *prev, root, prev_header.timestamp() + 1, bits, nonce);
+    let block = Block::new(header, txns.len() as u32, txns.clone());
+    if block.verify_nonce().is_ok() {
+      return block;
+    }
+    nonce += 1;
+  }
+}
+
+/// Mine a block of the given transactions on the chain's tip and connect it.
+pub fn push(chain: &mut ActiveChain, txns: Vec<Txn>) -> Block {
+  let block = mine(chain, &chain.last_block_hash(), txns);
+  chain
+    .validate_and_push(block.clone(), None, NOW)
+    .expect("Failed to push block");
+  block
+}
+
+/// Return a coinbase paying `COINBASE_VALUE`, made unique by `tag`.
+pub fn coinbase(tag: u32) -> Txn {
+  Txn::new(tag, 0, vec![], 1, vec![Txo::new(COINBASE_VALUE, [0u8; 20])])
+}
+
+/// Return a transaction spending output `index` of `prev` into outputs of
+/// the given values, with the given input sequence.
+pub fn spend_with_sequence(
+  prev: &Txn,
+  index: usize,
+  values: &[u64],
+  sequence: u32,
+) -> Txn {
+  spend_all(&[(prev, index)], values, sequence)
+}
+
+/// Return a transaction spending output `index` of `prev` into outputs of
+/// the given values, without signalling replaceability.
+pub fn spend(prev: &Txn, index: usize, values: &[u64]) -> Txn {
+  spend_with_sequence(prev, index, values, u32::MAX)
+}
+
+/// Return a transaction spending output `index` of `prev` into outputs of
+/// the given values, signalling replaceability.
+pub fn spend_rbf(prev: &Txn, index: usize, values: &[u64]) -> Txn {
+  spend_with_sequence(prev, index, values, 0)
+}
+
+/// Return a transaction spending the given outputs into outputs of the given
+/// values, every input with the given sequence.
+pub fn spend_all(
+  prevs: &[(&Txn, usize)],
+  values: &[u64],
+  sequence: u32,
+) -> Txn {
+  let inputs: Vec<_> = prevs
+    .iter()
+    .map(|(prev, index)| Txi::new(prev.hash(), *index, [0u8; 32], sequence))
+    .collect();
+  let outputs: Vec<_> = values
+    .iter()
+    .map(|value| Txo::new(*value, [1u8; 20]))
+    .collect();
+  Txn::new(
+    0,
+    inputs.len() as u32,
+    inputs,
+    outputs.len() as u32,
+    outputs,
+  )
+}
+
+/// Open a new testnet chain whose first `count` coinbases have matured, and
+/// return it with them.
+pub fn mature_chain(
+  dir: &TempDir,
+  config: &mut Config,
+  count: u32,
+) -> (ActiveChain, Vec<Txn>) {
+  let mut chain = open_chain(dir, config);
+  let coinbases: Vec<_> = (0..count).map(coinbase).collect();
+  for txn in &coinbases {
+    push(&mut chain, vec![txn.clone()]);
+  }
+  for tag in 0..100 {
+    push(&mut chain, vec![coinbase(1_000_000 + tag)]);
+  }
+  (chain, coinbases)
+}
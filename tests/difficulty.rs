@@ -0,0 +1,118 @@
+use ethnum::u256;
+use rbtc::{
+  mining::difficulty::{pow_limit, Asert, DifficultyAlgorithm, Epoch, Lwma},
+  util::{
+    constants::SHA256_HASH_SIZE,
+    types::header::{bits_to_target, target_to_bits, Header},
+  },
+};
+
+/// Target block time used by every simulation, in seconds.
+const T: u64 = 600;
+
+/// Hashrate at which the starting difficulty produces blocks on schedule, in
+/// multiples of the hashrate that mines proof-of-work-limit blocks in `T`.
+const BASE_HASHRATE: f64 = 1024.0;
+
+/// Mine `count` blocks on top of `headers` at the given relative hashrate,
+/// giving each block exactly its expected solve time under its required
+/// target.
+fn mine(
+  algorithm: &dyn DifficultyAlgorithm,
+  headers: &mut Vec<Header>,
+  hashrate: f64,
+  count: usize,
+) {
+  for _ in 0..count {
+    let prev_height = (headers.len() - 1) as u32;
+    let bits = algorithm.next_bits(prev_height, &|h| &headers[h as usize]);
+    let prev = headers.last().unwrap();
+    let difficulty = (pow_limit() / bits_to_target(bits)).as_f64();
+    let solve_time = (T as f64 * difficulty / hashrate).round().max(1.0);
+    headers.push(Header::new(
+      0,
+      prev.hash(),
+      [0u8; SHA256_HASH_SIZE],
+      prev.timestamp() + solve_time as u32,
+      bits,
+      0,
+    ));
+  }
+}
+
+/// Return the mean solve time of the last `count` blocks, in seconds.
+fn mean_solve_time(headers: &[Header], count: usize) -> f64 {
+  let last = &headers[headers.len() - 1];
+  let first = &headers[headers.len() - 1 - count];
+  (last.timestamp() - first.timestamp()) as f64 / count as f64
+}
+
+/// Start at equilibrium, then multiply the hashrate by eight, then divide the
+/// original hashrate by eight, checking that block times return to `T` after
+/// `settle` blocks each time.
+fn assert_recovers_from_shocks(
+  algorithm: &dyn DifficultyAlgorithm,
+  settle: usize,
+) {
+  let genesis_target = pow_limit() / u256::from(BASE_HASHRATE as u64);
+  let mut headers = vec![Header::new(
+    0,
+    [0u8; SHA256_HASH_SIZE],
+    [0u8; SHA256_HASH_SIZE],
+    1_600_000_000,
+    target_to_bits(genesis_target),
+    0,
+  )];
+
+  for hashrate in [BASE_HASHRATE, BASE_HASHRATE * 8.0, BASE_HASHRATE / 8.0] {
+    mine(algorithm, &mut headers, hashrate, settle);
+    let mean = mean_solve_time(&headers, settle / 4);
+    assert!(
+      (0.8 * T as f64..1.25 * T as f64).contains(&mean),
+      "mean solve time {:.0}s at hashrate {} after {} blocks",
+      mean,
+      hashrate,
+      settle
+    );
+  }
+}
+
+#[test]
+fn epoch_recovers_from_hashrate_shocks() {
+  let epoch = Epoch { window: 20, target_block_time: T, ..Epoch::default() };
+  assert_recovers_from_shocks(&epoch, 200);
+}
+
+#[test]
+fn lwma_recovers_from_hashrate_shocks() {
+  let lwma = Lwma { window: 45, target_block_time: T };
+  assert_recovers_from_shocks(&lwma, 300);
+}
+
+#[test]
+fn asert_recovers_from_hashrate_shocks() {
+  let asert =
+    Asert { anchor_height: 0, half_life: 12 * T, target_block_time: T };
+  assert_recovers_from_shocks(&asert, 300);
+}
+
+#[test]
+fn targets_never_exceed_pow_limit() {
+  let algorithms: [&dyn DifficultyAlgorithm; 3] = [
+    &Epoch { window: 20, target_block_time: T, ..Epoch::default() },
+    &Lwma { window: 45, target_block_time: T },
+    &Asert { anchor_height: 0, half_life: 12 * T, target_block_time: T },
+  ];
+  for algorithm in algorithms {
+    let mut headers = vec![Header::new(
+      0,
+      [0u8; SHA256_HASH_SIZE],
+      [0u8; SHA256_HASH_SIZE],
+      1_600_000_000,
+      target_to_bits(pow_limit()),
+      0,
+    )];
+    mine(algorithm, &mut headers, 0.01, 100);
+    assert!(headers.iter().all(|header| header.target() <= pow_limit()));
+  }
+}
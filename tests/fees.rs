@@ -0,0 +1,76 @@
+mod common;
+
+use std::fs;
+
+use common::TempDir;
+use rbtc::mempool::{
+  constants::FEE_ESTIMATES_FILE,
+  error::Error,
+  fees::{Confidence, FeeEstimator},
+};
+
+/// Return a distinct transaction hash for each `index`.
+fn hash(index: u32) -> [u8; 32] {
+  let mut hash = [0u8; 32];
+  hash[..4].copy_from_slice(&index.to_le_bytes());
+  hash
+}
+
+/// Return an estimator that saw transactions paying 50000 confirm in the
+/// next block, and transactions paying 2000 leave unconfirmed after 10.
+fn estimator_with_history() -> FeeEstimator {
+  let mut estimator = FeeEstimator::new();
+  for index in 0..10 {
+    estimator.track(hash(index), 100, 50_000);
+    estimator.track(hash(100 + index), 100, 2000);
+  }
+  let confirmed: Vec<_> = (0..10).map(hash).collect();
+  estimator.confirm_block(101, &confirmed);
+  for height in 102..=110 {
+    estimator.confirm_block(height, &[]);
+  }
+  for index in 0..10 {
+    estimator.untrack(&hash(100 + index));
+  }
+  estimator
+}
+
+/// Estimates come from the fee rates that confirmed in time, and are the same
+/// after the history is dumped and loaded again.
+#[test]
+fn estimates_survive_dump_and_load() {
+  let dir = TempDir::new("fees-dump");
+  fs::create_dir_all(&dir.0).unwrap();
+  let estimator = estimator_with_history();
+  let estimate = estimator.estimate(1, Confidence::High).unwrap();
+  assert!(estimate > 2000 && estimate <= 50_000);
+  assert_eq!(estimator.estimate(0, Confidence::High), None);
+
+  estimator.dump(&dir.0).unwrap();
+  let mut loaded = FeeEstimator::new();
+  assert_eq!(loaded.estimate(1, Confidence::High), None);
+  loaded.load(&dir.0).unwrap();
+  for target in [1, 10, 48] {
+    for confidence in [Confidence::Low, Confidence::Medium, Confidence::High] {
+      assert_eq!(
+        loaded.estimate(target, confidence),
+        estimator.estimate(target, confidence)
+      );
+    }
+  }
+}
+
+/// Loading without a dump leaves the history empty, and a dump of another
+/// version is refused.
+#[test]
+fn loads_only_known_dumps() {
+  let dir = TempDir::new("fees-load");
+  fs::create_dir_all(&dir.0).unwrap();
+  let mut estimator = FeeEstimator::new();
+  estimator.load(&dir.0).unwrap();
+  assert_eq!(estimator.estimate(1, Confidence::Low), None);
+
+  fs::write(dir.0.join(FEE_ESTIMATES_FILE), 99u32.to_le_bytes()).unwrap();
+  let result = estimator.load(&dir.0);
+  assert!(matches!(result, Err(Error::UnsupportedDumpVersion(99))));
+}
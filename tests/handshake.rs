@@ -0,0 +1,86 @@
+mod common;
+
+use std::{
+  net::Ipv4Addr,
+  sync::{Arc, Mutex},
+};
+
+use async_std::channel;
+use common::{open_chain, TempDir};
+use rbtc::{
+  events::validation::ValidationEvents,
+  mempool::pool::Mempool,
+  networking::{
+    messages::Msg,
+    sync::{Event, SyncManager},
+  },
+  util::{
+    config::Config,
+    constants::MIN_TIME_SAMPLES,
+    time::{MockClock, TimeSource},
+  },
+};
+
+/// The local clock of the node under test.
+const LOCAL_TIME: u32 = 2_000_000_000;
+
+/// Return a sync manager on a new chain reading the given time source.
+fn sync_manager(dir: &TempDir, time: Arc<Mutex<TimeSource>>) -> SyncManager {
+  let mut config = Config::default();
+  let chain = open_chain(dir, &mut config);
+  let (validation_events, _queue) = ValidationEvents::new();
+  SyncManager::new(
+    Arc::new(Mutex::new(chain)),
+    Arc::new(Mutex::new(Mempool::new(&config))),
+    time,
+    validation_events,
+  )
+}
+
+/// A newly connected peer is sent our clock before anything else.
+#[test]
+fn sends_version_on_connect() {
+  let dir = TempDir::new("handshake-send");
+  let clock = Arc::new(MockClock::new(LOCAL_TIME));
+  let time = Arc::new(Mutex::new(TimeSource::new(clock)));
+  let mut sync = sync_manager(&dir, time);
+
+  let (sender, receiver) = channel::unbounded();
+  sync.handle_event(Event::Connected(Ipv4Addr::new(10, 0, 0, 1), sender));
+  match receiver.try_recv() {
+    Ok(Msg::Version { timestamp }) => assert_eq!(timestamp, LOCAL_TIME),
+    other => panic!("Expected a version message first, got {:?}", other),
+  }
+}
+
+/// The clocks peers send in their `Version` messages move the
+/// network-adjusted time once there are enough of them, one sample per peer.
+#[test]
+fn version_messages_adjust_time() {
+  let dir = TempDir::new("handshake-adjust");
+  let clock = Arc::new(MockClock::new(LOCAL_TIME));
+  let time = Arc::new(Mutex::new(TimeSource::new(clock)));
+  let mut sync = sync_manager(&dir, time.clone());
+
+  let mut receivers = Vec::new();
+  for i in 0..MIN_TIME_SAMPLES as u8 {
+    let peer = Ipv4Addr::new(10, 0, 0, i + 1);
+    let (sender, receiver) = channel::unbounded();
+    receivers.push(receiver);
+    sync.handle_event(Event::Connected(peer, sender));
+
+    // Later messages from a peer already sampled are ignored.
+    let version = Msg::Version { timestamp: LOCAL_TIME + 60 };
+    sync.handle_event(Event::Message(peer, version));
+    let version = Msg::Version { timestamp: LOCAL_TIME + 600 };
+    sync.handle_event(Event::Message(peer, version));
+
+    let offset = time.lock().unwrap().offset();
+    if (i as usize) < MIN_TIME_SAMPLES - 1 {
+      assert_eq!(offset, 0);
+    } else {
+      assert_eq!(offset, 60);
+    }
+  }
+  assert_eq!(time.lock().unwrap().adjusted_time(), LOCAL_TIME + 60);
+}
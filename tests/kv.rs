@@ -0,0 +1,84 @@
+mod common;
+
+use std::{
+  fs::{self, OpenOptions},
+  io::Write,
+};
+
+use common::TempDir;
+use rbtc::storage::{
+  constants::{KV_COMPACT_THRESHOLD, KV_LOG_FILE},
+  kv::{KvStore, WriteBatch},
+};
+
+/// Return a batch setting each key to its index repeated `len` times.
+fn batch(keys: &[&str], len: usize) -> WriteBatch {
+  let mut batch = WriteBatch::new();
+  for (i, key) in keys.iter().enumerate() {
+    batch.put(key.as_bytes(), &vec![i as u8; len]);
+  }
+  batch
+}
+
+/// Compaction keeps exactly the live values, across a reopen.
+#[test]
+fn compact_keeps_live_values() {
+  let dir = TempDir::new("kv-compact");
+  let mut store = KvStore::open(&dir.0).unwrap();
+  store.write(batch(&["a", "b", "c"], 1000), false).unwrap();
+  store.write(batch(&["b"], 10), false).unwrap();
+  let mut delete = WriteBatch::new();
+  delete.delete(b"c");
+  store.write(delete, false).unwrap();
+  store.compact().unwrap();
+
+  let store = KvStore::open(&dir.0).unwrap();
+  assert_eq!(store.get(b"a").unwrap(), Some(vec![0u8; 1000]));
+  assert_eq!(store.get(b"b").unwrap(), Some(vec![0u8; 10]));
+  assert_eq!(store.get(b"c").unwrap(), None);
+}
+
+/// A torn record at the end of the log is dropped on open, so batches
+/// written afterwards survive the next open.
+#[test]
+fn torn_record_is_cut_off() {
+  let dir = TempDir::new("kv-torn");
+  let mut store = KvStore::open(&dir.0).unwrap();
+  store.write(batch(&["a"], 100), true).unwrap();
+  drop(store);
+
+  let mut log = OpenOptions::new()
+    .append(true)
+    .open(dir.0.join(KV_LOG_FILE))
+    .unwrap();
+  log.write_all(&[200, 0, 0, 0, 1, 2, 3, 4, 5]).unwrap();
+  drop(log);
+
+  let mut store = KvStore::open(&dir.0).unwrap();
+  store.write(batch(&["b", "c"], 100), true).unwrap();
+  drop(store);
+
+  let store = KvStore::open(&dir.0).unwrap();
+  assert_eq!(store.get(b"a").unwrap(), Some(vec![0u8; 100]));
+  assert_eq!(store.get(b"c").unwrap(), Some(vec![1u8; 100]));
+}
+
+/// Overwriting values compacts the log once most of it is dead, and the store
+/// keeps writing to the compacted log, leaving no temporary file behind.
+#[test]
+fn writes_continue_after_compaction() {
+  let dir = TempDir::new("kv-auto-compact");
+  let mut store = KvStore::open(&dir.0).unwrap();
+  let log = dir.0.join(KV_LOG_FILE);
+  for _ in 0..(KV_COMPACT_THRESHOLD >> 16) + 2 {
+    store.write(batch(&["a"], 1 << 16), false).unwrap();
+  }
+  assert!(fs::metadata(&log).unwrap().len() < KV_COMPACT_THRESHOLD);
+  assert!(!log.with_extension("tmp").exists());
+
+  store.write(batch(&["b", "c"], 10), true).unwrap();
+  drop(store);
+  let store = KvStore::open(&dir.0).unwrap();
+  assert_eq!(store.get(b"a").unwrap(), Some(vec![0u8; 1 << 16]));
+  assert_eq!(store.get(b"c").unwrap(), Some(vec![1u8; 10]));
+}
@@ -0,0 +1,610 @@
+mod common;
+
+use std::{borrow::Borrow, sync::Arc};
+
+use async_std::task;
+use common::{
+  coinbase, mature_chain, push, spend, spend_all, spend_rbf, TempDir,
+  COINBASE_VALUE, NOW,
+};
+use rbtc::{
+  events::validation::{ValidationEvent, ValidationEvents},
+  mempool::{
+    constants::{
+      MAX_ANCESTORS, MAX_DESCENDANTS, MAX_REPLACEMENT_EVICTIONS,
+      MIN_FEE_RATE_HALF_LIFE, MIN_RELAY_FEE_RATE,
+    },
+    error::Error,
+    pool::{fee_rate, Mempool, RemovalReason},
+  },
+  util::{
+    config::Config,
+    constants::SEQUENCE_FINAL,
+    types::{coin::OutPoint, txn::Txn},
+  },
+};
+
+/// A fee comfortably above the minimum relay fee of a one-input transaction.
+const FEE: u64 = 10_000;
+
+/// Return the hashes of the given transactions, in order.
+fn hashes<T: Borrow<Txn>>(txns: &[T]) -> Vec<[u8; 32]> {
+  txns.iter().map(|txn| txn.borrow().hash()).collect()
+}
+
+/// Transactions are only accepted if their inputs exist, are spendable, and
+/// cover their outputs plus the minimum fee.
+#[test]
+fn validates_transactions() {
+  let dir = TempDir::new("mempool-validate");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 1);
+  let mut mempool = Mempool::new(&config);
+
+  let result = mempool.accept(coinbase(7), &mut chain, NOW);
+  assert!(matches!(result, Err(Error::Coinbase)));
+
+  let unknown = spend(&coinbase(7), 0, &[COINBASE_VALUE - FEE]);
+  let result = mempool.accept(unknown, &mut chain, NOW);
+  assert!(matches!(result, Err(Error::MissingInput(_))));
+
+  let recent = chain.block_at_height(chain.height()).unwrap().unwrap();
+  let immature = spend(&recent.txns()[0], 0, &[COINBASE_VALUE - FEE]);
+  let result = mempool.accept(immature, &mut chain, NOW);
+  assert!(matches!(result, Err(Error::ImmatureCoinbaseSpend(_))));
+
+  let overspend = spend(&coinbases[0], 0, &[COINBASE_VALUE + 1]);
+  let result = mempool.accept(overspend, &mut chain, NOW);
+  assert!(matches!(result, Err(Error::OutputsExceedInputs { .. })));
+
+  let free = spend(&coinbases[0], 0, &[COINBASE_VALUE]);
+  let result = mempool.accept(free, &mut chain, NOW);
+  assert!(matches!(result, Err(Error::FeeTooLow { .. })));
+
+  let twice = spend_all(
+    &[(&coinbases[0], 0), (&coinbases[0], 0)],
+    &[COINBASE_VALUE - FEE],
+    SEQUENCE_FINAL,
+  );
+  let result = mempool.accept(twice, &mut chain, NOW);
+  assert!(matches!(result, Err(Error::DuplicateInput(_))));
+
+  let parent = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  let hash = mempool.accept(parent.clone(), &mut chain, NOW).unwrap();
+  let entry = mempool.get(&hash).unwrap();
+  assert_eq!(entry.fee(), FEE);
+  assert_eq!(entry.size(), parent.size());
+  assert_eq!(mempool.size(), parent.size());
+  let result = mempool.accept(parent.clone(), &mut chain, NOW);
+  assert!(matches!(result, Err(Error::AlreadyInMempool(_))));
+
+  // Outputs of mempool transactions may be spent, but not twice.
+  let child = spend(&parent, 0, &[COINBASE_VALUE - 2 * FEE]);
+  mempool.accept(child, &mut chain, NOW).unwrap();
+  let double_spend = spend(&coinbases[0], 0, &[COINBASE_VALUE - 2 * FEE]);
+  let result = mempool.accept(double_spend, &mut chain, NOW);
+  assert!(
+    matches!(result, Err(Error::Conflict(_, spender)) if spender == hash)
+  );
+  assert_eq!(mempool.len(), 2);
+}
+
+/// A block removes the transactions it confirms, and those conflicting with
+/// them along with their descendants, and its transactions are turned away
+/// afterwards.
+#[test]
+fn removes_transactions_for_block() {
+  let dir = TempDir::new("mempool-block");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  let mut mempool = Mempool::new(&config);
+
+  let confirmed = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  let child = spend(&confirmed, 0, &[COINBASE_VALUE - 2 * FEE]);
+  let conflicted = spend(&coinbases[1], 0, &[COINBASE_VALUE - FEE]);
+  let conflicted_child = spend(&conflicted, 0, &[COINBASE_VALUE - 2 * FEE]);
+  for txn in [&confirmed, &child, &conflicted, &conflicted_child] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+
+  let conflict = spend(&coinbases[1], 0, &[COINBASE_VALUE - 2 * FEE]);
+  let block = push(
+    &mut chain,
+    vec![coinbase(7), confirmed.clone(), conflict.clone()],
+  );
+  mempool.remove_for_block(&block, chain.height());
+  let remaining: Vec<_> = mempool.entries().map(|entry| entry.hash()).collect();
+  assert_eq!(remaining, vec![child.hash()]);
+  assert_eq!(mempool.size(), child.size());
+  assert_eq!(
+    mempool.spender(&OutPoint::new(confirmed.hash(), 0)),
+    Some(child.hash())
+  );
+
+  let result = mempool.accept(confirmed, &mut chain, NOW);
+  assert!(matches!(result, Err(Error::AlreadyConfirmed(_))));
+  let result = mempool.accept(conflicted, &mut chain, NOW);
+  assert!(matches!(result, Err(Error::MissingInput(_))));
+}
+
+/// A transaction spending outputs already spent in the mempool is only let in
+/// if they signal replaceability, or full replace-by-fee is on.
+#[test]
+fn replaces_signalling_transactions() {
+  let dir = TempDir::new("mempool-rbf-signal");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  let mut mempool = Mempool::new(&config);
+
+  let final_txn = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  mempool.accept(final_txn.clone(), &mut chain, NOW).unwrap();
+  let replacement = spend(&coinbases[0], 0, &[COINBASE_VALUE - 3 * FEE]);
+  let result = mempool.accept(replacement.clone(), &mut chain, NOW);
+  assert!(matches!(result, Err(Error::Conflict(..))));
+
+  let original = spend_rbf(&coinbases[1], 0, &[COINBASE_VALUE - FEE]);
+  let child = spend(&original, 0, &[COINBASE_VALUE - 2 * FEE]);
+  mempool.accept(original.clone(), &mut chain, NOW).unwrap();
+  mempool.accept(child.clone(), &mut chain, NOW).unwrap();
+  let rbf_replacement = spend(&coinbases[1], 0, &[COINBASE_VALUE - 3 * FEE]);
+  mempool
+    .accept(rbf_replacement.clone(), &mut chain, NOW)
+    .unwrap();
+  assert!(!mempool.contains(&original.hash()));
+  assert!(!mempool.contains(&child.hash()));
+  assert_eq!(mempool.len(), 2);
+  assert_eq!(mempool.size(), final_txn.size() + rbf_replacement.size());
+
+  let mut config = Config { mempool_full_rbf: true, ..config };
+  let full_rbf_dir = TempDir::new("mempool-rbf-full");
+  let (mut chain, _) = mature_chain(&full_rbf_dir, &mut config, 1);
+  let mut mempool = Mempool::new(&config);
+  mempool.accept(final_txn.clone(), &mut chain, NOW).unwrap();
+  mempool
+    .accept(replacement.clone(), &mut chain, NOW)
+    .unwrap();
+  assert!(!mempool.contains(&final_txn.hash()));
+  assert!(mempool.contains(&replacement.hash()));
+}
+
+/// A replacement must pay a higher fee rate than each transaction it
+/// conflicts with, and more fee than everything it evicts, and may not spend
+/// other unconfirmed outputs or outputs of what it evicts.
+#[test]
+fn rejects_unworthy_replacements() {
+  let dir = TempDir::new("mempool-rbf-rules");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  let mut mempool = Mempool::new(&config);
+
+  let original = spend_rbf(
+    &coinbases[0],
+    0,
+    &[COINBASE_VALUE / 2 - FEE, COINBASE_VALUE / 2],
+  );
+  let child = spend(&original, 0, &[COINBASE_VALUE / 2 - 2 * FEE]);
+  let other = spend(&coinbases[1], 0, &[COINBASE_VALUE - FEE]);
+  for txn in [&original, &child, &other] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+
+  let same_rate = spend(
+    &coinbases[0],
+    0,
+    &[COINBASE_VALUE / 2 - FEE, COINBASE_VALUE / 2],
+  );
+  let result = mempool.accept(same_rate, &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::ReplacementFeeRateTooLow { .. })
+  ));
+
+  // A higher rate, but not enough to also pay for the evicted child.
+  let cheap = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE - 1000]);
+  let result = mempool.accept(cheap, &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::InsufficientReplacementFee { fee, min_fee })
+      if fee == FEE + 1000 && min_fee > 2 * FEE
+  ));
+
+  let new_input = spend_all(
+    &[(&coinbases[0], 0), (&other, 0)],
+    &[COINBASE_VALUE],
+    SEQUENCE_FINAL,
+  );
+  let result = mempool.accept(new_input, &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::NewUnconfirmedInput(outpoint)) if outpoint.txn_hash == other.hash()
+  ));
+
+  let spends_replaced = spend_all(
+    &[(&coinbases[0], 0), (&original, 1)],
+    &[COINBASE_VALUE],
+    SEQUENCE_FINAL,
+  );
+  let result = mempool.accept(spends_replaced, &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::SpendsReplacedOutput(outpoint))
+      if outpoint.txn_hash == original.hash()
+  ));
+  assert_eq!(mempool.len(), 3);
+}
+
+/// A replacement may not evict more than `MAX_REPLACEMENT_EVICTIONS`
+/// transactions, counting the descendants of those it conflicts with.
+#[test]
+fn limits_replacement_evictions() {
+  let dir = TempDir::new("mempool-rbf-evictions");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 5);
+  let mut mempool = Mempool::new(&config);
+
+  // Five chains of 21 transactions each.
+  for coinbase in &coinbases {
+    let mut txn = spend_rbf(coinbase, 0, &[COINBASE_VALUE - FEE]);
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+    for depth in 2..=21 {
+      txn = spend(&txn, 0, &[COINBASE_VALUE - depth * FEE]);
+      mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+    }
+  }
+  assert_eq!(mempool.len(), 105);
+
+  let prevs: Vec<_> = coinbases.iter().map(|coinbase| (coinbase, 0)).collect();
+  let replacement = spend_all(&prevs, &[COINBASE_VALUE], SEQUENCE_FINAL);
+  let result = mempool.accept(replacement, &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::TooManyReplacements { count: 105, max })
+      if max == MAX_REPLACEMENT_EVICTIONS
+  ));
+
+  let replacement = spend_all(&prevs[..4], &[COINBASE_VALUE], SEQUENCE_FINAL);
+  mempool.accept(replacement, &mut chain, NOW).unwrap();
+  assert_eq!(mempool.len(), 22);
+}
+
+/// Descendants are listed parents first, even where one spends from
+/// several others.
+#[test]
+fn lists_descendants_parents_first() {
+  let dir = TempDir::new("mempool-descendants");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 1);
+  let mut mempool = Mempool::new(&config);
+
+  let half = COINBASE_VALUE / 2;
+  let top = spend(&coinbases[0], 0, &[half, half - FEE]);
+  let left = spend(&top, 0, &[half - FEE]);
+  let right = spend(&top, 1, &[half - 2 * FEE]);
+  let bottom = spend_all(
+    &[(&left, 0), (&right, 0)],
+    &[2 * half - 4 * FEE],
+    SEQUENCE_FINAL,
+  );
+  for txn in [&top, &left, &right, &bottom] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+  assert_eq!(mempool.get(&bottom.hash()).unwrap().ancestor_count(), 4);
+  assert_eq!(mempool.get(&top.hash()).unwrap().descendant_count(), 4);
+
+  let descendants = mempool.descendants(&top.hash());
+  let position = |txn: &Txn| {
+    descendants
+      .iter()
+      .position(|hash| *hash == txn.hash())
+      .unwrap()
+  };
+  assert_eq!(descendants.len(), 4);
+  assert_eq!(position(&top), 0);
+  assert!(position(&left) < position(&bottom));
+  assert!(position(&right) < position(&bottom));
+
+  mempool.remove_with_descendants(&top.hash(), RemovalReason::Replaced);
+  assert!(mempool.is_empty());
+  assert_eq!(mempool.size(), 0);
+}
+
+/// A child paying a high fee pulls its low-fee parent into a block ahead of
+/// transactions paying more than the parent, and a package too big for what
+/// is left of the block is passed over.
+#[test]
+fn selects_child_pays_for_parent() {
+  let dir = TempDir::new("mempool-cpfp");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  let mut mempool = Mempool::new(&config);
+
+  let parent = spend(&coinbases[0], 0, &[COINBASE_VALUE - 200]);
+  let child = spend(&parent, 0, &[COINBASE_VALUE - 200 - 5 * FEE]);
+  let other = spend(&coinbases[1], 0, &[COINBASE_VALUE - FEE]);
+  for txn in [&parent, &child, &other] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+  assert!(mempool.get(&parent.hash()).unwrap().fee_rate() < FEE);
+
+  let txns = mempool.block_txns(usize::MAX);
+  assert_eq!(hashes(&txns), hashes(&[&parent, &child, &other]));
+  let txns = mempool.block_txns(parent.size() + child.size());
+  assert_eq!(hashes(&txns), hashes(&[&parent, &child]));
+  let txns = mempool.block_txns(parent.size() + child.size() - 1);
+  assert_eq!(hashes(&txns), hashes(&[&other]));
+}
+
+/// Once a package is chosen, its members' other descendants are ranked by
+/// what they pay along with their ancestors not chosen yet.
+#[test]
+fn selects_by_remaining_package() {
+  let dir = TempDir::new("mempool-modified");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  let mut mempool = Mempool::new(&config);
+
+  let half = COINBASE_VALUE / 2;
+  let parent = spend(&coinbases[0], 0, &[half, half - 1000]);
+  let rich_child = spend(&parent, 0, &[half - 20_000]);
+  let poor_child = spend(&parent, 1, &[half - 1000 - 5000]);
+  let other = spend(&coinbases[1], 0, &[COINBASE_VALUE - 3000]);
+  for txn in [&parent, &rich_child, &poor_child, &other] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+
+  // `poor_child` pays less than `other` along with `parent`, but more alone.
+  let poor_entry = mempool.get(&poor_child.hash()).unwrap();
+  let other_entry = mempool.get(&other.hash()).unwrap();
+  assert!(poor_entry.ancestor_fee_rate() < other_entry.fee_rate());
+  assert!(poor_entry.fee_rate() > other_entry.fee_rate());
+
+  let txns = mempool.block_txns(usize::MAX);
+  assert_eq!(
+    hashes(&txns),
+    hashes(&[&parent, &rich_child, &poor_child, &other])
+  );
+}
+
+/// A transaction may not have more than `MAX_ANCESTORS` in-mempool
+/// ancestors, nor give one more than `MAX_DESCENDANTS` descendants, counting
+/// itself.
+#[test]
+fn limits_packages() {
+  let dir = TempDir::new("mempool-limits");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  let mut mempool = Mempool::new(&config);
+
+  let mut txn = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  for depth in 2..=MAX_ANCESTORS as u64 {
+    txn = spend(&txn, 0, &[COINBASE_VALUE - depth * FEE]);
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+  let too_deep = spend(&txn, 0, &[COINBASE_VALUE - 30 * FEE]);
+  let result = mempool.accept(too_deep, &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::TooManyAncestors { count, max })
+      if count == MAX_ANCESTORS + 1 && max == MAX_ANCESTORS
+  ));
+
+  let fanout = MAX_DESCENDANTS;
+  let root = spend(&coinbases[1], 0, &vec![FEE; fanout]);
+  mempool.accept(root.clone(), &mut chain, NOW).unwrap();
+  for index in 0..fanout - 1 {
+    let child = spend(&root, index, &[FEE / 2]);
+    mempool.accept(child, &mut chain, NOW).unwrap();
+  }
+  let root_entry = mempool.get(&root.hash()).unwrap();
+  assert_eq!(root_entry.descendant_count(), MAX_DESCENDANTS);
+  let one_too_many = spend(&root, fanout - 1, &[FEE / 2]);
+  let result = mempool.accept(one_too_many, &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::TooManyDescendants { ancestor, count, .. })
+      if ancestor == root.hash() && count == MAX_DESCENDANTS + 1
+  ));
+}
+
+/// Past its size limit, the mempool evicts what pays the lowest fee rate and
+/// raises its minimum fee rate above it, which then halves every
+/// `MIN_FEE_RATE_HALF_LIFE`.
+#[test]
+fn evicts_lowest_fee_rate() {
+  let dir = TempDir::new("mempool-size");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 6);
+  let txns: Vec<_> = coinbases
+    .iter()
+    .zip([1000, 2000, 3000, 4000, 1500, 1000])
+    .map(|(coinbase, fee)| spend(coinbase, 0, &[COINBASE_VALUE - fee]))
+    .collect();
+  let config = Config { mempool_max_size: 3 * txns[0].size(), ..config };
+  let mut mempool = Mempool::new(&config);
+
+  for txn in &txns[..3] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+  assert_eq!(mempool.min_fee_rate(NOW), MIN_RELAY_FEE_RATE);
+  mempool.accept(txns[3].clone(), &mut chain, NOW).unwrap();
+  assert!(!mempool.contains(&txns[0].hash()));
+  assert_eq!(mempool.len(), 3);
+  let raised = fee_rate(1000, txns[0].size()) + MIN_RELAY_FEE_RATE;
+  assert_eq!(mempool.min_fee_rate(NOW), raised);
+
+  // Paying above the minimum, but below everything else in the mempool.
+  let result = mempool.accept(txns[4].clone(), &mut chain, NOW);
+  assert!(matches!(result, Err(Error::MempoolFull)));
+  assert!(!mempool.contains(&txns[4].hash()));
+  let raised = fee_rate(1500, txns[4].size()) + MIN_RELAY_FEE_RATE;
+  assert_eq!(mempool.min_fee_rate(NOW), raised);
+
+  let result = mempool.accept(txns[5].clone(), &mut chain, NOW);
+  assert!(matches!(
+    result,
+    Err(Error::FeeTooLow { min_fee_rate, .. }) if min_fee_rate == raised
+  ));
+  let half_life = NOW + MIN_FEE_RATE_HALF_LIFE;
+  assert_eq!(mempool.min_fee_rate(half_life), raised / 2);
+  let later = NOW + 10 * MIN_FEE_RATE_HALF_LIFE;
+  assert_eq!(mempool.min_fee_rate(later), MIN_RELAY_FEE_RATE);
+}
+
+/// A transaction evicted as soon as it is accepted is never announced, while
+/// one accepted in its place is announced after the evictions it caused.
+#[test]
+fn announces_only_surviving_transactions() {
+  let dir = TempDir::new("mempool-size-events");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 5);
+  let txns: Vec<_> = coinbases
+    .iter()
+    .zip([1000, 2000, 3000, 4000, 1500])
+    .map(|(coinbase, fee)| spend(coinbase, 0, &[COINBASE_VALUE - fee]))
+    .collect();
+  let config = Config { mempool_max_size: 3 * txns[0].size(), ..config };
+  let mut mempool = Mempool::new(&config);
+  let (events, queue) = ValidationEvents::new();
+  let receiver = events.subscribe();
+  task::spawn(queue.dispatch());
+  mempool.set_validation_events(events.clone());
+
+  for txn in &txns[..4] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+  let result = mempool.accept(txns[4].clone(), &mut chain, NOW);
+  assert!(matches!(result, Err(Error::MempoolFull)));
+  task::block_on(events.sync(0));
+
+  let mut received = Vec::new();
+  while let Ok(event) = receiver.try_recv() {
+    received.push(match event {
+      ValidationEvent::TransactionAddedToMempool(txn) => (txn.hash(), None),
+      ValidationEvent::TransactionRemovedFromMempool(txn, reason) => {
+        (txn.hash(), Some(reason))
+      },
+      other => panic!("Expected a mempool event, got {:?}", other),
+    });
+  }
+  assert_eq!(
+    received,
+    vec![
+      (txns[0].hash(), None),
+      (txns[1].hash(), None),
+      (txns[2].hash(), None),
+      (txns[0].hash(), Some(RemovalReason::SizeLimit)),
+      (txns[3].hash(), None),
+    ]
+  );
+}
+
+/// Transactions are dropped, along with their descendants, once in the
+/// mempool for longer than its expiry.
+#[test]
+fn expires_old_transactions() {
+  let dir = TempDir::new("mempool-expiry");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 3);
+  let config = Config { mempool_expiry: 100, ..config };
+  let mut mempool = Mempool::new(&config);
+
+  let old = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  let child = spend(&old, 0, &[COINBASE_VALUE - 2 * FEE]);
+  let recent = spend(&coinbases[1], 0, &[COINBASE_VALUE - FEE]);
+  mempool.accept(old.clone(), &mut chain, NOW).unwrap();
+  mempool.accept(child.clone(), &mut chain, NOW + 90).unwrap();
+  mempool
+    .accept(recent.clone(), &mut chain, NOW + 90)
+    .unwrap();
+
+  mempool.expire(NOW + 100);
+  assert_eq!(mempool.len(), 3);
+  mempool.expire(NOW + 101);
+  assert_eq!(hashes(&mempool.block_txns(usize::MAX)), hashes(&[&recent]));
+
+  // Adding a transaction expires the rest.
+  let last = spend(&coinbases[2], 0, &[COINBASE_VALUE - FEE]);
+  mempool.accept(last.clone(), &mut chain, NOW + 191).unwrap();
+  assert_eq!(hashes(&mempool.block_txns(usize::MAX)), hashes(&[&last]));
+}
+
+/// Dumped transactions are loaded with the times they first entered, but
+/// validated and evicted as of the time they are loaded.
+#[test]
+fn loads_dump_at_current_time() {
+  let dir = TempDir::new("mempool-dump");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 4);
+  let txns: Vec<_> = coinbases
+    .iter()
+    .zip([1000, 2000, 3000, 4000])
+    .map(|(coinbase, fee)| spend(coinbase, 0, &[COINBASE_VALUE - fee]))
+    .collect();
+  let entered = NOW - 2 * MIN_FEE_RATE_HALF_LIFE;
+  let mut mempool = Mempool::new(&config);
+  for txn in &txns {
+    mempool.accept(txn.clone(), &mut chain, entered).unwrap();
+  }
+  mempool.dump(&dir.0).unwrap();
+
+  let config = Config { mempool_max_size: 3 * txns[0].size(), ..config };
+  let mut mempool = Mempool::new(&config);
+  assert_eq!(mempool.load(&dir.0, &mut chain, NOW).unwrap(), 4);
+  assert_eq!(mempool.len(), 3);
+  assert!(!mempool.contains(&txns[0].hash()));
+  assert_eq!(mempool.get(&txns[1].hash()).unwrap().time(), entered);
+  let raised = fee_rate(1000, txns[0].size()) + MIN_RELAY_FEE_RATE;
+  assert_eq!(mempool.min_fee_rate(NOW), raised);
+}
+
+/// The transactions of disconnected blocks return to the mempool, joining the
+/// packages of their children there, and entries spending outputs the chain
+/// no longer has, or coinbase outputs no longer mature, are evicted.
+#[test]
+fn readmits_disconnected_transactions() {
+  let dir = TempDir::new("mempool-reorg");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 3);
+  let mut mempool = Mempool::new(&config);
+
+  let block_coinbase = coinbase(7);
+  let returned = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  let lost = spend(&block_coinbase, 0, &[COINBASE_VALUE - FEE]);
+  let block = push(
+    &mut chain,
+    vec![block_coinbase, returned.clone(), lost.clone()],
+  );
+  mempool.remove_for_block(&block, chain.height());
+  let returned_child = spend(&returned, 0, &[COINBASE_VALUE - 2 * FEE]);
+  let lost_child = spend(&lost, 0, &[COINBASE_VALUE - 2 * FEE]);
+  let immature = spend(&coinbases[2], 0, &[COINBASE_VALUE - FEE]);
+  for txn in [&returned_child, &lost_child, &immature] {
+    mempool.accept(txn.clone(), &mut chain, NOW).unwrap();
+  }
+
+  // Disconnect enough blocks for the third coinbase to be immature again.
+  let height = chain.height();
+  let mut disconnected = Vec::new();
+  for height in (height - 2..=height).rev() {
+    disconnected
+      .push(Arc::new(chain.block_at_height(height).unwrap().unwrap()));
+  }
+  chain.invalidate_block(&disconnected[2].hash()).unwrap();
+  assert_eq!(chain.height(), height - 3);
+  mempool
+    .update_for_disconnected(&disconnected, &mut chain, NOW)
+    .unwrap();
+
+  let remaining = mempool.block_txns(usize::MAX);
+  assert_eq!(hashes(&remaining), hashes(&[&returned, &returned_child]));
+  let entry = mempool.get(&returned.hash()).unwrap();
+  assert_eq!(entry.descendant_count(), 2);
+  assert_eq!(entry.descendant_fee(), 2 * FEE);
+  let entry = mempool.get(&returned_child.hash()).unwrap();
+  assert_eq!(entry.ancestor_count(), 2);
+  assert_eq!(
+    entry.ancestor_size(),
+    returned.size() + returned_child.size()
+  );
+}
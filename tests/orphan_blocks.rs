@@ -0,0 +1,91 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{coinbase, open_chain, push, TempDir, NOW};
+use rbtc::util::{
+  config::Config,
+  constants::{MAX_ORPHAN_BLOCKS, MAX_ORPHAN_BLOCKS_SIZE, ORPHAN_BLOCK_EXPIRY},
+  types::{
+    block::Block, chain::PushOutcome, header::Header, orphan_pool::OrphanPool,
+    txn::Txn, txo::Txo,
+  },
+};
+
+/// Return a block with a unique header, of roughly the given size, that
+/// need not be valid.
+fn block(tag: u32, size: usize) -> Block {
+  let outputs = vec![Txo::new(0, [0u8; 20]); size / 28];
+  let txns = vec![Txn::new(tag, 0, vec![], outputs.len() as u32, outputs)];
+  let header = Header::new(0, [1u8; 32], [0u8; 32], tag, 0, tag);
+  Block::new(header, 1, txns)
+}
+
+/// Blocks arriving before their parents are held, the missing ancestor of
+/// each new orphan is requested, and the orphans connect in order once it
+/// arrives.
+#[test]
+fn connects_orphans_once_parent_arrives() {
+  let mined_dir = TempDir::new("orphan-blocks-mined");
+  let mut mined = open_chain(&mined_dir, &mut Config::default());
+  let blocks: Vec<_> = (0..3)
+    .map(|tag| push(&mut mined, vec![coinbase(tag)]))
+    .collect();
+
+  let dir = TempDir::new("orphan-blocks");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let requests = Arc::new(Mutex::new(Vec::new()));
+  chain.set_missing_parent_hook(Box::new({
+    let requests = requests.clone();
+    move |_, hash| requests.lock().unwrap().push(hash)
+  }));
+  for block in blocks[1..].iter().rev() {
+    let outcome = chain.validate_and_push(block.clone(), None, NOW);
+    assert_eq!(outcome.unwrap(), PushOutcome::Orphaned);
+  }
+  assert_eq!(chain.orphans().len(), 2);
+  assert_eq!(chain.height(), 0);
+  let missing = vec![blocks[1].hash(), blocks[0].hash()];
+  assert_eq!(*requests.lock().unwrap(), missing);
+
+  let outcome = chain.validate_and_push(blocks[0].clone(), None, NOW);
+  assert_eq!(outcome.unwrap(), PushOutcome::Connected(3));
+  assert_eq!(chain.last_block_hash(), blocks[2].hash());
+  assert!(chain.orphans().is_empty());
+  assert_eq!(chain.orphans().size(), 0);
+}
+
+/// The oldest orphans are evicted to stay within the count and size bounds,
+/// and orphans are evicted once they expire.
+#[test]
+fn evicts_orphans() {
+  // By count.
+  let mut pool = OrphanPool::new();
+  for tag in 0..=MAX_ORPHAN_BLOCKS as u32 {
+    pool.insert(block(tag, 0), None, NOW + tag);
+  }
+  assert_eq!(pool.len(), MAX_ORPHAN_BLOCKS);
+  assert!(!pool.contains(&block(0, 0).hash()));
+  assert!(pool.contains(&block(1, 0).hash()));
+
+  // By size.
+  let mut pool = OrphanPool::new();
+  let size = MAX_ORPHAN_BLOCKS_SIZE / 3;
+  for tag in 0..3 {
+    pool.insert(block(tag, size), None, NOW + tag);
+  }
+  assert_eq!(pool.len(), 2);
+  assert!(!pool.contains(&block(0, 0).hash()));
+  assert!(pool.size() <= MAX_ORPHAN_BLOCKS_SIZE);
+  pool.insert(block(3, MAX_ORPHAN_BLOCKS_SIZE + 28), None, NOW);
+  assert_eq!(pool.len(), 2);
+
+  // By age.
+  let mut pool = OrphanPool::new();
+  pool.insert(block(0, 0), None, NOW);
+  pool.insert(block(1, 0), None, NOW + 1);
+  pool.insert(block(2, 0), None, NOW + ORPHAN_BLOCK_EXPIRY + 1);
+  assert!(!pool.contains(&block(0, 0).hash()));
+  assert!(pool.contains(&block(1, 0).hash()));
+  assert_eq!(pool.len(), 2);
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use std::{
+  net::Ipv4Addr,
+  sync::{Arc, Mutex},
+};
+
+use async_std::channel::{self, Receiver};
+use common::{
+  coinbase, mature_chain, push, spend, spend_all, TempDir, COINBASE_VALUE, NOW,
+};
+use rbtc::{
+  events::validation::ValidationEvents,
+  mempool::{
+    constants::{MAX_ORPHAN_TXNS, MAX_ORPHAN_TXNS_PER_PEER, ORPHAN_TXN_EXPIRY},
+    orphans::OrphanTxnPool,
+    pool::Mempool,
+  },
+  networking::{
+    messages::Msg,
+    sync::{Event, SyncManager},
+  },
+  util::{
+    config::Config,
+    constants::SEQUENCE_FINAL,
+    time::{MockClock, TimeSource},
+    types::{chain::ActiveChain, coin::OutPoint, txn::Txn},
+  },
+};
+
+/// A fee comfortably above the minimum relay fee of a one-input transaction.
+const FEE: u64 = 10_000;
+
+/// Return a transaction spending an output of a transaction that is nowhere
+/// to be found, made unique by `tag`.
+fn orphan(tag: u32) -> Txn {
+  spend(&coinbase(tag), 0, &[COINBASE_VALUE - FEE])
+}
+
+/// Return the outpoints the given transaction spends.
+fn spent(txn: &Txn) -> Vec<OutPoint> {
+  txn.txi_list().iter().map(OutPoint::spent_by).collect()
+}
+
+/// Return the peer with the given index.
+fn peer(index: u8) -> Ipv4Addr {
+  Ipv4Addr::new(10, 0, 0, index + 1)
+}
+
+/// A peer may hold at most `MAX_ORPHAN_TXNS_PER_PEER` orphans, its oldest
+/// evicted first, and the pool at most `MAX_ORPHAN_TXNS`.
+#[test]
+fn caps_orphans() {
+  let mut pool = OrphanTxnPool::new();
+  let first = orphan(0);
+  pool.insert(first.clone(), peer(0), spent(&first), NOW);
+  for tag in 1..=MAX_ORPHAN_TXNS_PER_PEER as u32 {
+    let txn = orphan(tag);
+    pool.insert(txn.clone(), peer(0), spent(&txn), NOW + tag);
+  }
+  assert_eq!(pool.len(), MAX_ORPHAN_TXNS_PER_PEER);
+  assert!(!pool.contains(&first.hash()));
+
+  let mut tag = 1000;
+  for index in 1..MAX_ORPHAN_TXNS as u8 {
+    let txn = orphan(tag);
+    pool.insert(txn.clone(), peer(index), spent(&txn), NOW + tag);
+    tag += 1;
+  }
+  assert_eq!(pool.len(), MAX_ORPHAN_TXNS);
+  assert!(!pool.contains(&orphan(1).hash()));
+  assert!(pool.contains(&orphan(tag - 1).hash()));
+}
+
+/// Orphans are evicted once older than `ORPHAN_TXN_EXPIRY`, including when
+/// another is added.
+#[test]
+fn expires_orphans() {
+  let mut pool = OrphanTxnPool::new();
+  let old = orphan(0);
+  let recent = orphan(1);
+  pool.insert(old.clone(), peer(0), spent(&old), NOW);
+  pool.insert(recent.clone(), peer(1), spent(&recent), NOW + 10);
+
+  pool.expire(NOW + ORPHAN_TXN_EXPIRY);
+  assert_eq!(pool.len(), 2);
+  pool.expire(NOW + ORPHAN_TXN_EXPIRY + 1);
+  assert!(!pool.contains(&old.hash()));
+  assert!(pool.contains(&recent.hash()));
+
+  let last = orphan(2);
+  pool.insert(
+    last.clone(),
+    peer(0),
+    spent(&last),
+    NOW + ORPHAN_TXN_EXPIRY + 11,
+  );
+  assert!(!pool.contains(&recent.hash()));
+  assert_eq!(pool.len(), 1);
+}
+
+/// The orphans spending a transaction's outputs are taken once each, and
+/// their own orphaned children only once they are.
+#[test]
+fn takes_spenders_in_chains() {
+  let mut pool = OrphanTxnPool::new();
+  let parent = spend(&coinbase(0), 0, &[FEE, FEE]);
+  let child = spend(&parent, 0, &[FEE / 2]);
+  let both = spend_all(&[(&parent, 0), (&parent, 1)], &[FEE], SEQUENCE_FINAL);
+  let grandchild = spend(&child, 0, &[FEE / 4]);
+  for txn in [&child, &both, &grandchild] {
+    pool.insert(txn.clone(), peer(0), spent(txn), NOW);
+  }
+
+  let mut taken: Vec<_> = pool
+    .take_spenders(&parent.hash(), 2)
+    .into_iter()
+    .map(|orphan| orphan.txn.hash())
+    .collect();
+  taken.sort();
+  let mut expected = vec![child.hash(), both.hash()];
+  expected.sort();
+  assert_eq!(taken, expected);
+  assert_eq!(pool.len(), 1);
+  assert!(pool.take_spenders(&parent.hash(), 2).is_empty());
+
+  let taken = pool.take_spenders(&child.hash(), 1);
+  assert_eq!(taken.len(), 1);
+  assert_eq!(taken[0].txn.hash(), grandchild.hash());
+  assert_eq!(taken[0].missing, spent(&grandchild));
+  assert!(pool.is_empty());
+}
+
+/// Removing a peer's orphans leaves everyone else's, and frees its share.
+#[test]
+fn removes_orphans_for_peer() {
+  let mut pool = OrphanTxnPool::new();
+  for tag in 0..MAX_ORPHAN_TXNS_PER_PEER as u32 {
+    let txn = orphan(tag);
+    pool.insert(txn.clone(), peer(0), spent(&txn), NOW);
+  }
+  let other = orphan(1000);
+  pool.insert(other.clone(), peer(1), spent(&other), NOW);
+
+  pool.remove_for_peer(&peer(0));
+  assert_eq!(pool.len(), 1);
+  assert!(pool.contains(&other.hash()));
+  for tag in 0..MAX_ORPHAN_TXNS_PER_PEER as u32 {
+    let txn = orphan(tag);
+    pool.insert(txn.clone(), peer(0), spent(&txn), NOW);
+  }
+  assert_eq!(pool.len(), MAX_ORPHAN_TXNS_PER_PEER + 1);
+}
+
+/// Return a sync manager on the given chain, connected to peers with the
+/// given indexes, and the receivers of the messages sent to each, with the
+/// handshake's messages taken out.
+fn connected_sync_manager(
+  chain: ActiveChain,
+  config: &Config,
+  peers: &[u8],
+) -> (SyncManager, Vec<Receiver<Msg>>) {
+  let time = TimeSource::new(Arc::new(MockClock::new(NOW)));
+  let (validation_events, _queue) = ValidationEvents::new();
+  let mut sync = SyncManager::new(
+    Arc::new(Mutex::new(chain)),
+    Arc::new(Mutex::new(Mempool::new(config))),
+    Arc::new(Mutex::new(time)),
+    validation_events,
+  );
+  let mut receivers = Vec::new();
+  for index in peers {
+    let (sender, receiver) = channel::unbounded();
+    sync.handle_event(Event::Connected(peer(*index), sender));
+    while receiver.try_recv().is_ok() {}
+    receivers.push(receiver);
+  }
+  (sync, receivers)
+}
+
+/// Return the hashes of the transactions requested from a peer since last
+/// called.
+fn requested_txns(receiver: &Receiver<Msg>) -> Vec<[u8; 32]> {
+  let mut requested = Vec::new();
+  while let Ok(msg) = receiver.try_recv() {
+    if let Msg::GetTxns(hashes) = msg {
+      requested.extend(hashes);
+    }
+  }
+  requested
+}
+
+/// Return the hashes of the transactions relayed to a peer since last
+/// called.
+fn relayed_txns(receiver: &Receiver<Msg>) -> Vec<[u8; 32]> {
+  let mut relayed = Vec::new();
+  while let Ok(msg) = receiver.try_recv() {
+    if let Msg::Txn(txn) = msg {
+      relayed.push(txn.hash());
+    }
+  }
+  relayed
+}
+
+/// An orphan's missing parent is requested from the peer that sent it, and
+/// the orphan is relayed once the parent arrives. Orphans spending a rejected
+/// transaction are rejected too.
+#[test]
+fn rejects_orphans_of_rejected_parents() {
+  let dir = TempDir::new("orphans-rejected");
+  let mut config = Config::default();
+  let (chain, coinbases) = mature_chain(&dir, &mut config, 2);
+  let (mut sync, receivers) = connected_sync_manager(chain, &config, &[0, 1]);
+
+  let parent = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  let child = spend(&parent, 0, &[COINBASE_VALUE - 2 * FEE]);
+  sync.handle_event(Event::Message(peer(0), Msg::Txn(child.clone())));
+  assert_eq!(requested_txns(&receivers[0]), vec![parent.hash()]);
+  sync.handle_event(Event::Message(peer(0), Msg::Txn(parent.clone())));
+  assert_eq!(
+    relayed_txns(&receivers[1]),
+    vec![parent.hash(), child.hash()]
+  );
+
+  let invalid = spend(&coinbases[1], 0, &[COINBASE_VALUE + 1]);
+  let invalid_child = spend(&invalid, 0, &[COINBASE_VALUE]);
+  sync.handle_event(Event::Message(peer(0), Msg::Txn(invalid)));
+  sync.handle_event(Event::Message(peer(0), Msg::Txn(invalid_child)));
+  assert!(requested_txns(&receivers[0]).is_empty());
+}
+
+/// A transaction spending an already spent output of a confirmed
+/// transaction is not held as an orphan when the transaction index knows
+/// the transaction is confirmed.
+#[test]
+fn rejects_orphans_of_confirmed_parents() {
+  let dir = TempDir::new("orphans-confirmed");
+  let mut config = Config { txindex: true, ..Config::default() };
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 1);
+  let parent = spend(&coinbases[0], 0, &[COINBASE_VALUE - FEE]);
+  let child = spend(&parent, 0, &[COINBASE_VALUE - 2 * FEE]);
+  push(&mut chain, vec![coinbase(7), parent.clone(), child]);
+  while !chain.sync_indexes(1000).unwrap() {}
+  let (mut sync, receivers) = connected_sync_manager(chain, &config, &[0]);
+
+  let double_spend = spend(&parent, 0, &[COINBASE_VALUE - 3 * FEE]);
+  sync.handle_event(Event::Message(peer(0), Msg::Txn(double_spend)));
+  assert!(requested_txns(&receivers[0]).is_empty());
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{
+  coinbase, mature_chain, open_chain, push, spend, TempDir, COINBASE_VALUE,
+};
+use rbtc::util::{
+  config::Config,
+  constants::{MIN_BLOCKS_TO_KEEP, MIN_PRUNE_TARGET, SEQUENCE_FINAL},
+  types::{chain::Error, txi::Txi, txn::Txn, txo::Txo},
+};
+
+/// The number of outputs each large transaction spends and creates again.
+const OUTPUTS: usize = 5000;
+
+/// The number of blocks holding large transactions, enough to fill more than
+/// the prune target.
+const LARGE_BLOCKS: u32 = 120;
+
+/// Blocks buried at least `MIN_BLOCKS_TO_KEEP` deep are pruned once the block
+/// store outgrows the prune target, unless an index has yet to index them,
+/// and reading a pruned block returns `BlockPruned`.
+///
+/// Filling the smallest prune target takes about a minute and a half in a
+/// debug build, so run it with `cargo test --release -- --ignored`.
+#[test]
+#[ignore = "slow: writes more block data than the smallest prune target"]
+fn prunes_old_blocks() {
+  let dir = TempDir::new("prune");
+  let mut config = Config::default();
+  let (mut chain, coinbases) = mature_chain(&dir, &mut config, 1);
+
+  // Pass the same coins on from each large transaction to the next.
+  let value = COINBASE_VALUE / OUTPUTS as u64;
+  let outputs = vec![Txo::new(value, [1; 20]); OUTPUTS];
+  let mut txn = spend(&coinbases[0], 0, &[value; OUTPUTS]);
+  let mut large_blocks = Vec::new();
+  for tag in 0..LARGE_BLOCKS {
+    let hash = txn.hash();
+    let inputs: Vec<_> = (0..OUTPUTS)
+      .map(|index| Txi::new(hash, index, [0; 32], SEQUENCE_FINAL))
+      .collect();
+    let next =
+      Txn::new(0, OUTPUTS as u32, inputs, OUTPUTS as u32, outputs.clone());
+    large_blocks.push(push(&mut chain, vec![coinbase(1_000 + tag), txn]));
+    txn = next;
+  }
+  for tag in 0..MIN_BLOCKS_TO_KEEP {
+    push(&mut chain, vec![coinbase(2_000 + tag)]);
+  }
+  drop(chain);
+
+  // Reopen with a block filter index, which starts from the genesis block,
+  // and a prune target the block store is well over.
+  config.prune_target = Some(MIN_PRUNE_TARGET);
+  config.blockfilterindex = true;
+  let mut chain = open_chain(&dir, &mut config);
+  push(&mut chain, vec![coinbase(3_000)]);
+  assert!(chain.block(&large_blocks[0].hash()).unwrap().is_some());
+
+  // Once it has caught up, the oldest blocks are pruned.
+  while !chain.sync_indexes(usize::MAX).unwrap() {}
+  let tip = push(&mut chain, vec![coinbase(3_001)]);
+  let result = chain.block(&large_blocks[0].hash());
+  assert!(
+    matches!(result, Err(Error::BlockPruned(hash)) if hash == large_blocks[0].hash())
+  );
+  assert!(chain.block(&tip.hash()).unwrap().is_some());
+  for depth in 0..MIN_BLOCKS_TO_KEEP {
+    let block = chain.block_at_height(chain.height() - depth).unwrap();
+    assert!(block.is_some());
+  }
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::{
+  net::Ipv4Addr,
+  sync::{Arc, Mutex},
+};
+
+use async_std::channel;
+use common::{coinbase, mine, open_chain, push, TempDir, NOW};
+use rbtc::{
+  events::validation::ValidationEvents,
+  mempool::pool::Mempool,
+  networking::{
+    constants::MAX_LOW_WORK_HEADERS,
+    messages::Msg,
+    sync::{Event, SyncManager},
+  },
+  util::{
+    config::Config,
+    time::{MockClock, TimeSource},
+    types::header::Header,
+  },
+};
+
+/// The height of the active chain low-work forks are made against.
+const TIP_HEIGHT: u32 = 40;
+
+/// A peer flooding us with headers of forks with less work than our active
+/// chain, each batch ending in a header whose `bits` claim more work than
+/// the rest combined, is cut off once it has made us store too many, and
+/// reconnecting does not reset its count.
+#[test]
+fn limits_low_work_header_floods() {
+  // Mine the active chain and the forks on one chain, and sync another.
+  let mined_dir = TempDir::new("sync-flood-mined");
+  let mut mined = open_chain(&mined_dir, &mut Config::default());
+  let genesis = mined.last_block_hash();
+  let dir = TempDir::new("sync-flood");
+  let mut config = Config::default();
+  let mut chain = open_chain(&dir, &mut config);
+  for tag in 0..TIP_HEIGHT {
+    let block = push(&mut mined, vec![coinbase(tag)]);
+    chain.validate_and_push(block, None, NOW).unwrap();
+  }
+
+  let chain = Arc::new(Mutex::new(chain));
+  let clock = Arc::new(MockClock::new(NOW));
+  let time = Arc::new(Mutex::new(TimeSource::new(clock)));
+  let (validation_events, _queue) = ValidationEvents::new();
+  let mut sync = SyncManager::new(
+    chain.clone(),
+    Arc::new(Mutex::new(Mempool::new(&config))),
+    time,
+    validation_events,
+  );
+
+  // Each fork is one block shorter than the active chain.
+  let fork_len = TIP_HEIGHT as usize - 1;
+  let mut fork = |tag: u32| -> Vec<Header> {
+    let mut headers = Vec::new();
+    let mut prev = genesis;
+    for i in 0..fork_len as u32 {
+      let block = mine(&mined, &prev, vec![coinbase(1_000_000 * tag + i)]);
+      mined
+        .accept_headers(&[block.header().clone()], NOW)
+        .unwrap();
+      prev = block.hash();
+      headers.push(block.header().clone());
+    }
+    headers
+  };
+  let bogus = |prev: &Header| {
+    Header::new(0, prev.hash(), [0; 32], prev.timestamp() + 1, 0x1d00ffff, 0)
+  };
+
+  let peer = Ipv4Addr::new(10, 0, 0, 1);
+  let batches = MAX_LOW_WORK_HEADERS / fork_len + 1;
+  for tag in 1..=batches as u32 {
+    let mut headers = fork(tag);
+    headers.push(bogus(headers.last().unwrap()));
+    let (sender, _receiver) = channel::unbounded();
+    sync.handle_event(Event::Connected(peer, sender));
+    sync.handle_event(Event::Message(peer, Msg::Headers(headers.clone())));
+    sync.handle_event(Event::Disconnected(peer));
+
+    // The valid headers are stored, and the bogus one is not.
+    let chain = chain.lock().unwrap();
+    assert!(chain.index().contains(&headers[fork_len - 1].hash()));
+    assert!(!chain.index().contains(&headers[fork_len].hash()));
+  }
+
+  // Once over the limit, the peer's headers are no longer stored, even
+  // valid ones after it reconnects.
+  let headers = fork(batches as u32 + 1);
+  let (sender, _receiver) = channel::unbounded();
+  sync.handle_event(Event::Connected(peer, sender));
+  sync.handle_event(Event::Message(peer, Msg::Headers(headers.clone())));
+  assert!(!chain.lock().unwrap().index().contains(&headers[0].hash()));
+
+  // Other peers are not affected.
+  let other = Ipv4Addr::new(10, 0, 0, 2);
+  let (sender, _receiver) = channel::unbounded();
+  sync.handle_event(Event::Connected(other, sender));
+  sync.handle_event(Event::Message(other, Msg::Headers(headers.clone())));
+  assert!(chain.lock().unwrap().index().contains(&headers[0].hash()));
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::{coinbase, mine, open_chain, push, TempDir, NOW};
+use rbtc::{index::txindex::TxIndex, util::config::Config};
+
+/// Enabling the transaction index on an existing chain indexes the blocks
+/// connected before it, once it has caught up with them.
+#[test]
+fn builds_retroactively() {
+  let dir = TempDir::new("txindex-retroactive");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let blocks: Vec<_> = (1..=5)
+    .map(|tag| push(&mut chain, vec![coinbase(tag)]))
+    .collect();
+  chain.flush().unwrap();
+  drop(chain);
+
+  let mut config = Config { txindex: true, ..Config::default() };
+  let mut chain = open_chain(&dir, &mut config);
+  let txn_hash = blocks[0].txns()[0].hash();
+  assert!(chain.get_transaction(&txn_hash).unwrap().is_none());
+
+  // Genesis and the first two blocks.
+  assert!(!chain.sync_indexes(3).unwrap());
+  assert!(chain.is_confirmed(&blocks[1].txns()[0].hash()).unwrap());
+  assert!(!chain.is_confirmed(&blocks[2].txns()[0].hash()).unwrap());
+
+  while !chain.sync_indexes(2).unwrap() {}
+  for (height, block) in (1..).zip(&blocks) {
+    let txn = &block.txns()[0];
+    let (found, entry) = chain.get_transaction(&txn.hash()).unwrap().unwrap();
+    assert_eq!(found.hash(), txn.hash());
+    assert_eq!(entry.hash(), block.hash());
+    assert_eq!(entry.height(), height);
+  }
+}
+
+/// Disconnecting blocks in a reorganization removes their transactions from
+/// the index, so they are no longer found, while those on the new branch are.
+#[test]
+fn reorg_removes_stale_transactions() {
+  let dir = TempDir::new("txindex-reorg");
+  let mut config = Config { txindex: true, ..Config::default() };
+  let mut chain = open_chain(&dir, &mut config);
+  while !chain.sync_indexes(100).unwrap() {}
+  let fork = push(&mut chain, vec![coinbase(1)]).hash();
+  let stale: Vec<_> = (2..=3)
+    .map(|tag| push(&mut chain, vec![coinbase(tag)]))
+    .collect();
+  for block in &stale {
+    let txn_hash = block.txns()[0].hash();
+    assert!(chain.get_transaction(&txn_hash).unwrap().is_some());
+  }
+
+  // Replace the two blocks with a branch that has more work.
+  let mut prev = fork;
+  let mut branch = Vec::new();
+  for tag in 4..=6 {
+    let block = mine(&chain, &prev, vec![coinbase(tag)]);
+    chain.validate_and_push(block.clone(), None, NOW).unwrap();
+    prev = block.hash();
+    branch.push(block);
+  }
+  assert_eq!(chain.last_block_hash(), prev);
+
+  for block in &stale {
+    let txn_hash = block.txns()[0].hash();
+    assert!(chain.get_transaction(&txn_hash).unwrap().is_none());
+    assert!(!chain.is_confirmed(&txn_hash).unwrap());
+  }
+  for block in &branch {
+    let txn_hash = block.txns()[0].hash();
+    let (_, entry) = chain.get_transaction(&txn_hash).unwrap().unwrap();
+    assert_eq!(entry.hash(), block.hash());
+  }
+
+  // The entries themselves are gone, not merely filtered out.
+  drop(chain);
+  let index = TxIndex::open(&dir.0).unwrap();
+  for block in &stale {
+    assert_eq!(index.get(&block.txns()[0].hash()).unwrap(), None);
+  }
+  let location = index.get(&branch[0].txns()[0].hash()).unwrap().unwrap();
+  assert_eq!(location.block_hash, branch[0].hash());
+}
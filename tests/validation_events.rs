@@ -0,0 +1,111 @@
+mod common;
+
+use std::time::Duration;
+
+use async_std::{future, task};
+use common::{coinbase, mine, open_chain, push, TempDir, NOW};
+use rbtc::{
+  events::{
+    constants::SUBSCRIBER_QUEUE_SIZE,
+    validation::{ValidationEvent, ValidationEvents},
+  },
+  util::config::Config,
+};
+
+/// How long to wait on `sync` before concluding it is held up.
+const SYNC_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A reorganization is published as the blocks it disconnected, tip first,
+/// then the blocks it connected, oldest first, then a single tip update.
+#[test]
+fn reorg_event_order() {
+  let dir = TempDir::new("events-reorg");
+  let mut chain = open_chain(&dir, &mut Config::default());
+  let fork = push(&mut chain, vec![coinbase(1)]).hash();
+  let stale: Vec<_> = (2..=3)
+    .map(|tag| push(&mut chain, vec![coinbase(tag)]).hash())
+    .collect();
+
+  let (events, queue) = ValidationEvents::new();
+  let receiver = events.subscribe();
+  task::spawn(queue.dispatch());
+  chain.set_validation_events(events.clone());
+
+  let mut prev = fork;
+  let mut branch = Vec::new();
+  for tag in 4..=6 {
+    let block = mine(&chain, &prev, vec![coinbase(tag)]);
+    chain.validate_and_push(block.clone(), None, NOW).unwrap();
+    prev = block.hash();
+    branch.push(prev);
+  }
+  assert_eq!(chain.last_block_hash(), prev);
+  task::block_on(events.sync(0));
+
+  let mut received = Vec::new();
+  while let Ok(event) = receiver.try_recv() {
+    received.push(event);
+  }
+  assert_eq!(received.len(), 6, "{:?}", received);
+  for (event, (hash, expected_height)) in
+    received[..2].iter().zip([(stale[1], 3), (stale[0], 2)])
+  {
+    match event {
+      ValidationEvent::BlockDisconnected { block, height } => {
+        assert_eq!(block.hash(), hash);
+        assert_eq!(*height, expected_height);
+      },
+      other => panic!("Expected a disconnected block, got {:?}", other),
+    }
+  }
+  for (event, (hash, expected_height)) in
+    received[2..5].iter().zip(branch.iter().zip(2..))
+  {
+    match event {
+      ValidationEvent::BlockConnected { block, height } => {
+        assert_eq!(block.hash(), *hash);
+        assert_eq!(*height, expected_height);
+      },
+      other => panic!("Expected a connected block, got {:?}", other),
+    }
+  }
+  match &received[5] {
+    ValidationEvent::TipUpdated { hash, height } => {
+      assert_eq!(*hash, prev);
+      assert_eq!(*height, 4);
+    },
+    other => panic!("Expected a tip update, got {:?}", other),
+  }
+}
+
+/// A subscriber that stops receiving once its channel is full holds up
+/// `sync` until it receives again.
+#[test]
+fn full_subscriber_blocks_sync() {
+  let (events, queue) = ValidationEvents::new();
+  let receiver = events.subscribe();
+  task::spawn(queue.dispatch());
+
+  let published = SUBSCRIBER_QUEUE_SIZE + 3;
+  for height in 0..published as u32 {
+    let hash = [0u8; 32];
+    events.publish(ValidationEvent::TipUpdated { hash, height });
+  }
+
+  // The channel holds `SUBSCRIBER_QUEUE_SIZE` events, and the rest wait.
+  let synced = task::block_on(future::timeout(SYNC_TIMEOUT, events.sync(0)));
+  assert!(synced.is_err());
+  assert_eq!(events.pending(), 3);
+
+  for expected in 0..published as u32 {
+    match task::block_on(receiver.recv()).unwrap() {
+      ValidationEvent::TipUpdated { height, .. } => {
+        assert_eq!(height, expected)
+      },
+      other => panic!("Expected a tip update, got {:?}", other),
+    }
+  }
+  let synced = task::block_on(future::timeout(SYNC_TIMEOUT, events.sync(0)));
+  assert!(synced.is_ok());
+  assert_eq!(events.pending(), 0);
+}